@@ -33,6 +33,8 @@ struct ConfigFile {
     font_size: f32,
     #[serde(default)]
     debug_mode: bool,
+    #[serde(default)]
+    auto_orient: bool,
 }
 
 fn default_alignment() -> String {
@@ -116,6 +118,7 @@ fn main() -> Result<()> {
         left_padding: left_padding_u32,
         font_size: Some(config.font_size),
         debug_mode: config.debug_mode,
+        auto_orient: config.auto_orient,
     };
 
     // Create the plot
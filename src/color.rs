@@ -0,0 +1,233 @@
+//! Colour management module.
+//!
+//! Images decoded by this crate keep the ICC profile embedded in the source file
+//! (see [`open_image_with_icc_profile`](crate::image_processing::open_image_with_icc_profile)).
+//! This module converts such pixel data to sRGB with the pure Rust `moxcms` colour
+//! management system, for outputs that cannot carry a profile or when plain sRGB
+//! is wanted.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use std::path::Path;
+//! use imx::color::convert_to_srgb;
+//! use imx::image_processing::open_image_with_icc_profile;
+//!
+//! fn example() -> anyhow::Result<()> {
+//!     let (image, icc_profile) = open_image_with_icc_profile(Path::new("display_p3.png"))?;
+//!     let image = match icc_profile {
+//!         Some(profile) => convert_to_srgb(&image, &profile)?,
+//!         None => image,
+//!     };
+//!     image.save("srgb.png")?;
+//!     Ok(())
+//! }
+//! ```
+
+#![warn(clippy::all, clippy::pedantic)]
+
+use anyhow::Result;
+use image::{ColorType, DynamicImage, ImageBuffer, Pixel};
+use moxcms::{ColorProfile, DataColorSpace, Layout, TransformExecutor, TransformOptions};
+use std::sync::Arc;
+
+/// Sample types `moxcms` can transform.
+trait CmsSample: Copy + Default {
+    fn create_transform(
+        source: &ColorProfile,
+        source_layout: Layout,
+        destination: &ColorProfile,
+        destination_layout: Layout,
+    ) -> Result<Arc<dyn TransformExecutor<Self> + Send + Sync>, moxcms::CmsError>;
+}
+
+impl CmsSample for u8 {
+    fn create_transform(
+        source: &ColorProfile,
+        source_layout: Layout,
+        destination: &ColorProfile,
+        destination_layout: Layout,
+    ) -> Result<Arc<dyn TransformExecutor<Self> + Send + Sync>, moxcms::CmsError> {
+        source.create_transform_8bit(
+            source_layout,
+            destination,
+            destination_layout,
+            TransformOptions::default(),
+        )
+    }
+}
+
+impl CmsSample for u16 {
+    fn create_transform(
+        source: &ColorProfile,
+        source_layout: Layout,
+        destination: &ColorProfile,
+        destination_layout: Layout,
+    ) -> Result<Arc<dyn TransformExecutor<Self> + Send + Sync>, moxcms::CmsError> {
+        source.create_transform_16bit(
+            source_layout,
+            destination,
+            destination_layout,
+            TransformOptions::default(),
+        )
+    }
+}
+
+impl CmsSample for f32 {
+    fn create_transform(
+        source: &ColorProfile,
+        source_layout: Layout,
+        destination: &ColorProfile,
+        destination_layout: Layout,
+    ) -> Result<Arc<dyn TransformExecutor<Self> + Send + Sync>, moxcms::CmsError> {
+        source.create_transform_f32(
+            source_layout,
+            destination,
+            destination_layout,
+            TransformOptions::default(),
+        )
+    }
+}
+
+/// Parses an ICC profile.
+fn parse_profile(icc_profile: &[u8]) -> Result<ColorProfile> {
+    ColorProfile::new_from_slice(icc_profile)
+        .map_err(|e| anyhow::anyhow!("Invalid ICC profile: {e}"))
+}
+
+/// Checks that a profile describes the colour model (gray or RGB) of an image.
+fn check_profile_fits(profile: &ColorProfile, color: ColorType) -> Result<()> {
+    let expected = if color.has_color() {
+        DataColorSpace::Rgb
+    } else {
+        DataColorSpace::Gray
+    };
+    if profile.color_space != expected {
+        anyhow::bail!(
+            "ICC profile colour space {:?} does not match the {color:?} image",
+            profile.color_space
+        );
+    }
+    Ok(())
+}
+
+/// Returns whether an ICC profile can be embedded with pixels of the given colour type.
+///
+/// The profile must parse and describe the same colour model (gray or RGB) as the
+/// pixels; a CMYK profile from a CMYK JPEG, for example, does not fit the RGB pixels
+/// it is decoded to.
+pub(crate) fn icc_profile_fits(icc_profile: &[u8], color: ColorType) -> bool {
+    parse_profile(icc_profile).is_ok_and(|profile| check_profile_fits(&profile, color).is_ok())
+}
+
+/// Converts one image buffer from `source` to sRGB, keeping its layout.
+///
+/// sRGB is an RGB profile, so gray pixels are converted to (neutral) RGB and one
+/// channel is kept.
+fn convert_buffer<P>(
+    buffer: &ImageBuffer<P, Vec<P::Subpixel>>,
+    source: &ColorProfile,
+) -> Result<ImageBuffer<P, Vec<P::Subpixel>>>
+where
+    P: Pixel,
+    P::Subpixel: CmsSample,
+{
+    let (source_layout, destination_layout) = match P::CHANNEL_COUNT {
+        1 => (Layout::Gray, Layout::Rgb),
+        2 => (Layout::GrayAlpha, Layout::Rgba),
+        3 => (Layout::Rgb, Layout::Rgb),
+        _ => (Layout::Rgba, Layout::Rgba),
+    };
+    let transform = P::Subpixel::create_transform(
+        source,
+        source_layout,
+        &ColorProfile::new_srgb(),
+        destination_layout,
+    )
+    .map_err(|e| anyhow::anyhow!("Failed to create sRGB colour transform: {e}"))?;
+
+    let samples = buffer.as_raw();
+    let pixel_count = samples.len() / source_layout.channels();
+    let mut converted = vec![P::Subpixel::default(); pixel_count * destination_layout.channels()];
+    transform
+        .transform(samples, &mut converted)
+        .map_err(|e| anyhow::anyhow!("Failed to convert pixels to sRGB: {e}"))?;
+
+    if source_layout != destination_layout {
+        let keep_alpha = destination_layout == Layout::Rgba;
+        converted = converted
+            .chunks_exact(destination_layout.channels())
+            .flat_map(|rgb| {
+                let alpha = keep_alpha.then(|| rgb[3]);
+                std::iter::once(rgb[0]).chain(alpha)
+            })
+            .collect();
+    }
+    ImageBuffer::from_raw(buffer.width(), buffer.height(), converted)
+        .ok_or_else(|| anyhow::anyhow!("Converted buffer does not match the image dimensions"))
+}
+
+/// Converts pixel data from the colour space of an ICC profile to sRGB.
+///
+/// The image keeps its colour type: gray images stay gray, alpha is passed through
+/// unchanged and 16-bit and float images are converted at their own precision.
+/// Colours outside the sRGB gamut are clipped, except in float images, which may
+/// end up with samples outside `0.0..=1.0`.
+///
+/// # Arguments
+///
+/// * `img` - Image whose pixels are in the colour space of `icc_profile`
+/// * `icc_profile` - The ICC profile embedded in the source file
+///
+/// # Returns
+///
+/// Returns the image with its pixels converted to sRGB
+///
+/// # Errors
+///
+/// Returns an error if:
+/// * The ICC profile is invalid
+/// * The profile is not an RGB profile for a colour image or a gray profile for a
+///   grayscale image
+/// * `moxcms` cannot build a transform for the profile
+pub fn convert_to_srgb(img: &DynamicImage, icc_profile: &[u8]) -> Result<DynamicImage> {
+    let source = parse_profile(icc_profile)?;
+    check_profile_fits(&source, img.color())?;
+
+    Ok(match img {
+        DynamicImage::ImageLuma8(buffer) => {
+            DynamicImage::ImageLuma8(convert_buffer(buffer, &source)?)
+        }
+        DynamicImage::ImageLumaA8(buffer) => {
+            DynamicImage::ImageLumaA8(convert_buffer(buffer, &source)?)
+        }
+        DynamicImage::ImageRgb8(buffer) => {
+            DynamicImage::ImageRgb8(convert_buffer(buffer, &source)?)
+        }
+        DynamicImage::ImageRgba8(buffer) => {
+            DynamicImage::ImageRgba8(convert_buffer(buffer, &source)?)
+        }
+        DynamicImage::ImageLuma16(buffer) => {
+            DynamicImage::ImageLuma16(convert_buffer(buffer, &source)?)
+        }
+        DynamicImage::ImageLumaA16(buffer) => {
+            DynamicImage::ImageLumaA16(convert_buffer(buffer, &source)?)
+        }
+        DynamicImage::ImageRgb16(buffer) => {
+            DynamicImage::ImageRgb16(convert_buffer(buffer, &source)?)
+        }
+        DynamicImage::ImageRgba16(buffer) => {
+            DynamicImage::ImageRgba16(convert_buffer(buffer, &source)?)
+        }
+        DynamicImage::ImageRgb32F(buffer) => {
+            DynamicImage::ImageRgb32F(convert_buffer(buffer, &source)?)
+        }
+        DynamicImage::ImageRgba32F(buffer) => {
+            DynamicImage::ImageRgba32F(convert_buffer(buffer, &source)?)
+        }
+        other => anyhow::bail!(
+            "Unsupported colour type for sRGB conversion: {:?}",
+            other.color()
+        ),
+    })
+}
@@ -0,0 +1,387 @@
+//! Typed encoder options for each output format.
+//!
+//! [`EncoderOptions`] holds the settings of one encoder as a plain struct, so
+//! invalid combinations are caught by [`EncoderOptions::validate`] instead of
+//! being parsed out of strings at conversion time. With the `serde` feature the
+//! options can be read from configuration files; the `format` field selects the
+//! variant.
+//!
+//! The builder presets of [`ImageFormatOptions`] convert to these structs with
+//! `TryFrom`, and typed options are passed to [`convert_image`] with
+//! [`ImageFormatOptions::with_encoder_options`].
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use std::path::Path;
+//! use imx::encoder_options::{ChromaSubsampling, JpegOptions};
+//! use imx::formats::{convert_image, ImageFormatOptions};
+//!
+//! async fn example() -> anyhow::Result<()> {
+//!     let jpeg = JpegOptions {
+//!         quality: 92,
+//!         subsampling: Some(ChromaSubsampling::Yuv444),
+//!         progressive: true,
+//!         ..JpegOptions::default()
+//!     };
+//!     let options = ImageFormatOptions::default().with_encoder_options(jpeg);
+//!     convert_image(Path::new("input.png"), Path::new("output.jpg"), Some(options)).await?;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! [`ImageFormatOptions`]: crate::formats::ImageFormatOptions
+//! [`ImageFormatOptions::with_encoder_options`]: crate::formats::ImageFormatOptions::with_encoder_options
+//! [`convert_image`]: crate::formats::convert_image
+
+#![warn(clippy::all, clippy::pedantic)]
+
+use anyhow::Result;
+use std::ops::RangeInclusive;
+
+/// Checks that an option lies within `range`.
+fn check_range(name: &str, value: u8, range: RangeInclusive<u8>) -> Result<()> {
+    if !range.contains(&value) {
+        anyhow::bail!(
+            "{name} must be between {} and {}, got {value}",
+            range.start(),
+            range.end()
+        );
+    }
+    Ok(())
+}
+
+/// Chroma subsampling of a JPEG image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChromaSubsampling {
+    /// Full colour resolution
+    #[cfg_attr(feature = "serde", serde(rename = "444"))]
+    Yuv444,
+    /// Half horizontal colour resolution
+    #[cfg_attr(feature = "serde", serde(rename = "422"))]
+    Yuv422,
+    /// Half horizontal and vertical colour resolution
+    #[cfg_attr(feature = "serde", serde(rename = "420"))]
+    Yuv420,
+    /// Half vertical colour resolution
+    #[cfg_attr(feature = "serde", serde(rename = "440"))]
+    Yuv440,
+    /// Quarter horizontal colour resolution
+    #[cfg_attr(feature = "serde", serde(rename = "411"))]
+    Yuv411,
+}
+
+/// Options for JPEG output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct JpegOptions {
+    /// Quality (1-100)
+    pub quality: u8,
+    /// Chroma subsampling; `None` uses 4:2:0 below quality 90 and 4:4:4 above
+    pub subsampling: Option<ChromaSubsampling>,
+    /// Whether to write a progressive JPEG
+    pub progressive: bool,
+    /// Whether to build Huffman tables for the image instead of using the standard ones
+    pub optimize_huffman: bool,
+}
+
+impl Default for JpegOptions {
+    fn default() -> Self {
+        Self {
+            quality: 85,
+            subsampling: None,
+            progressive: false,
+            optimize_huffman: false,
+        }
+    }
+}
+
+impl JpegOptions {
+    /// Checks that every setting is in range.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `quality` is not between 1 and 100
+    pub fn validate(&self) -> Result<()> {
+        check_range("JPEG quality", self.quality, 1..=100)
+    }
+}
+
+/// Deflate compression level of PNG output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum PngCompression {
+    /// Fast, minimal compression
+    #[default]
+    Fast,
+    /// Balanced compression
+    Default,
+    /// Slowest, smallest output
+    Best,
+}
+
+/// Row filter of PNG output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum PngFilter {
+    /// No filtering
+    None,
+    /// Difference to the pixel on the left
+    Sub,
+    /// Difference to the pixel above
+    Up,
+    /// Difference to the average of the left and upper pixels
+    Avg,
+    /// Paeth predictor
+    Paeth,
+    /// Choose a filter for each row
+    #[default]
+    Adaptive,
+}
+
+/// Options for PNG output.
+///
+/// PNG is lossless, so there is no quality setting.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct PngOptions {
+    /// Deflate compression level
+    pub compression: PngCompression,
+    /// Row filter
+    pub filter: PngFilter,
+}
+
+impl PngOptions {
+    /// Checks that every setting is in range; all PNG settings are.
+    ///
+    /// # Errors
+    ///
+    /// Never returns an error; present for symmetry with the other formats
+    pub fn validate(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Options for WebP output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct WebpOptions {
+    /// Quality (0-100) in lossy mode
+    pub quality: u8,
+    /// Whether to encode losslessly
+    pub lossless: bool,
+    /// Quality (0-100) of the alpha plane in lossy mode; libwebp's default if `None`
+    pub alpha_quality: Option<u8>,
+    /// Speed/size trade-off (0-6), higher is slower and smaller; libwebp's default if `None`
+    pub method: Option<u8>,
+}
+
+impl Default for WebpOptions {
+    fn default() -> Self {
+        Self {
+            quality: 80,
+            lossless: false,
+            alpha_quality: None,
+            method: None,
+        }
+    }
+}
+
+impl WebpOptions {
+    /// Checks that every setting is in range.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `quality` or `alpha_quality` is above 100, or `method` above 6
+    pub fn validate(&self) -> Result<()> {
+        check_range("WebP quality", self.quality, 0..=100)?;
+        if let Some(alpha_quality) = self.alpha_quality {
+            check_range("WebP alpha quality", alpha_quality, 0..=100)?;
+        }
+        if let Some(method) = self.method {
+            check_range("WebP method", method, 0..=6)?;
+        }
+        Ok(())
+    }
+}
+
+/// Options for JPEG XL output.
+///
+/// Lossy output (`lossless: false` and a quality below 100) needs the
+/// `jxl-lossy` cargo feature.
+///
+/// Lossless output is exact for 8-bit images and for 16-bit colour, but alpha
+/// is always stored with 8 bits, so 16-bit and floating point alpha are rounded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct JxlOptions {
+    /// Quality (0-100) in lossy mode; 100 is lossless
+    pub quality: u8,
+    /// Whether to encode losslessly
+    pub lossless: bool,
+    /// Speed/size trade-off (1-9), higher is slower and smaller; the encoder's default if `None`
+    pub effort: Option<u8>,
+}
+
+impl Default for JxlOptions {
+    fn default() -> Self {
+        Self {
+            quality: 100,
+            lossless: true,
+            effort: None,
+        }
+    }
+}
+
+impl JxlOptions {
+    /// Checks that every setting is in range.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `quality` is above 100 or `effort` is not between 1 and 9
+    pub fn validate(&self) -> Result<()> {
+        check_range("JPEG XL quality", self.quality, 0..=100)?;
+        if let Some(effort) = self.effort {
+            check_range("JPEG XL effort", effort, 1..=9)?;
+        }
+        Ok(())
+    }
+}
+
+/// Options for AVIF output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct AvifOptions {
+    /// Quality (0-100); 100 is near-lossless
+    pub quality: u8,
+    /// Quality (0-100) of the alpha channel; `quality` if `None`
+    pub alpha_quality: Option<u8>,
+    /// Encoding speed (1-10), higher is faster and larger; the encoder's default if `None`
+    pub speed: Option<u8>,
+    /// Bit depth (8 or 10) of the encoded image; 10 for inputs with more than 8 bits
+    /// per channel and the encoder's choice otherwise if `None`
+    pub bit_depth: Option<u8>,
+}
+
+impl Default for AvifOptions {
+    fn default() -> Self {
+        Self {
+            quality: 80,
+            alpha_quality: None,
+            speed: None,
+            bit_depth: None,
+        }
+    }
+}
+
+impl AvifOptions {
+    /// Checks that every setting is in range.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `quality` or `alpha_quality` is above 100, `speed` is not
+    /// between 1 and 10, or `bit_depth` is not 8 or 10
+    pub fn validate(&self) -> Result<()> {
+        check_range("AVIF quality", self.quality, 0..=100)?;
+        if let Some(alpha_quality) = self.alpha_quality {
+            check_range("AVIF alpha quality", alpha_quality, 0..=100)?;
+        }
+        if let Some(speed) = self.speed {
+            check_range("AVIF speed", speed, 1..=10)?;
+        }
+        if let Some(bit_depth) = self.bit_depth
+            && bit_depth != 8
+            && bit_depth != 10
+        {
+            anyhow::bail!("AVIF bit depth must be 8 or 10, got {bit_depth}");
+        }
+        Ok(())
+    }
+}
+
+/// Encoder options for one output format.
+///
+/// With the `serde` feature the variant is chosen by a `format` field, so a TOML
+/// table like `format = "webp"`, `quality = 90` reads as [`EncoderOptions::Webp`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "format", rename_all = "lowercase"))]
+pub enum EncoderOptions {
+    /// JPEG output
+    Jpeg(JpegOptions),
+    /// PNG output
+    Png(PngOptions),
+    /// WebP output
+    Webp(WebpOptions),
+    /// JPEG XL output
+    Jxl(JxlOptions),
+    /// AVIF output
+    Avif(AvifOptions),
+}
+
+impl EncoderOptions {
+    /// Returns the name of the output format these options are for.
+    #[must_use]
+    pub fn format_name(&self) -> &'static str {
+        match self {
+            Self::Jpeg(_) => "JPEG",
+            Self::Png(_) => "PNG",
+            Self::Webp(_) => "WebP",
+            Self::Jxl(_) => "JPEG XL",
+            Self::Avif(_) => "AVIF",
+        }
+    }
+
+    /// Checks that every setting is in range.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the format's own `validate`
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Self::Jpeg(options) => options.validate(),
+            Self::Png(options) => options.validate(),
+            Self::Webp(options) => options.validate(),
+            Self::Jxl(options) => options.validate(),
+            Self::Avif(options) => options.validate(),
+        }
+    }
+}
+
+impl From<JpegOptions> for EncoderOptions {
+    fn from(options: JpegOptions) -> Self {
+        Self::Jpeg(options)
+    }
+}
+
+impl From<PngOptions> for EncoderOptions {
+    fn from(options: PngOptions) -> Self {
+        Self::Png(options)
+    }
+}
+
+impl From<WebpOptions> for EncoderOptions {
+    fn from(options: WebpOptions) -> Self {
+        Self::Webp(options)
+    }
+}
+
+impl From<JxlOptions> for EncoderOptions {
+    fn from(options: JxlOptions) -> Self {
+        Self::Jxl(options)
+    }
+}
+
+impl From<AvifOptions> for EncoderOptions {
+    fn from(options: AvifOptions) -> Self {
+        Self::Avif(options)
+    }
+}
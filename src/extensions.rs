@@ -0,0 +1,688 @@
+//! File extension validation and repair.
+//!
+//! Scrapers and downloaders frequently save images under the wrong extension
+//! (for example a PNG named `photo.jpg`). This module provides:
+//! - A typed comparison between the extension of a file and its contents
+//! - A directory-level fixer that renames files to the extension of their actual format
+//! - Renaming of sidecar files (such as `.txt` captions) alongside their images
+//! - Dry-run mode and collision handling, with a report of every change
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use std::path::Path;
+//! use imx::extensions::{fix_image_extensions, ExtensionFixOptions};
+//!
+//! async fn fix_dataset() -> anyhow::Result<()> {
+//!     // Preview what would be renamed
+//!     let options = ExtensionFixOptions::default().with_dry_run(true);
+//!     let report = fix_image_extensions(Path::new("dataset"), &options).await?;
+//!     for rename in &report.renamed {
+//!         println!("{} -> {}", rename.from.display(), rename.to.display());
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+#![warn(clippy::all, clippy::pedantic)]
+
+use crate::image_processing::{
+    DetectedImageFormat, FormatMatch, SniffConfidence, SniffOutcome, sniff_image_format_from_reader,
+};
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// A file whose extension claims a different format than its contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtensionMismatch {
+    /// Format claimed by the file extension
+    pub claimed: DetectedImageFormat,
+    /// Format detected from the file contents
+    pub actual: FormatMatch,
+}
+
+/// Result of comparing the extension of a file with its contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtensionCheck {
+    /// The extension agrees with the detected format
+    Consistent(FormatMatch),
+    /// The extension claims a different format than the contents
+    Mismatch(ExtensionMismatch),
+    /// The extension is not a known image extension
+    UnknownExtension,
+    /// The extension is an image extension, but the contents were not recognized
+    UnknownContent {
+        /// Format claimed by the file extension
+        claimed: DetectedImageFormat,
+    },
+}
+
+/// Compares the extension of a file with the format detected from its contents.
+///
+/// # Arguments
+///
+/// * `path` - Path to the file to check
+///
+/// # Returns
+///
+/// Returns a `Result` containing the `ExtensionCheck` for the file
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be opened or read
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use std::path::Path;
+/// use imx::extensions::{check_image_extension, ExtensionCheck};
+///
+/// fn report() -> anyhow::Result<()> {
+///     if let ExtensionCheck::Mismatch(mismatch) = check_image_extension(Path::new("photo.jpg"))? {
+///         println!(
+///             "claims {:?} but is {:?}",
+///             mismatch.claimed, mismatch.actual.format
+///         );
+///     }
+///     Ok(())
+/// }
+/// ```
+pub fn check_image_extension(path: &Path) -> Result<ExtensionCheck> {
+    let Some(claimed) = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .and_then(DetectedImageFormat::from_extension)
+    else {
+        return Ok(ExtensionCheck::UnknownExtension);
+    };
+
+    let mut file = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open file: {}", path.display()))?;
+    let (outcome, _) = sniff_image_format_from_reader(&mut file)
+        .with_context(|| format!("Failed to read file: {}", path.display()))?;
+
+    Ok(match outcome {
+        SniffOutcome::Detected(actual) if actual.format == claimed => {
+            ExtensionCheck::Consistent(actual)
+        }
+        SniffOutcome::Detected(actual) => {
+            ExtensionCheck::Mismatch(ExtensionMismatch { claimed, actual })
+        }
+        SniffOutcome::Inconclusive { .. } | SniffOutcome::Unknown => {
+            ExtensionCheck::UnknownContent { claimed }
+        }
+    })
+}
+
+/// What to do when the corrected file name is already taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CollisionPolicy {
+    /// Leave the file alone and record it as skipped (default)
+    #[default]
+    Skip,
+    /// Append `_1`, `_2`, ... to the file stem until the name is free
+    Suffix,
+}
+
+/// Options for `fix_image_extensions`.
+#[derive(Debug, Clone)]
+pub struct ExtensionFixOptions {
+    /// Only report what would be renamed, without touching the filesystem
+    pub dry_run: bool,
+    /// Descend into subdirectories
+    pub recursive: bool,
+    /// What to do when the corrected name is already taken
+    pub collision_policy: CollisionPolicy,
+    /// Rename sidecar files (e.g. `.txt` captions) together with their image
+    pub rename_sidecars: bool,
+    /// Minimum confidence of the detected format required to rename a file
+    pub min_confidence: SniffConfidence,
+}
+
+impl Default for ExtensionFixOptions {
+    fn default() -> Self {
+        Self {
+            dry_run: false,
+            recursive: false,
+            collision_policy: CollisionPolicy::default(),
+            rename_sidecars: true,
+            min_confidence: SniffConfidence::Medium,
+        }
+    }
+}
+
+impl ExtensionFixOptions {
+    /// Set whether to only report changes without renaming anything
+    #[must_use]
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Set whether to descend into subdirectories
+    #[must_use]
+    pub fn with_recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    /// Set the policy for name collisions
+    #[must_use]
+    pub fn with_collision_policy(mut self, policy: CollisionPolicy) -> Self {
+        self.collision_policy = policy;
+        self
+    }
+
+    /// Set whether sidecar files are renamed together with their image
+    #[must_use]
+    pub fn with_rename_sidecars(mut self, rename_sidecars: bool) -> Self {
+        self.rename_sidecars = rename_sidecars;
+        self
+    }
+
+    /// Set the minimum detection confidence required to rename a file
+    #[must_use]
+    pub fn with_min_confidence(mut self, confidence: SniffConfidence) -> Self {
+        self.min_confidence = confidence;
+        self
+    }
+}
+
+/// A file that was (or, in dry-run mode, would be) renamed.
+#[derive(Debug, Clone)]
+pub struct ExtensionRename {
+    /// Original path of the image
+    pub from: PathBuf,
+    /// New path of the image
+    pub to: PathBuf,
+    /// The mismatch that caused the rename
+    pub mismatch: ExtensionMismatch,
+    /// Sidecar files renamed alongside the image, as `(from, to)` pairs
+    pub sidecars: Vec<(PathBuf, PathBuf)>,
+}
+
+/// Why a mismatched file was not renamed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The corrected name (or the name of one of its sidecars) is already taken
+    Collision(PathBuf),
+    /// The detected format was below `ExtensionFixOptions::min_confidence`
+    LowConfidence,
+}
+
+/// A mismatched file that was left alone.
+#[derive(Debug, Clone)]
+pub struct SkippedFix {
+    /// Path of the image
+    pub path: PathBuf,
+    /// The mismatch that was found
+    pub mismatch: ExtensionMismatch,
+    /// Why the file was not renamed
+    pub reason: SkipReason,
+}
+
+/// A file or directory that could not be checked or renamed.
+#[derive(Debug)]
+pub struct FailedFix {
+    /// Path of the file or directory
+    pub path: PathBuf,
+    /// What went wrong
+    pub error: anyhow::Error,
+}
+
+/// Report of what `fix_image_extensions` changed.
+#[derive(Debug, Default)]
+pub struct ExtensionFixReport {
+    /// Whether this report describes a dry run
+    pub dry_run: bool,
+    /// Number of files whose extension was checked
+    pub checked: usize,
+    /// Files that were renamed (or would be, in dry-run mode)
+    pub renamed: Vec<ExtensionRename>,
+    /// Mismatched files that were left alone
+    pub skipped: Vec<SkippedFix>,
+    /// Files and subdirectories that could not be checked or renamed
+    pub failed: Vec<FailedFix>,
+}
+
+/// Lists the entries of a directory, sorted by name so runs are deterministic.
+async fn sorted_entries(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut read_dir = tokio::fs::read_dir(dir)
+        .await
+        .with_context(|| format!("Failed to read directory: {}", dir.display()))?;
+    let mut entries = Vec::new();
+    while let Some(entry) = read_dir.next_entry().await? {
+        entries.push(entry.path());
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+/// Finds the sidecar files of an image among the entries of its directory.
+///
+/// Two naming conventions are recognized: files sharing the stem of the image
+/// (`photo.txt` for `photo.jpg`) that are not images themselves, and files that
+/// extend the full image name (`photo.jpg.json`). Returns `(path, suffix)` pairs,
+/// where the suffix is what follows the shared part of the name.
+fn find_sidecars<'a>(
+    image: &Path,
+    entries: impl Iterator<Item = &'a PathBuf>,
+) -> Vec<(PathBuf, String)> {
+    let (Some(stem), Some(name)) = (
+        image.file_stem().and_then(|s| s.to_str()),
+        image.file_name().and_then(|s| s.to_str()),
+    ) else {
+        return Vec::new();
+    };
+
+    entries
+        .filter(|entry| entry.as_path() != image)
+        .filter(|entry| {
+            // Other images sharing the name are never treated as sidecars
+            entry
+                .extension()
+                .and_then(|e| e.to_str())
+                .and_then(DetectedImageFormat::from_extension)
+                .is_none()
+        })
+        .filter_map(|entry| {
+            let entry_name = entry.file_name()?.to_str()?;
+            if let Some(suffix) = entry_name.strip_prefix(name).filter(|s| s.starts_with('.')) {
+                return Some((entry.clone(), format!("{{name}}{suffix}")));
+            }
+            let suffix = entry_name.strip_prefix(stem)?.strip_prefix('.')?;
+            (!suffix.contains('.')).then(|| (entry.clone(), format!("{{stem}}.{suffix}")))
+        })
+        .collect()
+}
+
+/// Computes the target names for an image and its sidecars with the given stem.
+fn plan_names(
+    dir: &Path,
+    stem: &str,
+    extension: &str,
+    sidecars: &[(PathBuf, String)],
+) -> (PathBuf, Vec<(PathBuf, PathBuf)>) {
+    let name = format!("{stem}.{extension}");
+    let sidecar_targets = sidecars
+        .iter()
+        .map(|(path, pattern)| {
+            let target = pattern.replace("{name}", &name).replace("{stem}", stem);
+            (path.clone(), dir.join(target))
+        })
+        .collect();
+    (dir.join(name), sidecar_targets)
+}
+
+/// Renames files whose extension does not match their contents.
+///
+/// Every file with an image extension in `dir` is sniffed; files whose contents
+/// belong to a different format are renamed to the standard extension of that
+/// format (`DetectedImageFormat::extension()`). Sidecar files are renamed with
+/// their image when `ExtensionFixOptions::rename_sidecars` is set, and targets
+/// that are already taken are handled according to the `CollisionPolicy`.
+///
+/// Symbolic links are checked like the files they point to, but links to
+/// directories are never followed. Files and subdirectories that cannot be read
+/// or renamed are recorded in `ExtensionFixReport::failed` and the walk goes on.
+///
+/// # Arguments
+///
+/// * `dir` - Directory to scan
+/// * `options` - Options controlling dry-run mode, recursion, collisions and sidecars
+///
+/// # Returns
+///
+/// Returns a `Result` containing an `ExtensionFixReport` describing every rename and skip
+///
+/// # Errors
+///
+/// Returns an error if `dir` itself cannot be read
+pub async fn fix_image_extensions(
+    dir: &Path,
+    options: &ExtensionFixOptions,
+) -> Result<ExtensionFixReport> {
+    let mut report = ExtensionFixReport {
+        dry_run: options.dry_run,
+        ..ExtensionFixReport::default()
+    };
+    let mut pending_dirs = vec![dir.to_path_buf()];
+
+    while let Some(current_dir) = pending_dirs.pop() {
+        let entries = match sorted_entries(&current_dir).await {
+            Ok(entries) => entries,
+            Err(error) if current_dir != dir => {
+                warn!("Skipping {}: {error:#}", current_dir.display());
+                report.failed.push(FailedFix {
+                    path: current_dir,
+                    error,
+                });
+                continue;
+            }
+            Err(error) => return Err(error),
+        };
+        // Names taken by the files on disk and by the renames planned so far
+        let mut taken: HashSet<PathBuf> = entries.iter().cloned().collect();
+
+        for path in &entries {
+            // Sidecars renamed together with an earlier image are gone
+            if !taken.contains(path) {
+                continue;
+            }
+            let Some(mismatch) = check_entry(path, options, &mut report, &mut pending_dirs).await
+            else {
+                continue;
+            };
+
+            let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+                report.fail(
+                    path,
+                    anyhow::anyhow!("Invalid file name: {}", path.display()),
+                );
+                continue;
+            };
+            let sidecars = if options.rename_sidecars {
+                find_sidecars(path, entries.iter().filter(|entry| taken.contains(*entry)))
+            } else {
+                Vec::new()
+            };
+
+            let (target, sidecar_targets) = match plan_rename(
+                &current_dir,
+                path,
+                stem,
+                mismatch.actual.format.extension(),
+                &sidecars,
+                &taken,
+                options.collision_policy,
+            ) {
+                RenamePlan::Rename(target, sidecar_targets) => (target, sidecar_targets),
+                RenamePlan::Collision(existing) => {
+                    report.skip_collision(path, mismatch, existing);
+                    continue;
+                }
+            };
+
+            info!(
+                "{}Renaming {} to {} ({:?} saved as {:?})",
+                if options.dry_run { "[dry run] " } else { "" },
+                path.display(),
+                target.display(),
+                mismatch.actual.format,
+                mismatch.claimed
+            );
+            let sidecar_targets = if options.dry_run {
+                sidecar_targets
+            } else {
+                match apply_rename(path, &target, sidecar_targets, &mut report).await {
+                    RenameOutcome::Renamed(moved) => moved,
+                    RenameOutcome::Collision => {
+                        report.skip_collision(path, mismatch, target);
+                        continue;
+                    }
+                    RenameOutcome::Failed => continue,
+                }
+            };
+
+            taken.remove(path);
+            taken.insert(target.clone());
+            for (from, to) in &sidecar_targets {
+                taken.remove(from);
+                taken.insert(to.clone());
+            }
+            report.renamed.push(ExtensionRename {
+                from: path.clone(),
+                to: target,
+                mismatch,
+                sidecars: sidecar_targets,
+            });
+        }
+    }
+
+    info!(
+        "Checked {} files: {} renamed, {} skipped, {} failed",
+        report.checked,
+        report.renamed.len(),
+        report.skipped.len(),
+        report.failed.len()
+    );
+    Ok(report)
+}
+
+/// Checks one directory entry, returning its mismatch if it should be renamed.
+///
+/// Subdirectories are queued in `pending_dirs` when recursing, and files that
+/// cannot be checked or are detected with too little confidence are recorded
+/// in `report`.
+async fn check_entry(
+    path: &Path,
+    options: &ExtensionFixOptions,
+    report: &mut ExtensionFixReport,
+    pending_dirs: &mut Vec<PathBuf>,
+) -> Option<ExtensionMismatch> {
+    match entry_kind(path).await {
+        Ok(EntryKind::File) => {}
+        Ok(EntryKind::Directory) => {
+            if options.recursive {
+                pending_dirs.push(path.to_path_buf());
+            }
+            return None;
+        }
+        Ok(EntryKind::Other) => return None,
+        Err(error) => {
+            report.fail(path, error);
+            return None;
+        }
+    }
+
+    // Sniffing reads the file, so it runs on the blocking pool
+    let owned_path = path.to_path_buf();
+    let check = tokio::task::spawn_blocking(move || check_image_extension(&owned_path))
+        .await
+        .context("Extension check task failed")
+        .and_then(|check| check);
+    let check = match check {
+        Ok(check) => check,
+        Err(error) => {
+            report.fail(path, error);
+            return None;
+        }
+    };
+    if !matches!(check, ExtensionCheck::UnknownExtension) {
+        report.checked += 1;
+    }
+    let ExtensionCheck::Mismatch(mismatch) = check else {
+        return None;
+    };
+
+    if mismatch.actual.confidence < options.min_confidence {
+        debug!(
+            "Not renaming {}: {:?} detection has {:?} confidence",
+            path.display(),
+            mismatch.actual.format,
+            mismatch.actual.confidence
+        );
+        report.skipped.push(SkippedFix {
+            path: path.to_path_buf(),
+            mismatch,
+            reason: SkipReason::LowConfidence,
+        });
+        return None;
+    }
+    Some(mismatch)
+}
+
+/// Result of [`plan_rename`].
+enum RenamePlan {
+    /// The new path of the image, and `(from, to)` pairs for the sidecars that move
+    Rename(PathBuf, Vec<(PathBuf, PathBuf)>),
+    /// The name that is already taken, under `CollisionPolicy::Skip`
+    Collision(PathBuf),
+}
+
+/// Picks the new names of an image and its sidecars, avoiding the `taken` names.
+fn plan_rename(
+    dir: &Path,
+    path: &Path,
+    stem: &str,
+    extension: &str,
+    sidecars: &[(PathBuf, String)],
+    taken: &HashSet<PathBuf>,
+    policy: CollisionPolicy,
+) -> RenamePlan {
+    // A sidecar target is free if nothing else holds it, or if it is
+    // the sidecar itself (same-stem sidecars keep their name)
+    let is_free = |target: &PathBuf, own: &Path| target == own || !taken.contains(target);
+    let mut attempt = 0u32;
+    loop {
+        let candidate_stem = if attempt == 0 {
+            stem.to_string()
+        } else {
+            format!("{stem}_{attempt}")
+        };
+        let (target, sidecar_targets) = plan_names(dir, &candidate_stem, extension, sidecars);
+        let collision = std::iter::once((&target, path))
+            .chain(
+                sidecar_targets
+                    .iter()
+                    .map(|(from, to)| (to, from.as_path())),
+            )
+            .find(|(to, from)| !is_free(to, from))
+            .map(|(to, _)| to.clone());
+
+        match (collision, policy) {
+            (None, _) => {
+                let sidecar_targets = sidecar_targets
+                    .into_iter()
+                    .filter(|(from, to)| from != to)
+                    .collect();
+                return RenamePlan::Rename(target, sidecar_targets);
+            }
+            (Some(_), CollisionPolicy::Suffix) => attempt += 1,
+            (Some(existing), CollisionPolicy::Skip) => return RenamePlan::Collision(existing),
+        }
+    }
+}
+
+/// Result of [`apply_rename`].
+enum RenameOutcome {
+    /// The image was renamed, with the sidecars that moved alongside it
+    Renamed(Vec<(PathBuf, PathBuf)>),
+    /// The target appeared after planning, so nothing was renamed
+    Collision,
+    /// The image could not be renamed; the error is in the report
+    Failed,
+}
+
+/// Renames an image and its sidecars, never replacing a file that already exists.
+///
+/// Files can appear between planning and renaming, so every target is checked
+/// again right before its rename. Sidecars that cannot be renamed keep their
+/// name and are recorded in `report`.
+async fn apply_rename(
+    path: &Path,
+    target: &Path,
+    sidecar_targets: Vec<(PathBuf, PathBuf)>,
+    report: &mut ExtensionFixReport,
+) -> RenameOutcome {
+    match rename_if_free(path, target).await {
+        Ok(true) => {}
+        Ok(false) => return RenameOutcome::Collision,
+        Err(error) => {
+            report.fail(path, error);
+            return RenameOutcome::Failed;
+        }
+    }
+
+    let mut moved = Vec::with_capacity(sidecar_targets.len());
+    for (from, to) in sidecar_targets {
+        match rename_if_free(&from, &to).await {
+            Ok(true) => moved.push((from, to)),
+            Ok(false) => report.fail(
+                &from,
+                anyhow::anyhow!("Not renaming sidecar: {} already exists", to.display()),
+            ),
+            Err(error) => report.fail(&from, error),
+        }
+    }
+    RenameOutcome::Renamed(moved)
+}
+
+/// Renames `from` to `to` unless something already exists at `to`.
+///
+/// Returns whether the file was renamed.
+async fn rename_if_free(from: &Path, to: &Path) -> Result<bool> {
+    match tokio::fs::symlink_metadata(to).await {
+        Ok(_) => return Ok(false),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+        Err(error) => {
+            return Err(anyhow::Error::new(error)
+                .context(format!("Failed to read metadata: {}", to.display())));
+        }
+    }
+    tokio::fs::rename(from, to)
+        .await
+        .with_context(|| format!("Failed to rename {}", from.display()))?;
+    Ok(true)
+}
+
+impl ExtensionFixReport {
+    /// Records a file left alone because its new name is taken.
+    fn skip_collision(&mut self, path: &Path, mismatch: ExtensionMismatch, existing: PathBuf) {
+        warn!(
+            "Not renaming {}: {} already exists",
+            path.display(),
+            existing.display()
+        );
+        self.skipped.push(SkippedFix {
+            path: path.to_path_buf(),
+            mismatch,
+            reason: SkipReason::Collision(existing),
+        });
+    }
+
+    /// Records a file that could not be checked or renamed.
+    fn fail(&mut self, path: &Path, error: anyhow::Error) {
+        warn!("Skipping {}: {error:#}", path.display());
+        self.failed.push(FailedFix {
+            path: path.to_path_buf(),
+            error,
+        });
+    }
+}
+
+/// What a directory entry is, as far as `fix_image_extensions` is concerned.
+enum EntryKind {
+    /// A file, or a symbolic link to one
+    File,
+    /// A directory that is not a symbolic link
+    Directory,
+    /// A symbolic link to a directory, a dangling link or a special file
+    Other,
+}
+
+/// Classifies a directory entry without following links to directories.
+async fn entry_kind(path: &Path) -> Result<EntryKind> {
+    let metadata = tokio::fs::symlink_metadata(path)
+        .await
+        .with_context(|| format!("Failed to read metadata: {}", path.display()))?;
+    if metadata.is_dir() {
+        return Ok(EntryKind::Directory);
+    }
+    if metadata.is_file() {
+        return Ok(EntryKind::File);
+    }
+    if metadata.file_type().is_symlink() {
+        return Ok(match tokio::fs::metadata(path).await {
+            Ok(target) if target.is_file() => EntryKind::File,
+            Ok(_) => EntryKind::Other,
+            Err(_) => {
+                debug!("Skipping dangling link {}", path.display());
+                EntryKind::Other
+            }
+        });
+    }
+    Ok(EntryKind::Other)
+}
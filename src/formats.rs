@@ -1,7 +1,7 @@
 //! Image format conversion module.
 //!
 //! This module provides comprehensive support for converting between different image formats
-//! supported by the `image` crate. It includes:
+//! supported by the `image` crate, plus JPEG XL output. It includes:
 //! - Format detection and validation
 //! - Format conversion with quality control
 //! - Batch processing capabilities
@@ -30,23 +30,81 @@
 //! }
 //! ```
 
+use crate::color::{convert_to_srgb, icc_profile_fits};
+use crate::encoder_options::{
+    AvifOptions, ChromaSubsampling, EncoderOptions, JpegOptions, JxlOptions, PngCompression,
+    PngFilter, PngOptions, WebpOptions,
+};
+use crate::image_processing::{decode_image_file, open_image};
+use crate::jxl::is_jxl_file;
+use crate::metadata::{
+    ImageMetadata, JPEG_XMP_PREFIX, MetadataKind, MetadataPolicy, embed_jxl_metadata,
+    embed_png_metadata, embed_webp_metadata,
+};
+use crate::metrics::{ImageMetrics, compare_images};
+use crate::numeric::{f32_to_u8, f32_to_u16, f32_to_u32};
 use anyhow::{Context, Result};
 use image::{
-    ImageEncoder, ImageFormat,
-    codecs::{jpeg::JpegEncoder, png::PngEncoder, webp::WebPEncoder},
+    ColorType, DynamicImage, ImageEncoder, ImageFormat,
+    codecs::png::{CompressionType, FilterType, PngEncoder},
 };
 use log::{debug, info, warn};
-use std::path::{Path, PathBuf};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
 
 /// Options for controlling image format conversion.
+///
+/// Besides `quality` and `lossless`, some encoders read extra settings added
+/// through [`ImageFormatOptions::with_option`]:
+///
+/// * JPEG: `subsampling` (`444`, `422`, `420`, `440` or `411`, colons allowed)
+///   sets the chroma subsampling, `progressive` (`true`/`false`) writes a
+///   progressive file and `optimize_huffman` (`true`/`false`) builds Huffman
+///   tables for the image instead of using the standard ones.
+/// * PNG: `compression` (`fast`, `default` or `best`) sets the deflate level and
+///   `filter` (`none`, `sub`, `up`, `avg`, `paeth` or `adaptive`) the row filter.
+///   PNG is lossless, so `quality` does not apply.
+/// * WebP: `alpha_quality` (0-100) sets the quality of the alpha plane in lossy
+///   mode, and `method` (0-6) trades encoding speed for smaller output.
+/// * JPEG XL: `effort` (1-9) trades encoding speed for smaller output.
+///
+/// Keys the output format does not read are rejected, so typos fail the
+/// conversion instead of being ignored.
+///
+/// The same settings can be given as typed [`EncoderOptions`] with
+/// [`ImageFormatOptions::with_encoder_options`], which replace `quality`,
+/// `lossless` and the string options. The typed options of a preset are
+/// available through `TryFrom`, e.g. `JpegOptions::try_from(&ImageFormatOptions::jpeg())`.
+///
+/// ICC profiles of the input are embedded in JPEG, PNG and WebP output. JPEG XL
+/// and AVIF can carry a profile too, but the encoders used here cannot write one,
+/// so their pixels are converted to sRGB instead and a warning is logged;
+/// [`ImageFormatOptions::with_srgb_conversion`] does this for every format without
+/// the warning.
+///
+/// Which other metadata (EXIF, XMP, PNG text) is copied is set with
+/// [`ImageFormatOptions::with_metadata`]; by default only the ICC profile is kept.
 #[derive(Debug, Clone)]
 pub struct ImageFormatOptions {
     /// Quality setting (0-100) for lossy formats
     quality: u8,
     /// Whether to use lossless compression (when supported)
     lossless: bool,
+    /// Whether to convert pixels to sRGB instead of embedding the ICC profile
+    srgb_conversion: bool,
+    /// Whether to rotate and flip the image upright according to its EXIF orientation
+    auto_orient: bool,
+    /// Which metadata of the input is written to the output
+    metadata: MetadataPolicy,
     /// Format-specific options as key-value pairs
     extra_options: std::collections::HashMap<String, String>,
+    /// Typed encoder options, used instead of `quality`, `lossless` and `extra_options`
+    encoder: Option<EncoderOptions>,
 }
 
 impl Default for ImageFormatOptions {
@@ -54,7 +112,11 @@ impl Default for ImageFormatOptions {
         Self {
             quality: 90,
             lossless: false,
+            srgb_conversion: false,
+            auto_orient: false,
+            metadata: MetadataPolicy::default(),
             extra_options: std::collections::HashMap::new(),
+            encoder: None,
         }
     }
 }
@@ -66,7 +128,7 @@ impl ImageFormatOptions {
         Self {
             quality: 85,
             lossless: false,
-            extra_options: std::collections::HashMap::new(),
+            ..Self::default()
         }
     }
 
@@ -76,7 +138,7 @@ impl ImageFormatOptions {
         Self {
             quality: 100,
             lossless: true,
-            extra_options: std::collections::HashMap::new(),
+            ..Self::default()
         }
     }
 
@@ -86,7 +148,37 @@ impl ImageFormatOptions {
         Self {
             quality: 80,
             lossless: false,
-            extra_options: std::collections::HashMap::new(),
+            ..Self::default()
+        }
+    }
+
+    /// Create options optimized for JPEG XL format
+    ///
+    /// Defaults to lossless encoding, which is always available. Lossy output
+    /// (`with_lossless(false)` and a quality below 100) needs the `jxl-lossy`
+    /// cargo feature.
+    ///
+    /// Lossless output keeps colour samples at up to 16 bits, but alpha is
+    /// stored with 8 bits, so images with 16-bit or floating point alpha lose
+    /// alpha precision.
+    #[must_use]
+    pub fn jxl() -> Self {
+        Self {
+            quality: 100,
+            lossless: true,
+            ..Self::default()
+        }
+    }
+
+    /// Create options optimized for AVIF format
+    ///
+    /// AVIF has no lossless mode; quality 100 is near-lossless.
+    #[must_use]
+    pub fn avif() -> Self {
+        Self {
+            quality: 80,
+            lossless: false,
+            ..Self::default()
         }
     }
 
@@ -104,6 +196,52 @@ impl ImageFormatOptions {
         self
     }
 
+    /// Set whether to convert pixels to sRGB using the input's ICC profile
+    ///
+    /// When enabled, pixel data is converted with the embedded profile before
+    /// writing and no profile is embedded in the output. Inputs without a profile
+    /// are assumed to be sRGB already and are left untouched.
+    #[must_use]
+    pub fn with_srgb_conversion(mut self, srgb_conversion: bool) -> Self {
+        self.srgb_conversion = srgb_conversion;
+        self
+    }
+
+    /// Set whether to apply the input's EXIF orientation to the pixels
+    ///
+    /// When enabled, images are rotated and flipped upright before encoding (see
+    /// [`open_image_oriented`](crate::image_processing::open_image_oriented)), so
+    /// the output displays correctly without an orientation tag.
+    #[must_use]
+    pub fn with_auto_orient(mut self, auto_orient: bool) -> Self {
+        self.auto_orient = auto_orient;
+        self
+    }
+
+    /// Set which metadata of the input is written to the output
+    ///
+    /// EXIF and XMP are written to JPEG, PNG, WebP and JPEG XL output, and PNG
+    /// text chunks to PNG output. Images whose ICC profile is not kept are
+    /// converted to sRGB. Without the orientation, images that rely on it are
+    /// no longer displayed upright unless [`ImageFormatOptions::with_auto_orient`]
+    /// is enabled too.
+    #[must_use]
+    pub fn with_metadata(mut self, metadata: MetadataPolicy) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Set typed encoder options for the output format
+    ///
+    /// They replace `quality`, `lossless` and the string options of
+    /// [`ImageFormatOptions::with_option`], which must not be set as well. The
+    /// conversion fails if the options are for a different format than the output.
+    #[must_use]
+    pub fn with_encoder_options(mut self, encoder: impl Into<EncoderOptions>) -> Self {
+        self.encoder = Some(encoder.into());
+        self
+    }
+
     /// Add a format-specific option
     #[must_use]
     pub fn with_option(mut self, key: &str, value: &str) -> Self {
@@ -111,6 +249,588 @@ impl ImageFormatOptions {
             .insert(key.to_string(), value.to_string());
         self
     }
+
+    /// Parse a format-specific option, checking that it lies within `range`.
+    fn parse_option<T>(&self, key: &str, range: std::ops::RangeInclusive<T>) -> Result<Option<T>>
+    where
+        T: FromStr + PartialOrd + std::fmt::Display,
+    {
+        let Some(raw) = self.extra_options.get(key) else {
+            return Ok(None);
+        };
+        let value = raw
+            .trim()
+            .parse::<T>()
+            .map_err(|_| anyhow::anyhow!("Invalid value {raw:?} for option {key:?}"))?;
+        if !range.contains(&value) {
+            anyhow::bail!(
+                "Option {key:?} must be between {} and {}, got {value}",
+                range.start(),
+                range.end()
+            );
+        }
+        Ok(Some(value))
+    }
+
+    /// Parse a format-specific option that takes one of a fixed set of names.
+    ///
+    /// Names are matched case-insensitively.
+    fn parse_choice<T: Copy>(&self, key: &str, choices: &[(&str, T)]) -> Result<Option<T>> {
+        let Some(raw) = self.extra_options.get(key) else {
+            return Ok(None);
+        };
+        let name = raw.trim();
+        choices
+            .iter()
+            .find(|(choice, _)| choice.eq_ignore_ascii_case(name))
+            .map(|&(_, value)| Some(value))
+            .ok_or_else(|| {
+                let names: Vec<&str> = choices.iter().map(|&(choice, _)| choice).collect();
+                anyhow::anyhow!(
+                    "Invalid value {raw:?} for option {key:?}, expected one of {}",
+                    names.join(", ")
+                )
+            })
+    }
+
+    /// Parse a `true`/`false` format-specific option.
+    fn parse_flag(&self, key: &str) -> Result<Option<bool>> {
+        self.parse_choice(key, &[("true", true), ("false", false)])
+    }
+
+    /// Check that every format-specific option is one `format` reads.
+    pub(crate) fn check_option_keys(&self, format: &str, supported: &[&str]) -> Result<()> {
+        let mut unknown: Vec<&str> = self
+            .extra_options
+            .keys()
+            .map(String::as_str)
+            .filter(|key| !supported.contains(key))
+            .collect();
+        if unknown.is_empty() {
+            return Ok(());
+        }
+        unknown.sort_unstable();
+        if supported.is_empty() {
+            anyhow::bail!("{format} output takes no options, got {unknown:?}");
+        }
+        anyhow::bail!(
+            "Unknown {format} options {unknown:?}, supported options are {}",
+            supported.join(", ")
+        )
+    }
+
+    /// Return the typed encoder options, checking they are not mixed with string options.
+    fn typed_encoder_options(&self) -> Result<Option<&EncoderOptions>> {
+        if self.encoder.is_some() && !self.extra_options.is_empty() {
+            anyhow::bail!("Typed encoder options cannot be combined with string options");
+        }
+        Ok(self.encoder.as_ref())
+    }
+}
+
+impl From<EncoderOptions> for ImageFormatOptions {
+    fn from(encoder: EncoderOptions) -> Self {
+        Self::default().with_encoder_options(encoder)
+    }
+}
+
+/// Error for typed encoder options given for another output format.
+fn mismatched_encoder_options(encoder: &EncoderOptions, format: &str) -> anyhow::Error {
+    anyhow::anyhow!(
+        "{} encoder options cannot be used for {format} output",
+        encoder.format_name()
+    )
+}
+
+impl TryFrom<&ImageFormatOptions> for JpegOptions {
+    type Error = anyhow::Error;
+
+    /// Build validated JPEG options from `quality` and the string options.
+    fn try_from(options: &ImageFormatOptions) -> Result<Self> {
+        let jpeg = match options.typed_encoder_options()? {
+            Some(EncoderOptions::Jpeg(jpeg)) => jpeg.clone(),
+            Some(other) => return Err(mismatched_encoder_options(other, "JPEG")),
+            None => {
+                options.check_option_keys("JPEG", JPEG_OPTIONS)?;
+                Self {
+                    quality: options.quality.max(1),
+                    subsampling: options.parse_choice(
+                        "subsampling",
+                        &[
+                            ("444", ChromaSubsampling::Yuv444),
+                            ("422", ChromaSubsampling::Yuv422),
+                            ("420", ChromaSubsampling::Yuv420),
+                            ("440", ChromaSubsampling::Yuv440),
+                            ("411", ChromaSubsampling::Yuv411),
+                            ("4:4:4", ChromaSubsampling::Yuv444),
+                            ("4:2:2", ChromaSubsampling::Yuv422),
+                            ("4:2:0", ChromaSubsampling::Yuv420),
+                            ("4:4:0", ChromaSubsampling::Yuv440),
+                            ("4:1:1", ChromaSubsampling::Yuv411),
+                        ],
+                    )?,
+                    progressive: options.parse_flag("progressive")?.unwrap_or(false),
+                    optimize_huffman: options.parse_flag("optimize_huffman")?.unwrap_or(false),
+                }
+            }
+        };
+        jpeg.validate()?;
+        Ok(jpeg)
+    }
+}
+
+impl TryFrom<&ImageFormatOptions> for PngOptions {
+    type Error = anyhow::Error;
+
+    /// Build PNG options from the string options; PNG has no use for `quality`.
+    fn try_from(options: &ImageFormatOptions) -> Result<Self> {
+        let png = match options.typed_encoder_options()? {
+            Some(EncoderOptions::Png(png)) => png.clone(),
+            Some(other) => return Err(mismatched_encoder_options(other, "PNG")),
+            None => {
+                options.check_option_keys("PNG", PNG_OPTIONS)?;
+                Self {
+                    compression: options
+                        .parse_choice(
+                            "compression",
+                            &[
+                                ("fast", PngCompression::Fast),
+                                ("default", PngCompression::Default),
+                                ("best", PngCompression::Best),
+                            ],
+                        )?
+                        .unwrap_or_default(),
+                    filter: options
+                        .parse_choice(
+                            "filter",
+                            &[
+                                ("none", PngFilter::None),
+                                ("sub", PngFilter::Sub),
+                                ("up", PngFilter::Up),
+                                ("avg", PngFilter::Avg),
+                                ("paeth", PngFilter::Paeth),
+                                ("adaptive", PngFilter::Adaptive),
+                            ],
+                        )?
+                        .unwrap_or_default(),
+                }
+            }
+        };
+        png.validate()?;
+        Ok(png)
+    }
+}
+
+impl TryFrom<&ImageFormatOptions> for WebpOptions {
+    type Error = anyhow::Error;
+
+    /// Build validated WebP options from `quality`, `lossless` and the string options.
+    fn try_from(options: &ImageFormatOptions) -> Result<Self> {
+        let webp = match options.typed_encoder_options()? {
+            Some(EncoderOptions::Webp(webp)) => webp.clone(),
+            Some(other) => return Err(mismatched_encoder_options(other, "WebP")),
+            None => {
+                options.check_option_keys("WebP", WEBP_OPTIONS)?;
+                Self {
+                    quality: options.quality,
+                    lossless: options.lossless,
+                    alpha_quality: options.parse_option("alpha_quality", 0..=100)?,
+                    method: options.parse_option("method", 0..=6)?,
+                }
+            }
+        };
+        webp.validate()?;
+        Ok(webp)
+    }
+}
+
+impl TryFrom<&ImageFormatOptions> for JxlOptions {
+    type Error = anyhow::Error;
+
+    /// Build validated JPEG XL options from `quality`, `lossless` and the string options.
+    fn try_from(options: &ImageFormatOptions) -> Result<Self> {
+        let jxl = match options.typed_encoder_options()? {
+            Some(EncoderOptions::Jxl(jxl)) => jxl.clone(),
+            Some(other) => return Err(mismatched_encoder_options(other, "JPEG XL")),
+            None => {
+                options.check_option_keys("JPEG XL", JXL_OPTIONS)?;
+                Self {
+                    quality: options.quality,
+                    lossless: options.lossless,
+                    effort: options.parse_option("effort", 1..=9)?,
+                }
+            }
+        };
+        jxl.validate()?;
+        Ok(jxl)
+    }
+}
+
+impl TryFrom<&ImageFormatOptions> for AvifOptions {
+    type Error = anyhow::Error;
+
+    /// Build validated AVIF options from `quality` and the string options.
+    fn try_from(options: &ImageFormatOptions) -> Result<Self> {
+        let avif = match options.typed_encoder_options()? {
+            Some(EncoderOptions::Avif(avif)) => avif.clone(),
+            Some(other) => return Err(mismatched_encoder_options(other, "AVIF")),
+            None => {
+                options.check_option_keys("AVIF", AVIF_OPTIONS)?;
+                Self {
+                    quality: options.quality,
+                    alpha_quality: options.parse_option("alpha_quality", 0..=100)?,
+                    speed: options.parse_option("speed", 1..=10)?,
+                    bit_depth: options.parse_choice("bit_depth", &[("8", 8), ("10", 10)])?,
+                }
+            }
+        };
+        avif.validate()?;
+        Ok(avif)
+    }
+}
+
+/// String options read into [`JpegOptions`].
+const JPEG_OPTIONS: &[&str] = &["subsampling", "progressive", "optimize_huffman"];
+
+/// String options read into [`PngOptions`].
+const PNG_OPTIONS: &[&str] = &["compression", "filter"];
+
+/// String options read into [`WebpOptions`].
+const WEBP_OPTIONS: &[&str] = &["alpha_quality", "method"];
+
+/// String options read into [`JxlOptions`].
+const JXL_OPTIONS: &[&str] = &["effort"];
+
+/// String options read into [`AvifOptions`].
+const AVIF_OPTIONS: &[&str] = &["alpha_quality", "speed", "bit_depth"];
+
+/// Encode an image as JPEG, with its ICC profile, EXIF and XMP.
+///
+/// Without a `subsampling` setting, qualities below 90 use 4:2:0 and higher ones 4:4:4.
+fn encode_jpeg(
+    img: &DynamicImage,
+    metadata: &ImageMetadata,
+    options: &JpegOptions,
+) -> Result<Vec<u8>> {
+    use jpeg_encoder::{ColorType as JpegColorType, Encoder, SamplingFactor};
+
+    // JPEG only stores 8-bit gray or RGB, so drop alpha and extra precision
+    let (img, color_type) = match img.color() {
+        ColorType::L8 => (img.clone(), JpegColorType::Luma),
+        ColorType::Rgb8 => (img.clone(), JpegColorType::Rgb),
+        color if color.has_color() => (DynamicImage::ImageRgb8(img.to_rgb8()), JpegColorType::Rgb),
+        _ => (
+            DynamicImage::ImageLuma8(img.to_luma8()),
+            JpegColorType::Luma,
+        ),
+    };
+    let width = u16::try_from(img.width()).context("JPEG images are at most 65535 pixels wide")?;
+    let height =
+        u16::try_from(img.height()).context("JPEG images are at most 65535 pixels high")?;
+
+    let mut output = Vec::new();
+    let mut encoder = Encoder::new(&mut output, options.quality);
+    if let Some(subsampling) = options.subsampling {
+        encoder.set_sampling_factor(match subsampling {
+            ChromaSubsampling::Yuv444 => SamplingFactor::R_4_4_4,
+            ChromaSubsampling::Yuv422 => SamplingFactor::R_4_2_2,
+            ChromaSubsampling::Yuv420 => SamplingFactor::R_4_2_0,
+            ChromaSubsampling::Yuv440 => SamplingFactor::R_4_4_0,
+            ChromaSubsampling::Yuv411 => SamplingFactor::R_4_1_1,
+        });
+    }
+    encoder.set_progressive(options.progressive);
+    encoder.set_optimized_huffman_tables(options.optimize_huffman);
+    if let Some(profile) = &metadata.icc_profile {
+        encoder
+            .add_icc_profile(profile)
+            .map_err(|e| anyhow::anyhow!("Failed to embed ICC profile in JPEG: {e}"))?;
+    }
+    if let Some(exif) = &metadata.exif
+        && let Err(e) = encoder.add_exif_metadata(exif)
+    {
+        warn!("Not writing EXIF metadata: {e}");
+    }
+    if let Some(xmp) = &metadata.xmp
+        && let Err(e) = encoder.add_app_segment(1, [JPEG_XMP_PREFIX, xmp].concat())
+    {
+        warn!("Not writing XMP metadata: {e}");
+    }
+    encoder
+        .encode(img.as_bytes(), width, height, color_type)
+        .map_err(|e| anyhow::anyhow!("Failed to encode JPEG: {e}"))?;
+    Ok(output)
+}
+
+/// Encode an image as PNG with an optional ICC profile.
+fn encode_png(
+    img: &DynamicImage,
+    icc_profile: Option<Vec<u8>>,
+    options: &PngOptions,
+) -> Result<Vec<u8>> {
+    let compression = match options.compression {
+        PngCompression::Fast => CompressionType::Fast,
+        PngCompression::Default => CompressionType::Default,
+        PngCompression::Best => CompressionType::Best,
+    };
+    let filter = match options.filter {
+        PngFilter::None => FilterType::NoFilter,
+        PngFilter::Sub => FilterType::Sub,
+        PngFilter::Up => FilterType::Up,
+        PngFilter::Avg => FilterType::Avg,
+        PngFilter::Paeth => FilterType::Paeth,
+        PngFilter::Adaptive => FilterType::Adaptive,
+    };
+
+    let mut output = Vec::new();
+    let mut encoder = PngEncoder::new_with_quality(&mut output, compression, filter);
+    if let Some(profile) = icc_profile {
+        encoder
+            .set_icc_profile(profile)
+            .context("Failed to embed ICC profile in PNG")?;
+    }
+    encoder
+        .write_image(
+            img.as_bytes(),
+            img.width(),
+            img.height(),
+            img.color().into(),
+        )
+        .context("Failed to encode PNG")?;
+    Ok(output)
+}
+
+/// Build a libwebp encoder config from WebP options.
+///
+/// Lossy output honours `quality` and `alpha_quality`; both modes honour `method`.
+pub(crate) fn webp_config(options: &WebpOptions) -> Result<webp::WebPConfig> {
+    let mut config = webp::WebPConfig::new()
+        .map_err(|()| anyhow::anyhow!("Failed to initialize WebP encoder config"))?;
+    config.lossless = i32::from(options.lossless);
+    config.quality = f32::from(options.quality);
+    if let Some(method) = options.method {
+        config.method = i32::from(method);
+    }
+    if let Some(alpha_quality) = options.alpha_quality {
+        config.alpha_quality = i32::from(alpha_quality);
+    }
+    Ok(config)
+}
+
+/// Encode an image as WebP using libwebp, configured by [`webp_config`].
+fn encode_webp(img: &DynamicImage, options: &WebpOptions) -> Result<Vec<u8>> {
+    let config = webp_config(options)?;
+
+    // libwebp only accepts 8-bit RGB(A) input
+    let encoded = if img.color().has_alpha() {
+        let rgba = img.to_rgba8();
+        webp::Encoder::from_rgba(rgba.as_raw(), rgba.width(), rgba.height())
+            .encode_advanced(&config)
+    } else {
+        let rgb = img.to_rgb8();
+        webp::Encoder::from_rgb(rgb.as_raw(), rgb.width(), rgb.height()).encode_advanced(&config)
+    }
+    .map_err(|e| anyhow::anyhow!("Failed to encode WebP: {e:?}"))?;
+
+    Ok(encoded.to_vec())
+}
+
+/// Encode an image as AVIF using ravif.
+///
+/// Images with more than 8 bits per channel are encoded at 10 bits unless
+/// `bit_depth` asks for 8. The alpha channel is left out of opaque images.
+fn encode_avif(img: &DynamicImage, options: &AvifOptions) -> Result<Vec<u8>> {
+    use rgb::FromSlice;
+
+    // ravif takes qualities of 1-100
+    let mut encoder = ravif::Encoder::new()
+        .with_quality(f32::from(options.quality.max(1)))
+        .with_alpha_quality(f32::from(
+            options.alpha_quality.unwrap_or(options.quality).max(1),
+        ));
+    if let Some(speed) = options.speed {
+        encoder = encoder.with_speed(speed);
+    }
+
+    let high_bit_depth = !matches!(
+        img.color(),
+        ColorType::L8 | ColorType::La8 | ColorType::Rgb8 | ColorType::Rgba8
+    );
+    let avif = if high_bit_depth && options.bit_depth != Some(8) {
+        encode_avif_10_bit(&encoder, img)
+    } else {
+        let encoder = encoder.with_bit_depth(match options.bit_depth {
+            Some(8) => ravif::BitDepth::Eight,
+            Some(_) => ravif::BitDepth::Ten,
+            None => ravif::BitDepth::Auto,
+        });
+        let width = img.width() as usize;
+        let height = img.height() as usize;
+        if img.color().has_alpha() {
+            let rgba = img.to_rgba8();
+            encoder.encode_rgba(ravif::Img::new(rgba.as_raw().as_rgba(), width, height))
+        } else {
+            let rgb = img.to_rgb8();
+            encoder.encode_rgb(ravif::Img::new(rgb.as_raw().as_rgb(), width, height))
+        }
+    }
+    .map_err(|e| anyhow::anyhow!("Failed to encode AVIF: {e}"))?;
+
+    Ok(avif.avif_file)
+}
+
+/// Encode 16-bit samples as 10-bit AVIF, converting them to full range BT.601 YCbCr.
+///
+/// ravif only converts 8-bit RGB itself, so this keeps the extra precision.
+fn encode_avif_10_bit(
+    encoder: &ravif::Encoder,
+    img: &DynamicImage,
+) -> Result<ravif::EncodedImage, ravif::Error> {
+    const KR: f32 = 0.299;
+    const KB: f32 = 0.114;
+    const MAX: f32 = 1023.0;
+
+    let rgba = img.to_rgba16();
+    let unit = |sample: u16| f32::from(sample) / 65535.0;
+    let planes: Vec<[u16; 3]> = rgba
+        .pixels()
+        .map(|p| {
+            let (r, g, b) = (unit(p[0]), unit(p[1]), unit(p[2]));
+            let y = KR * r + (1.0 - KR - KB) * g + KB * b;
+            let cb = (b - y) / (2.0 * (1.0 - KB)) + 0.5;
+            let cr = (r - y) / (2.0 * (1.0 - KR)) + 0.5;
+            [y, cb, cr].map(|v| f32_to_u16(v * MAX).min(1023))
+        })
+        .collect();
+    let alpha: Option<Vec<u16>> = rgba.pixels().any(|p| p[3] != u16::MAX).then(|| {
+        rgba.pixels()
+            .map(|p| f32_to_u16(unit(p[3]) * MAX).min(1023))
+            .collect()
+    });
+
+    encoder.encode_raw_planes_10_bit(
+        img.width() as usize,
+        img.height() as usize,
+        planes,
+        alpha,
+        rav1e::prelude::PixelRange::Full,
+        ravif::MatrixCoefficients::BT601,
+    )
+}
+
+/// Map a 0-100 quality to a JPEG XL Butteraugli distance.
+///
+/// Uses the same curve as libjxl's `cjxl --quality`: 100 is lossless, 90 is
+/// distance 1.0 (visually lossless) and lower qualities grow quadratically.
+fn jxl_distance_for_quality(quality: u8) -> f32 {
+    let q = f32::from(quality.min(100));
+    if q >= 100.0 {
+        0.0
+    } else if q >= 30.0 {
+        0.1 + (100.0 - q) * 0.09
+    } else {
+        53.0 / 3000.0 * q * q - 23.0 / 20.0 * q + 25.0
+    }
+}
+
+/// Encode an image as JPEG XL.
+///
+/// Lossless output (or quality 100) uses the permissively licensed
+/// `zune-jpegxl` encoder. Lossy output maps `quality` to a distance with
+/// [`jxl_distance_for_quality`] and needs the `jxl-lossy` feature.
+fn encode_jxl(img: &DynamicImage, options: &JxlOptions) -> Result<Vec<u8>> {
+    if options.lossless || options.quality >= 100 {
+        encode_jxl_lossless(img, options.effort)
+    } else {
+        encode_jxl_lossy(
+            img,
+            jxl_distance_for_quality(options.quality),
+            options.effort,
+        )
+    }
+}
+
+/// Encode an image as lossless JPEG XL, keeping 16-bit colour samples when present.
+///
+/// Alpha is reduced to 8 bits, as documented on [`ImageFormatOptions::jxl`].
+fn encode_jxl_lossless(img: &DynamicImage, effort: Option<u8>) -> Result<Vec<u8>> {
+    use zune_core::{bit_depth::BitDepth, colorspace::ColorSpace, options::EncoderOptions};
+
+    let (colorspace, depth, converted) = match img {
+        DynamicImage::ImageLuma8(_) => (ColorSpace::Luma, BitDepth::Eight, None),
+        DynamicImage::ImageLumaA8(_) => (ColorSpace::LumaA, BitDepth::Eight, None),
+        DynamicImage::ImageRgb8(_) => (ColorSpace::RGB, BitDepth::Eight, None),
+        DynamicImage::ImageRgba8(_) => (ColorSpace::RGBA, BitDepth::Eight, None),
+        DynamicImage::ImageLuma16(_) => (ColorSpace::Luma, BitDepth::Sixteen, None),
+        DynamicImage::ImageRgb16(_) => (ColorSpace::RGB, BitDepth::Sixteen, None),
+        // Everything else is stored as 16-bit RGBA. zune-jpegxl reads 16-bit
+        // gray+alpha samples as signed and always declares an 8-bit alpha
+        // channel, so alpha is reduced to 8 bits to decode correctly.
+        other => {
+            let mut rgba = other.to_rgba16();
+            for pixel in rgba.pixels_mut() {
+                pixel[3] = u16::from(f32_to_u8(f32::from(pixel[3]) / 257.0));
+            }
+            (
+                ColorSpace::RGBA,
+                BitDepth::Sixteen,
+                Some(DynamicImage::ImageRgba16(rgba)),
+            )
+        }
+    };
+    let source = converted.as_ref().unwrap_or(img);
+
+    let mut encoder_options = EncoderOptions::new(
+        source.width() as usize,
+        source.height() as usize,
+        colorspace,
+        depth,
+    );
+    if let Some(effort) = effort {
+        // zune's effort is a 0-127 row sampling budget rather than libjxl's 1-9
+        encoder_options = encoder_options.set_effort(effort * 14);
+    }
+
+    let mut encoded = Vec::new();
+    zune_jpegxl::JxlSimpleEncoder::new(source.as_bytes(), encoder_options)
+        .encode(&mut encoded)
+        .map_err(|e| anyhow::anyhow!("Failed to encode JPEG XL: {e:?}"))?;
+    Ok(encoded)
+}
+
+/// Encode an image as lossy JPEG XL at the given Butteraugli distance.
+#[cfg(feature = "jxl-lossy")]
+fn encode_jxl_lossy(img: &DynamicImage, distance: f32, effort: Option<u8>) -> Result<Vec<u8>> {
+    use jxl_encoder::{LossyConfig, PixelLayout};
+
+    let mut config = LossyConfig::new(distance);
+    if let Some(effort) = effort {
+        config = config.with_effort(effort);
+    }
+
+    let converted;
+    let (pixels, layout) = match img {
+        DynamicImage::ImageLuma8(_) => (img.as_bytes(), PixelLayout::Gray8),
+        DynamicImage::ImageLumaA8(_) => (img.as_bytes(), PixelLayout::GrayAlpha8),
+        DynamicImage::ImageRgb8(_) => (img.as_bytes(), PixelLayout::Rgb8),
+        other => {
+            converted = other.to_rgba8();
+            (converted.as_raw().as_slice(), PixelLayout::Rgba8)
+        }
+    };
+
+    let encoded = config
+        .encode(pixels, img.width(), img.height(), layout)
+        .map_err(|e| anyhow::anyhow!("Failed to encode JPEG XL: {e:?}"))?;
+    Ok(encoded)
+}
+
+/// Lossy JPEG XL output is unavailable without the `jxl-lossy` feature.
+#[cfg(not(feature = "jxl-lossy"))]
+fn encode_jxl_lossy(_img: &DynamicImage, _distance: f32, _effort: Option<u8>) -> Result<Vec<u8>> {
+    anyhow::bail!(
+        "Lossy JPEG XL encoding requires the `jxl-lossy` feature; use lossless mode or quality 100"
+    )
 }
 
 /// Detect the image format from a file extension.
@@ -134,7 +854,8 @@ pub fn detect_format_from_extension(path: &Path) -> Option<ImageFormat> {
 ///
 /// * `input_path` - Path to the input image
 /// * `output_path` - Path where the converted image should be saved
-/// * `options` - Optional format-specific conversion options
+/// * `options` - Optional format-specific conversion options; the defaults if
+///   `None`, or the lossless [`ImageFormatOptions::jxl`] preset for JPEG XL output
 ///
 /// # Returns
 ///
@@ -153,9 +874,7 @@ pub async fn convert_image(
     output_path: &Path,
     options: Option<ImageFormatOptions>,
 ) -> Result<()> {
-    // Detect output format
-    let output_format = detect_format_from_extension(output_path)
-        .context("Could not determine output format from file extension")?;
+    let output_format = detect_output_format(output_path)?;
 
     info!(
         "Converting {} to {}",
@@ -163,11 +882,13 @@ pub async fn convert_image(
         output_path.display()
     );
 
-    // Read input image
-    let img = image::open(input_path).context("Failed to open input image")?;
-
-    // Get or create options
-    let options = options.unwrap_or_default();
+    // Decoding and encoding are CPU-bound, so keep them off the async executor
+    let options = options_or_default(options, output_format);
+    let input = input_path.to_path_buf();
+    let encoded =
+        tokio::task::spawn_blocking(move || transcode_image(&input, output_format, &options))
+            .await
+            .context("Image conversion task failed")??;
 
     // Ensure output directory exists
     if let Some(parent) = output_path.parent() {
@@ -176,158 +897,1520 @@ pub async fn convert_image(
             .context("Failed to create output directory")?;
     }
 
-    // Convert and save with format-specific options
-    match output_format {
-        ImageFormat::Jpeg => {
-            let mut output = std::fs::File::create(output_path)?;
-            let mut encoder = JpegEncoder::new_with_quality(&mut output, options.quality);
-            encoder
-                .encode(
-                    img.as_bytes(),
-                    img.width(),
-                    img.height(),
-                    img.color().into(),
-                )
-                .context("Failed to encode JPEG")?;
-        }
-        ImageFormat::Png => {
-            let mut output = std::fs::File::create(output_path)?;
-            let encoder = PngEncoder::new(&mut output);
-            encoder
-                .write_image(
-                    img.as_bytes(),
-                    img.width(),
-                    img.height(),
-                    img.color().into(),
-                )
-                .context("Failed to encode PNG")?;
-        }
-        ImageFormat::WebP => {
-            let mut output = std::fs::File::create(output_path)?;
-            let encoder = WebPEncoder::new_lossless(&mut output);
-
-            // Note: Image 0.25.5 only supports lossless WebP encoding
-            // If options.lossless is false, we'll log a warning that we're still using lossless
-            if !options.lossless {
-                warn!(
-                    "Lossy WebP encoding not supported by this version of the image crate. Using lossless encoding instead."
-                );
-            }
-
-            encoder
-                .encode(
-                    img.as_bytes(),
-                    img.width(),
-                    img.height(),
-                    img.color().into(),
-                )
-                .context("Failed to encode WebP")?;
-        }
-        _ => {
-            // Fallback for other formats
-            img.save(output_path)
-                .with_context(|| format!("Failed to save image as {output_format:?}"))?;
-        }
-    }
+    write_output(output_path, output_format, encoded).await?;
 
     info!("Successfully converted image to {}", output_path.display());
     Ok(())
 }
 
-/// Convert multiple images in a batch operation.
+/// Decode an image file and encode it in `output_format`; the blocking part of [`convert_image`].
+fn transcode_image(
+    input_path: &Path,
+    output_format: Option<ImageFormat>,
+    options: &ImageFormatOptions,
+) -> Result<Vec<u8>> {
+    let (img, metadata) = decode_for_output(input_path, output_format, options)?;
+    encode_image(img, metadata, output_format, options)
+}
+
+/// Decode an image file for encoding in `output_format`, applying its orientation
+/// when the options ask for it.
+fn decode_for_output(
+    input_path: &Path,
+    output_format: Option<ImageFormat>,
+    options: &ImageFormatOptions,
+) -> Result<(DynamicImage, ImageMetadata)> {
+    // Read input image, decoding JPEG XL by content
+    let decoded = decode_image_file(input_path).context("Failed to open input image")?;
+
+    let mut img = decoded.image;
+    let mut metadata = decoded.metadata;
+    // JPEG XL readers ignore the EXIF orientation, so a kept orientation is applied
+    let orient_jxl = output_format.is_none() && options.metadata.keeps(MetadataKind::Orientation);
+    if options.auto_orient || orient_jxl {
+        img.apply_orientation(decoded.orientation);
+        metadata.reset_orientation();
+    }
+    Ok((img, metadata))
+}
+
+/// Convert an image like [`convert_image`] and measure how much the encoding changed it.
+///
+/// After writing, the output is decoded again and compared with the decoded source
+/// (oriented as it was encoded) using [`compare_images`]. Any ICC conversion to
+/// sRGB counts as a change too.
 ///
 /// # Arguments
 ///
-/// * `input_paths` - List of input image paths
-/// * `output_dir` - Directory where converted images should be saved
-/// * `output_format` - Target format for conversion
-/// * `options` - Optional format-specific conversion options
+/// * `input_path` - Path to the input image
+/// * `output_path` - Path where the converted image should be saved
+/// * `options` - Optional format-specific conversion options; the defaults if
+///   `None`, or the lossless [`ImageFormatOptions::jxl`] preset for JPEG XL output
 ///
 /// # Returns
 ///
-/// Returns a `Result<()>` indicating success or failure
+/// Returns the quality metrics of the output against the source
 ///
 /// # Errors
 ///
-/// Returns an error if:
-/// * The output directory cannot be created
-/// * Any individual conversion fails
-pub async fn convert_images_batch(
-    input_paths: &[PathBuf],
-    output_dir: &Path,
-    output_format: ImageFormat,
+/// Returns an error if the conversion fails, or if the output cannot be decoded
+/// again (such as AVIF without the `avif-decode` feature)
+pub async fn convert_image_with_metrics(
+    input_path: &Path,
+    output_path: &Path,
     options: Option<ImageFormatOptions>,
-) -> Result<()> {
-    // Create output directory
-    tokio::fs::create_dir_all(output_dir)
-        .await
-        .context("Failed to create output directory")?;
+) -> Result<ImageMetrics> {
+    let output_format = detect_output_format(output_path)?;
 
-    // Process each image
-    let total = input_paths.len();
     info!(
-        "Converting batch of {total} images to {output_format:?}"
+        "Converting {} to {}",
+        input_path.display(),
+        output_path.display()
     );
 
-    for (i, input_path) in input_paths.iter().enumerate() {
-        // Generate output path with new extension
-        let file_name = input_path
-            .file_name()
-            .context("Invalid input path")?
-            .to_string_lossy();
-        let extension = match output_format {
-            ImageFormat::Jpeg => "jpg",
-            ImageFormat::Png => "png",
-            ImageFormat::WebP => "webp",
-            ImageFormat::Gif => "gif",
-            _ => "bin", // Fallback
-        };
-        let output_name = format!(
-            "{}.{}",
-            file_name.split('.').next().unwrap_or("image"),
-            extension
-        );
-        let output_path = output_dir.join(output_name);
-
-        debug!(
-            "[{}/{}] Converting {} to {}",
-            i + 1,
-            total,
-            input_path.display(),
-            output_path.display()
-        );
+    let options = options_or_default(options, output_format);
+    let input = input_path.to_path_buf();
+    let (reference, encoded) = tokio::task::spawn_blocking(move || {
+        let (img, metadata) = decode_for_output(&input, output_format, &options)?;
+        let encoded = encode_image(img.clone(), metadata, output_format, &options)?;
+        Ok::<_, anyhow::Error>((img, encoded))
+    })
+    .await
+    .context("Image conversion task failed")??;
 
-        // Perform conversion
-        convert_image(input_path, &output_path, options.clone())
+    if let Some(parent) = output_path.parent() {
+        tokio::fs::create_dir_all(parent)
             .await
-            .with_context(|| format!("Failed to convert {}", input_path.display()))?;
+            .context("Failed to create output directory")?;
     }
+    write_output(output_path, output_format, encoded).await?;
 
-    info!("Successfully converted {total} images");
-    Ok(())
+    let output = output_path.to_path_buf();
+    let metrics = tokio::task::spawn_blocking(move || {
+        let converted = open_image(&output).context("Failed to decode converted image")?;
+        compare_images(&reference, &converted)
+    })
+    .await
+    .context("Image comparison task failed")??;
+
+    info!(
+        "Converted image to {} (PSNR {:.2} dB, SSIM {:.4})",
+        output_path.display(),
+        metrics.psnr,
+        metrics.ssim
+    );
+    Ok(metrics)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use image::DynamicImage;
-    use tempfile::TempDir;
+/// Options for `convert_image_to_size`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetSizeOptions {
+    /// Largest acceptable output size in bytes
+    pub max_bytes: u64,
+    /// Lowest quality (1-100) the search may pick
+    pub min_quality: u8,
+    /// Shrink the image when the output is over budget even at `min_quality`
+    pub allow_downscale: bool,
+}
 
-    #[tokio::test]
-    async fn test_convert_png_to_jpeg() -> Result<()> {
-        let temp_dir = TempDir::new()?;
-        let input = temp_dir.path().join("test.png");
-        let output = temp_dir.path().join("test.jpg");
+impl TargetSizeOptions {
+    /// Create options for a budget of `max_bytes`, searching down to quality 1
+    /// without downscaling
+    #[must_use]
+    pub fn new(max_bytes: u64) -> Self {
+        Self {
+            max_bytes,
+            min_quality: 1,
+            allow_downscale: false,
+        }
+    }
 
-        // Create a test PNG image
-        let img = DynamicImage::new_rgb8(100, 100);
-        img.save(&input)?;
+    /// Set the lowest quality the search may pick (1-100)
+    #[must_use]
+    pub fn with_min_quality(mut self, min_quality: u8) -> Self {
+        self.min_quality = min_quality.clamp(1, 100);
+        self
+    }
 
-        // Convert to JPEG
-        convert_image(&input, &output, None).await?;
+    /// Set whether to downscale the image when no quality fits the budget
+    #[must_use]
+    pub fn with_downscale(mut self, allow_downscale: bool) -> Self {
+        self.allow_downscale = allow_downscale;
+        self
+    }
+}
 
-        // Verify the output exists and is a valid JPEG
-        assert!(output.exists());
+/// Outcome of `convert_image_to_size`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetSizeReport {
+    /// Quality the output was encoded at
+    pub quality: u8,
+    /// Size of the written output in bytes
+    pub size: u64,
+    /// Width of the output
+    pub width: u32,
+    /// Height of the output
+    pub height: u32,
+    /// Whether the image had to be downscaled to fit the budget
+    pub downscaled: bool,
+}
+
+/// Images are not downscaled below this many pixels on either side.
+const MIN_DOWNSCALE_SIDE: u32 = 16;
+
+/// Convert an image, picking the highest quality whose output fits a byte budget.
+///
+/// The quality is binary-searched between `target.min_quality` and the quality of
+/// `options` (the output format's preset if `None`), for JPEG, WebP, JPEG XL and
+/// AVIF output. WebP and JPEG XL are encoded lossy below quality 100; lossy JPEG XL
+/// needs the `jxl-lossy` feature. When even the minimum quality is too large and
+/// `target.allow_downscale` is set, the image is shrunk and searched again.
+///
+/// # Arguments
+///
+/// * `input_path` - Path to the input image
+/// * `output_path` - Path where the converted image should be saved
+/// * `options` - Optional format-specific conversion options
+/// * `target` - The byte budget and how far the search may go to meet it
+///
+/// # Returns
+///
+/// Returns the chosen quality, the final size and the output dimensions
+///
+/// # Errors
+///
+/// Returns an error if:
+/// * The output format has no quality setting
+/// * The output is JPEG XL and the `jxl-lossy` feature is disabled
+/// * The input cannot be decoded or an encode fails
+/// * No quality (and no allowed downscale) fits the budget
+/// * The output file cannot be written
+pub async fn convert_image_to_size(
+    input_path: &Path,
+    output_path: &Path,
+    options: Option<ImageFormatOptions>,
+    target: &TargetSizeOptions,
+) -> Result<TargetSizeReport> {
+    let output_format = detect_output_format(output_path)?;
+    if !matches!(
+        output_format,
+        None | Some(ImageFormat::Jpeg | ImageFormat::WebP | ImageFormat::Avif)
+    ) {
+        anyhow::bail!(
+            "Target file sizes need JPEG, WebP, JPEG XL or AVIF output, not {}",
+            output_format_name(output_format)
+        );
+    }
+    // Lossless JPEG XL has no quality to search
+    if output_format.is_none() && cfg!(not(feature = "jxl-lossy")) {
+        anyhow::bail!("Target file sizes for JPEG XL output need the `jxl-lossy` feature");
+    }
+
+    info!(
+        "Converting {} to {} within {} bytes",
+        input_path.display(),
+        output_path.display(),
+        target.max_bytes
+    );
+
+    let options = options.unwrap_or_else(|| default_options_for(output_format));
+    let input = input_path.to_path_buf();
+    let target = target.clone();
+    let (encoded, report) = tokio::task::spawn_blocking(move || {
+        let (img, metadata) = decode_for_output(&input, output_format, &options)?;
+        encode_to_size(img, &metadata, output_format, &options, &target)
+    })
+    .await
+    .context("Image conversion task failed")??;
+
+    if let Some(parent) = output_path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .context("Failed to create output directory")?;
+    }
+    write_output(output_path, output_format, encoded).await?;
+
+    info!(
+        "Wrote {} at quality {} ({} bytes)",
+        output_path.display(),
+        report.quality,
+        report.size
+    );
+    Ok(report)
+}
+
+/// Encode an image within `target`, downscaling it if that is allowed and needed.
+fn encode_to_size(
+    mut img: DynamicImage,
+    metadata: &ImageMetadata,
+    output_format: Option<ImageFormat>,
+    options: &ImageFormatOptions,
+    target: &TargetSizeOptions,
+) -> Result<(Vec<u8>, TargetSizeReport)> {
+    let min_quality = target.min_quality.clamp(1, 100);
+    let max_quality = options_quality(options).max(min_quality);
+    let original = (img.width(), img.height());
+
+    loop {
+        let smallest = match search_quality(
+            &img,
+            metadata,
+            output_format,
+            options,
+            target.max_bytes,
+            min_quality..=max_quality,
+        )? {
+            SizeSearch::Fits(quality, encoded) => {
+                let report = TargetSizeReport {
+                    quality,
+                    size: encoded.len() as u64,
+                    width: img.width(),
+                    height: img.height(),
+                    downscaled: (img.width(), img.height()) != original,
+                };
+                return Ok((encoded, report));
+            }
+            SizeSearch::TooLarge(smallest) => smallest,
+        };
+
+        let format = output_format_name(output_format);
+        if !target.allow_downscale {
+            anyhow::bail!(
+                "No quality down to {min_quality} fits the budget of {} bytes; the smallest {format} output was {smallest} bytes",
+                target.max_bytes
+            );
+        }
+        // The size grows roughly with the pixel count, so scale each side by the square root
+        #[allow(clippy::cast_precision_loss)]
+        let ratio = target.max_bytes as f32 / smallest as f32;
+        let scale = ratio.sqrt().clamp(0.5, 0.9);
+        #[allow(clippy::cast_precision_loss)]
+        let (width, height) = (
+            f32_to_u32(img.width() as f32 * scale),
+            f32_to_u32(img.height() as f32 * scale),
+        );
+        if width < MIN_DOWNSCALE_SIDE || height < MIN_DOWNSCALE_SIDE {
+            anyhow::bail!(
+                "No quality down to {min_quality} fits the budget of {} bytes at {}x{}, and the image cannot shrink below {MIN_DOWNSCALE_SIDE} pixels a side; the smallest {format} output was {smallest} bytes",
+                target.max_bytes,
+                img.width(),
+                img.height()
+            );
+        }
+        debug!(
+            "Downscaling to {width}x{height} to fit {} bytes",
+            target.max_bytes
+        );
+        img = img.resize_exact(width, height, image::imageops::FilterType::Lanczos3);
+    }
+}
+
+/// Result of [`search_quality`].
+enum SizeSearch {
+    /// The highest quality that fits, with its output
+    Fits(u8, Vec<u8>),
+    /// Nothing fits; the size at the lowest quality
+    TooLarge(u64),
+}
+
+/// Binary-search `qualities` for the highest one whose output is at most `max_bytes`.
+fn search_quality(
+    img: &DynamicImage,
+    metadata: &ImageMetadata,
+    output_format: Option<ImageFormat>,
+    options: &ImageFormatOptions,
+    max_bytes: u64,
+    qualities: std::ops::RangeInclusive<u8>,
+) -> Result<SizeSearch> {
+    let encode_at = |quality: u8| {
+        let options = options_with_quality(options, quality);
+        let encoded = encode_image(img.clone(), metadata.clone(), output_format, &options)?;
+        debug!("Quality {quality} encodes to {} bytes", encoded.len());
+        Ok::<_, anyhow::Error>(encoded)
+    };
+
+    // Many images already fit at the requested quality
+    let (mut low, mut high) = qualities.into_inner();
+    let encoded = encode_at(high)?;
+    if encoded.len() as u64 <= max_bytes {
+        return Ok(SizeSearch::Fits(high, encoded));
+    }
+
+    let mut best = None;
+    let mut smallest = encoded.len() as u64;
+    high -= 1;
+    while low <= high {
+        let quality = low + (high - low) / 2;
+        let encoded = encode_at(quality)?;
+        let size = encoded.len() as u64;
+        if size <= max_bytes {
+            best = Some((quality, encoded));
+            low = quality + 1;
+        } else {
+            smallest = smallest.min(size);
+            // `low` is at least 1, so this ends the loop instead of wrapping
+            high = quality - 1;
+        }
+    }
+    Ok(match best {
+        Some((quality, encoded)) => SizeSearch::Fits(quality, encoded),
+        None => SizeSearch::TooLarge(smallest),
+    })
+}
+
+/// The quality set in `options`, typed or not.
+fn options_quality(options: &ImageFormatOptions) -> u8 {
+    match &options.encoder {
+        Some(EncoderOptions::Jpeg(jpeg)) => jpeg.quality,
+        Some(EncoderOptions::Webp(webp)) => webp.quality,
+        Some(EncoderOptions::Jxl(jxl)) => jxl.quality,
+        Some(EncoderOptions::Avif(avif)) => avif.quality,
+        Some(EncoderOptions::Png(_)) | None => options.quality,
+    }
+}
+
+/// Copy of `options` encoding lossy at `quality`, typed or not.
+fn options_with_quality(options: &ImageFormatOptions, quality: u8) -> ImageFormatOptions {
+    let mut options = options.clone();
+    match &mut options.encoder {
+        Some(EncoderOptions::Jpeg(jpeg)) => jpeg.quality = quality,
+        Some(EncoderOptions::Webp(webp)) => {
+            webp.quality = quality;
+            webp.lossless = false;
+        }
+        Some(EncoderOptions::Jxl(jxl)) => {
+            jxl.quality = quality;
+            jxl.lossless = false;
+        }
+        Some(EncoderOptions::Avif(avif)) => avif.quality = quality,
+        Some(EncoderOptions::Png(_)) => {}
+        None => {
+            options.quality = quality;
+            options.lossless = false;
+        }
+    }
+    options
+}
+
+/// Detect the output format of a path for [`write_image`].
+///
+/// JPEG XL is not an `image` crate format and maps to `None`.
+pub(crate) fn detect_output_format(output_path: &Path) -> Result<Option<ImageFormat>> {
+    if is_jxl_file(output_path) {
+        return Ok(None);
+    }
+    detect_format_from_extension(output_path)
+        .map(Some)
+        .context("Could not determine output format from file extension")
+}
+
+/// Options for `convert_image` when it is given none.
+///
+/// These are [`ImageFormatOptions::default`], except for JPEG XL output, which
+/// gets the lossless [`ImageFormatOptions::jxl`] preset so it needs no cargo feature.
+fn options_or_default(
+    options: Option<ImageFormatOptions>,
+    output_format: Option<ImageFormat>,
+) -> ImageFormatOptions {
+    options.unwrap_or_else(|| match output_format {
+        None => ImageFormatOptions::jxl(),
+        Some(_) => ImageFormatOptions::default(),
+    })
+}
+
+/// Default conversion options for an output format from [`detect_output_format`].
+pub(crate) fn default_options_for(output_format: Option<ImageFormat>) -> ImageFormatOptions {
+    match output_format {
+        None => ImageFormatOptions::jxl(),
+        Some(ImageFormat::Jpeg) => ImageFormatOptions::jpeg(),
+        Some(ImageFormat::Png) => ImageFormatOptions::png(),
+        Some(ImageFormat::WebP) => ImageFormatOptions::webp(),
+        Some(ImageFormat::Avif) => ImageFormatOptions::avif(),
+        Some(_) => ImageFormatOptions::default(),
+    }
+}
+
+/// Decide what happens to the input's ICC profile before writing.
+///
+/// The profile is kept for JPEG, PNG and WebP output. The pixels are converted to
+/// sRGB instead when asked to, when the metadata policy drops the profile, or when
+/// the encoder of the output format cannot embed a profile (JPEG XL and AVIF),
+/// which is logged as a warning. Profiles that do not fit the pixels (such as the
+/// CMYK profile of a CMYK JPEG) are dropped.
+fn apply_icc_profile(
+    img: DynamicImage,
+    icc_profile: Option<Vec<u8>>,
+    output_format: Option<ImageFormat>,
+    options: &ImageFormatOptions,
+) -> Result<(DynamicImage, Option<Vec<u8>>)> {
+    let Some(profile) = icc_profile else {
+        return Ok((img, None));
+    };
+    if !icc_profile_fits(&profile, img.color()) {
+        warn!("Dropping ICC profile that does not describe the decoded pixels");
+        return Ok((img, None));
+    }
+
+    let embeddable = matches!(
+        output_format,
+        Some(ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP)
+    );
+    let keep = options.metadata.keeps(MetadataKind::IccProfile);
+    if embeddable && keep && !options.srgb_conversion {
+        return Ok((img, Some(profile)));
+    }
+    if !embeddable && keep && !options.srgb_conversion {
+        // The formats could carry the profile, so the conversion may not be expected
+        warn!(
+            "The {} encoder cannot embed ICC profiles, converting pixels to sRGB; \
+             enable sRGB conversion to do this on purpose",
+            output_format_name(output_format)
+        );
+    }
+    let converted = convert_to_srgb(&img, &profile).context("Failed to convert image to sRGB")?;
+    Ok((converted, None))
+}
+
+/// Encode an image in `output_format`.
+///
+/// The ICC profile is handled by [`apply_icc_profile`]; the other metadata the
+/// options' [`MetadataPolicy`] keeps is spliced into the encoded file. Encoding is
+/// CPU-bound, so async callers run this on the blocking thread pool.
+pub(crate) fn encode_image(
+    img: DynamicImage,
+    mut metadata: ImageMetadata,
+    output_format: Option<ImageFormat>,
+    options: &ImageFormatOptions,
+) -> Result<Vec<u8>> {
+    let (img, icc_profile) =
+        apply_icc_profile(img, metadata.icc_profile.take(), output_format, options)?;
+    let mut metadata = metadata.filter(&options.metadata);
+    metadata.icc_profile = icc_profile;
+
+    // Convert with format-specific options
+    match output_format {
+        None => {
+            let encoded = encode_jxl(&img, &JxlOptions::try_from(options)?)?;
+            embed_jxl_metadata(encoded, &metadata)
+        }
+        Some(ImageFormat::Jpeg) => encode_jpeg(&img, &metadata, &JpegOptions::try_from(options)?),
+        Some(ImageFormat::Png) => {
+            let png_options = PngOptions::try_from(options)?;
+            let encoded = encode_png(&img, metadata.icc_profile.take(), &png_options)?;
+            embed_png_metadata(encoded, &metadata)
+        }
+        Some(ImageFormat::WebP) => {
+            let encoded = encode_webp(&img, &WebpOptions::try_from(options)?)?;
+            embed_webp_metadata(&encoded, &metadata, &img)
+        }
+        Some(ImageFormat::Avif) => encode_avif(&img, &AvifOptions::try_from(options)?),
+        Some(other) => {
+            // Fallback for other formats
+            let format = format!("{other:?}");
+            if let Some(encoder) = options.typed_encoder_options()? {
+                return Err(mismatched_encoder_options(encoder, &format));
+            }
+            options.check_option_keys(&format, &[])?;
+            let mut encoded = Vec::new();
+            img.write_to(&mut std::io::Cursor::new(&mut encoded), other)
+                .with_context(|| format!("Failed to encode image as {other:?}"))?;
+            Ok(encoded)
+        }
+    }
+}
+
+/// Name of an output format from [`detect_output_format`], for messages.
+fn output_format_name(output_format: Option<ImageFormat>) -> String {
+    match output_format {
+        None => "JPEG XL".to_string(),
+        Some(ImageFormat::Jpeg) => "JPEG".to_string(),
+        Some(ImageFormat::Png) => "PNG".to_string(),
+        Some(ImageFormat::WebP) => "WebP".to_string(),
+        Some(ImageFormat::Avif) => "AVIF".to_string(),
+        Some(other) => format!("{other:?}"),
+    }
+}
+
+/// Encode an image in `output_format` and write it to `output_path`.
+///
+/// See [`encode_image`] for how the metadata is handled.
+pub(crate) async fn write_image(
+    img: DynamicImage,
+    metadata: ImageMetadata,
+    output_path: &Path,
+    output_format: Option<ImageFormat>,
+    options: &ImageFormatOptions,
+) -> Result<()> {
+    let encoded = encode_image(img, metadata, output_format, options)?;
+    write_output(output_path, output_format, encoded).await
+}
+
+/// Write encoded output to `output_path`.
+///
+/// The output is written to a hidden temporary file in the same directory and
+/// then renamed over `output_path`, so an interrupted write never leaves a
+/// truncated file that an incremental batch would take as up to date.
+pub(crate) async fn write_output(
+    output_path: &Path,
+    output_format: Option<ImageFormat>,
+    encoded: Vec<u8>,
+) -> Result<()> {
+    let format = output_format_name(output_format);
+    let file_name = output_path
+        .file_name()
+        .with_context(|| format!("Invalid output path: {}", output_path.display()))?;
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(".tmp");
+    let temp_path = output_path.with_file_name(temp_name);
+
+    let written = async {
+        tokio::fs::write(&temp_path, encoded)
+            .await
+            .with_context(|| format!("Failed to write {format} output"))?;
+        tokio::fs::rename(&temp_path, output_path)
+            .await
+            .with_context(|| format!("Failed to replace {}", output_path.display()))
+    }
+    .await;
+    if written.is_err() {
+        // Nothing useful is left in a temporary file that was not moved into place
+        let _ = tokio::fs::remove_file(&temp_path).await;
+    }
+    written
+}
+
+/// What a batch does when an output path is already taken, by an existing file
+/// or by an earlier image of the same batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputCollisionPolicy {
+    /// Fail the image
+    Error,
+    /// Replace the existing file; of images sharing an output, the last one wins (default)
+    #[default]
+    Overwrite,
+    /// Append `_1`, `_2`, ... to the file name until it is free
+    Suffix,
+    /// Leave the existing file alone and report the image as skipped
+    Skip,
+}
+
+/// How an incremental batch decides that an existing output is up to date.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IncrementalMode {
+    /// Convert every image (default)
+    #[default]
+    Off,
+    /// Skip images whose output was modified after the input
+    Mtime,
+    /// Skip images whose content hash matches the one recorded for their output
+    /// in the manifest of the output directory ([`BATCH_MANIFEST_NAME`])
+    ContentHash,
+}
+
+/// File name of the manifest an incremental batch keeps in its output directory.
+///
+/// It lists the outputs the batch wrote, with the content hash of their input
+/// (`-` when an [`IncrementalMode::Mtime`] batch did not hash it).
+pub const BATCH_MANIFEST_NAME: &str = ".imx-manifest";
+
+/// Options for `convert_images_batch_with_options`.
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    /// Maximum number of images converted at the same time
+    pub concurrency: usize,
+    /// Keep converting the remaining images after one fails
+    pub continue_on_error: bool,
+    /// Template of output file names, without the extension
+    pub name_template: String,
+    /// Directory whose tree is mirrored under the output directory
+    pub input_root: Option<PathBuf>,
+    /// What to do when an output path is already taken
+    pub collision_policy: OutputCollisionPolicy,
+    /// Whether up-to-date outputs are kept instead of converted again
+    pub incremental: IncrementalMode,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: std::thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            continue_on_error: false,
+            name_template: "{stem}".to_string(),
+            input_root: None,
+            collision_policy: OutputCollisionPolicy::default(),
+            incremental: IncrementalMode::default(),
+        }
+    }
+}
+
+impl BatchOptions {
+    /// Set the maximum number of images converted at the same time; at least 1
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Set whether the batch keeps going after a failed conversion
+    #[must_use]
+    pub fn with_continue_on_error(mut self, continue_on_error: bool) -> Self {
+        self.continue_on_error = continue_on_error;
+        self
+    }
+
+    /// Set the template of output file names
+    ///
+    /// The extension of the output format is appended to the rendered name, and
+    /// a `/` in the template creates subdirectories. Placeholders:
+    /// * `{stem}` - the input file name without its last extension (the default)
+    /// * `{parent}` - the name of the directory holding the input
+    /// * `{index}` - the position of the input in the batch, starting at 1
+    /// * `{format}` - the output format in lower case, such as `webp`
+    /// * `{hash}` - the first 16 hex digits of the SHA-256 of the input file
+    #[must_use]
+    pub fn with_name_template(mut self, template: &str) -> Self {
+        self.name_template = template.to_string();
+        self
+    }
+
+    /// Mirror the directory tree below `root` under the output directory
+    ///
+    /// Inputs outside `root` fail.
+    #[must_use]
+    pub fn with_input_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.input_root = Some(root.into());
+        self
+    }
+
+    /// Set the policy for output paths that are already taken
+    #[must_use]
+    pub fn with_collision_policy(mut self, policy: OutputCollisionPolicy) -> Self {
+        self.collision_policy = policy;
+        self
+    }
+
+    /// Set how up-to-date outputs are detected and skipped
+    ///
+    /// Outputs listed in the manifest of the output directory
+    /// ([`BATCH_MANIFEST_NAME`]) then count as earlier results of the batch and
+    /// are replaced when out of date; other existing files are still collisions.
+    /// Changing the conversion options does not make outputs out of date.
+    #[must_use]
+    pub fn with_incremental(mut self, incremental: IncrementalMode) -> Self {
+        self.incremental = incremental;
+        self
+    }
+}
+
+/// An image a batch converted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConvertedImage {
+    /// Path of the input image
+    pub input: PathBuf,
+    /// Path of the written output
+    pub output: PathBuf,
+    /// Size of the input file in bytes
+    pub input_bytes: u64,
+    /// Size of the output file in bytes
+    pub output_bytes: u64,
+    /// Time taken to convert the image
+    pub duration: Duration,
+}
+
+/// Why a batch did not convert an image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchSkipReason {
+    /// An earlier image failed and `BatchOptions::continue_on_error` was off
+    EarlierFailure,
+    /// The output path was taken and the collision policy is `Skip`
+    OutputExists(PathBuf),
+    /// An incremental batch found this output up to date
+    UpToDate(PathBuf),
+}
+
+/// An image a batch did not try to convert.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedImage {
+    /// Path of the input image
+    pub input: PathBuf,
+    /// Why the image was skipped
+    pub reason: BatchSkipReason,
+}
+
+/// An image whose conversion failed.
+#[derive(Debug)]
+pub struct FailedImage {
+    /// Path of the input image
+    pub input: PathBuf,
+    /// What went wrong
+    pub error: anyhow::Error,
+}
+
+/// Report of what `convert_images_batch_with_options` did, in input order.
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    /// Images that were converted
+    pub converted: Vec<ConvertedImage>,
+    /// Images that were not attempted
+    pub skipped: Vec<SkippedImage>,
+    /// Images whose conversion failed
+    pub failed: Vec<FailedImage>,
+}
+
+impl BatchReport {
+    /// Whether no conversion failed
+    #[must_use]
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    /// Inputs to retry in another batch: those that failed, and those skipped
+    /// because of an earlier failure
+    ///
+    /// Inputs skipped on purpose, because their output exists or is up to date,
+    /// are left out.
+    #[must_use]
+    pub fn retry_inputs(&self) -> Vec<PathBuf> {
+        let stopped = self
+            .skipped
+            .iter()
+            .filter(|skipped| skipped.reason == BatchSkipReason::EarlierFailure);
+        self.failed
+            .iter()
+            .map(|failed| &failed.input)
+            .chain(stopped.map(|skipped| &skipped.input))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Convert multiple images in a batch operation.
+///
+/// Images are converted in parallel, one per available CPU core; see
+/// [`convert_images_batch_with_options`] to choose the concurrency or get a
+/// per-image report.
+///
+/// # Arguments
+///
+/// * `input_paths` - List of input image paths
+/// * `output_dir` - Directory where converted images should be saved
+/// * `output_format` - Target format for conversion
+/// * `options` - Optional format-specific conversion options
+///
+/// # Returns
+///
+/// Returns a `Result<()>` indicating success or failure
+///
+/// # Errors
+///
+/// Returns an error if:
+/// * The output directory cannot be created
+/// * Any individual conversion fails
+pub async fn convert_images_batch(
+    input_paths: &[PathBuf],
+    output_dir: &Path,
+    output_format: ImageFormat,
+    options: Option<ImageFormatOptions>,
+) -> Result<()> {
+    let report = convert_images_batch_with_options(
+        input_paths,
+        output_dir,
+        output_format,
+        options,
+        &BatchOptions::default(),
+    )
+    .await?;
+    match report.failed.into_iter().next() {
+        Some(failed) => Err(failed.error),
+        None => Ok(()),
+    }
+}
+
+/// Convert multiple images in a batch operation with bounded parallelism,
+/// reporting the outcome of every image.
+///
+/// Output paths are chosen up front in input order: the name template of
+/// `batch_options` is rendered for each input, below the mirrored directory if
+/// an input root is set, and collisions with existing files or earlier inputs
+/// are resolved by the collision policy. So the outputs never depend on the
+/// order conversions finish in. Inputs that overwrite the same output are
+/// converted one after another in input order, so the last one wins.
+///
+/// An incremental batch (see [`BatchOptions::with_incremental`]) skips inputs
+/// whose output is up to date and records the converted outputs, with the
+/// content hashes of their inputs, in [`BATCH_MANIFEST_NAME`].
+///
+/// Up to `batch_options.concurrency` images are then decoded and encoded at once
+/// on tokio's blocking thread pool, and results are logged and reported in input
+/// order. Unless `batch_options.continue_on_error` is set, no further conversions
+/// are started after a failure and the remaining images are reported as skipped.
+///
+/// # Arguments
+///
+/// * `input_paths` - List of input image paths
+/// * `output_dir` - Directory where converted images should be saved
+/// * `output_format` - Target format for conversion
+/// * `options` - Optional format-specific conversion options
+/// * `batch_options` - How the batch is run and its outputs are named
+///
+/// # Returns
+///
+/// Returns a [`BatchReport`] listing the converted, skipped and failed images
+///
+/// # Errors
+///
+/// Returns an error if the output directory cannot be created, the name template
+/// is invalid, the output format has no known extension, the manifest of an
+/// incremental batch cannot be read or a conversion task panics; failed
+/// conversions are listed in the report instead
+pub async fn convert_images_batch_with_options(
+    input_paths: &[PathBuf],
+    output_dir: &Path,
+    output_format: ImageFormat,
+    options: Option<ImageFormatOptions>,
+    batch_options: &BatchOptions,
+) -> Result<BatchReport> {
+    // Create output directory
+    tokio::fs::create_dir_all(output_dir)
+        .await
+        .context("Failed to create output directory")?;
+
+    let total = input_paths.len();
+    let concurrency = batch_options.concurrency.max(1);
+    let continue_on_error = batch_options.continue_on_error;
+    info!("Converting batch of {total} images to {output_format:?}");
+    debug!("Converting up to {concurrency} images at a time");
+
+    let incremental = batch_options.incremental;
+    let mut manifest = if incremental == IncrementalMode::Off {
+        HashMap::new()
+    } else {
+        read_batch_manifest(output_dir).await?
+    };
+    let (plans, input_hashes): (Vec<_>, Vec<_>) = plan_batch_outputs(
+        input_paths,
+        output_dir,
+        output_format,
+        batch_options,
+        &manifest,
+    )
+    .await?
+    .into_iter()
+    .unzip();
+
+    let results =
+        run_batch_conversions(input_paths, plans, options, concurrency, continue_on_error).await?;
+
+    // Record what the converted outputs were made from for the next run
+    if incremental != IncrementalMode::Off {
+        for (outcome, input_hash) in results.iter().zip(input_hashes) {
+            if let Some(BatchOutcome::Converted(converted)) = outcome {
+                let hash = input_hash.unwrap_or_else(|| "-".to_string());
+                manifest.insert(converted.output.clone(), hash);
+            }
+        }
+        if let Err(e) = write_batch_manifest(output_dir, &manifest).await {
+            warn!("{e:#}; the next incremental run converts these images again");
+        }
+    }
+
+    let report = batch_report(input_paths, results);
+    info!(
+        "Converted {} of {total} images ({} failed, {} skipped)",
+        report.converted.len(),
+        report.failed.len(),
+        report.skipped.len()
+    );
+    Ok(report)
+}
+
+/// Run the conversions of a planned batch, up to `concurrency` at a time.
+///
+/// Returns the outcome of each input by index; inputs left without one were not
+/// started because an earlier one failed.
+async fn run_batch_conversions(
+    input_paths: &[PathBuf],
+    plans: Vec<BatchPlan>,
+    options: Option<ImageFormatOptions>,
+    concurrency: usize,
+    continue_on_error: bool,
+) -> Result<Vec<Option<BatchOutcome>>> {
+    // Inputs sharing an output path run in one task so they cannot write at the same time
+    let mut groups: HashMap<PathBuf, Vec<usize>> = HashMap::new();
+    for (i, plan) in plans.iter().enumerate() {
+        if let BatchPlan::Convert(output_path) = plan {
+            groups.entry(output_path.clone()).or_default().push(i);
+        }
+    }
+
+    let mut results: Vec<Option<BatchOutcome>> = (0..plans.len()).map(|_| None).collect();
+    let mut tasks = JoinSet::new();
+    // Whether any input has failed so far
+    let mut failed = false;
+    for (i, plan) in plans.into_iter().enumerate() {
+        if failed && !continue_on_error {
+            break;
+        }
+        let output_path = match plan {
+            BatchPlan::Convert(output_path) => output_path,
+            BatchPlan::Skip(reason) => {
+                results[i] = Some(BatchOutcome::Skipped(reason));
+                continue;
+            }
+            BatchPlan::Fail(e) => {
+                results[i] = Some(BatchOutcome::Failed(e));
+                failed = true;
+                continue;
+            }
+        };
+        // Later members of a group were started with its first member
+        let Some(group) = groups.remove(&output_path) else {
+            continue;
+        };
+
+        while tasks.len() >= concurrency {
+            if let Some(finished) = tasks.join_next().await {
+                failed |= store_batch_results(&mut results, finished)?;
+            }
+        }
+        if failed && !continue_on_error {
+            break;
+        }
+
+        let jobs: Vec<_> = group
+            .into_iter()
+            .map(|i| (i, input_paths[i].clone()))
+            .collect();
+        let options = options.clone();
+        tasks.spawn(async move {
+            let mut finished = Vec::with_capacity(jobs.len());
+            for (i, input_path) in jobs {
+                let result =
+                    convert_batch_image(input_path, output_path.clone(), options.clone()).await;
+                let failed = result.is_err();
+                finished.push((i, result));
+                if failed && !continue_on_error {
+                    break;
+                }
+            }
+            finished
+        });
+    }
+    while let Some(finished) = tasks.join_next().await {
+        store_batch_results(&mut results, finished)?;
+    }
+    Ok(results)
+}
+
+/// Build the report of a batch from the outcome of each input, logging them in input order.
+///
+/// Images without an outcome were not started because an earlier one failed.
+fn batch_report(input_paths: &[PathBuf], results: Vec<Option<BatchOutcome>>) -> BatchReport {
+    let mut report = BatchReport::default();
+    for (i, outcome) in results.into_iter().enumerate() {
+        let input_path = &input_paths[i];
+        match outcome {
+            Some(BatchOutcome::Converted(converted)) => {
+                debug!(
+                    "[{}/{}] Converted {} to {}",
+                    i + 1,
+                    input_paths.len(),
+                    input_path.display(),
+                    converted.output.display()
+                );
+                report.converted.push(converted);
+            }
+            Some(BatchOutcome::Failed(e)) => {
+                let error = e.context(format!("Failed to convert {}", input_path.display()));
+                warn!("[{}/{}] {error:#}", i + 1, input_paths.len());
+                report.failed.push(FailedImage {
+                    input: input_path.clone(),
+                    error,
+                });
+            }
+            outcome => {
+                let reason = match outcome {
+                    Some(BatchOutcome::Skipped(reason)) => reason,
+                    _ => BatchSkipReason::EarlierFailure,
+                };
+                debug!(
+                    "[{}/{}] Skipped {}: {reason:?}",
+                    i + 1,
+                    input_paths.len(),
+                    input_path.display()
+                );
+                report.skipped.push(SkippedImage {
+                    input: input_path.clone(),
+                    reason,
+                });
+            }
+        }
+    }
+
+    report
+}
+
+/// What happened to one image of a batch.
+enum BatchOutcome {
+    Converted(ConvertedImage),
+    Skipped(BatchSkipReason),
+    Failed(anyhow::Error),
+}
+
+/// What a batch will do with one image, decided before any conversion starts.
+enum BatchPlan {
+    Convert(PathBuf),
+    Skip(BatchSkipReason),
+    Fail(anyhow::Error),
+}
+
+/// Convert one image of a batch, measuring its sizes and duration.
+async fn convert_batch_image(
+    input_path: PathBuf,
+    output_path: PathBuf,
+    options: Option<ImageFormatOptions>,
+) -> Result<ConvertedImage> {
+    let start = Instant::now();
+    let input_bytes = tokio::fs::metadata(&input_path)
+        .await
+        .context("Failed to read input file")?
+        .len();
+    convert_image(&input_path, &output_path, options).await?;
+    let output_bytes = tokio::fs::metadata(&output_path)
+        .await
+        .context("Failed to read output file")?
+        .len();
+    Ok(ConvertedImage {
+        input: input_path,
+        output: output_path,
+        input_bytes,
+        output_bytes,
+        duration: start.elapsed(),
+    })
+}
+
+/// Record the results of a finished batch task by input index.
+///
+/// Returns whether any of the conversions failed.
+fn store_batch_results(
+    results: &mut [Option<BatchOutcome>],
+    finished: std::result::Result<Vec<(usize, Result<ConvertedImage>)>, tokio::task::JoinError>,
+) -> Result<bool> {
+    let mut failed = false;
+    for (i, result) in finished.context("Batch conversion task panicked")? {
+        results[i] = Some(match result {
+            Ok(converted) => BatchOutcome::Converted(converted),
+            Err(e) => {
+                failed = true;
+                BatchOutcome::Failed(e)
+            }
+        });
+    }
+    Ok(failed)
+}
+
+/// File extension of an output format, without the dot.
+///
+/// Every extension maps back to its format in [`detect_format_from_extension`].
+///
+/// # Arguments
+///
+/// * `format` - The output format
+///
+/// # Returns
+///
+/// Returns the usual extension of the format, or `None` for formats the `image`
+/// crate knows no extension of
+#[must_use]
+pub fn format_extension(format: ImageFormat) -> Option<&'static str> {
+    match format {
+        ImageFormat::Png => Some("png"),
+        ImageFormat::Jpeg => Some("jpg"),
+        ImageFormat::Gif => Some("gif"),
+        ImageFormat::WebP => Some("webp"),
+        ImageFormat::Pnm => Some("ppm"),
+        ImageFormat::Tiff => Some("tiff"),
+        ImageFormat::Tga => Some("tga"),
+        ImageFormat::Dds => Some("dds"),
+        ImageFormat::Bmp => Some("bmp"),
+        ImageFormat::Ico => Some("ico"),
+        ImageFormat::Hdr => Some("hdr"),
+        ImageFormat::OpenExr => Some("exr"),
+        ImageFormat::Farbfeld => Some("ff"),
+        ImageFormat::Avif => Some("avif"),
+        ImageFormat::Qoi => Some("qoi"),
+        other => other.extensions_str().first().copied(),
+    }
+}
+
+/// Placeholders of batch output name templates.
+const NAME_PLACEHOLDERS: &[&str] = &["stem", "parent", "index", "format", "hash"];
+
+/// Render a batch output name template, looking up placeholders with `value`.
+fn render_name_template(template: &str, value: impl Fn(&str) -> String) -> Result<String> {
+    let mut name = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        name.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .with_context(|| format!("Unclosed placeholder in name template {template:?}"))?
+            + start;
+        let key = &rest[start + 1..end];
+        if !NAME_PLACEHOLDERS.contains(&key) {
+            anyhow::bail!(
+                "Unknown placeholder {{{key}}} in name template {template:?}, supported placeholders are {}",
+                NAME_PLACEHOLDERS
+                    .iter()
+                    .map(|key| format!("{{{key}}}"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        name.push_str(&value(key));
+        rest = &rest[end + 1..];
+    }
+    name.push_str(rest);
+    Ok(name)
+}
+
+/// Hex SHA-256 of file contents.
+pub(crate) fn content_hash(data: &[u8]) -> String {
+    use std::fmt::Write;
+
+    Sha256::digest(data)
+        .iter()
+        .fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+/// Read a file and return its [`content_hash`], off the async executor.
+async fn hash_file(path: PathBuf) -> Result<String> {
+    tokio::task::spawn_blocking(move || std::fs::read(path).map(|data| content_hash(&data)))
+        .await
+        .context("Hashing task failed")?
+        .context("Failed to read input file")
+}
+
+/// Hash the inputs of a batch, up to `concurrency` at a time.
+///
+/// Returns the hash of each input by index, or why it could not be read.
+async fn hash_batch_inputs(
+    input_paths: &[PathBuf],
+    concurrency: usize,
+) -> Result<Vec<Option<Result<String>>>> {
+    let mut hashes: Vec<Option<Result<String>>> = (0..input_paths.len()).map(|_| None).collect();
+    let mut tasks = JoinSet::new();
+    for (i, input_path) in input_paths.iter().enumerate() {
+        while tasks.len() >= concurrency {
+            if let Some(finished) = tasks.join_next().await {
+                let (j, hash) = finished.context("Hashing task panicked")?;
+                hashes[j] = Some(hash);
+            }
+        }
+        let input_path = input_path.clone();
+        tasks.spawn(async move { (i, hash_file(input_path).await) });
+    }
+    while let Some(finished) = tasks.join_next().await {
+        let (j, hash) = finished.context("Hashing task panicked")?;
+        hashes[j] = Some(hash);
+    }
+    Ok(hashes)
+}
+
+/// Read the manifest of an incremental batch, mapping output paths to the content
+/// hash of the input they were converted from. A missing manifest is empty.
+async fn read_batch_manifest(output_dir: &Path) -> Result<HashMap<PathBuf, String>> {
+    let path = output_dir.join(BATCH_MANIFEST_NAME);
+    let text = match tokio::fs::read_to_string(&path).await {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to read {}", path.display()));
+        }
+    };
+    let mut manifest = HashMap::new();
+    for line in text.lines().filter(|line| !line.is_empty()) {
+        match line.split_once('\t') {
+            Some((hash, output)) => {
+                manifest.insert(output_dir.join(output), hash.to_string());
+            }
+            None => warn!("Ignoring malformed line in {}: {line:?}", path.display()),
+        }
+    }
+    Ok(manifest)
+}
+
+/// Write the manifest of an incremental batch, replacing the previous one.
+///
+/// Lines are `hash<TAB>output`, with outputs relative to `output_dir` and sorted.
+async fn write_batch_manifest(
+    output_dir: &Path,
+    manifest: &HashMap<PathBuf, String>,
+) -> Result<()> {
+    let mut lines: Vec<_> = manifest
+        .iter()
+        .filter_map(|(output, hash)| {
+            let relative = output.strip_prefix(output_dir).ok()?;
+            Some(format!("{hash}\t{}\n", relative.to_string_lossy()))
+        })
+        .collect();
+    lines.sort();
+
+    // Write to a temporary file first so an interrupted run keeps the old manifest
+    let path = output_dir.join(BATCH_MANIFEST_NAME);
+    let temp_path = output_dir.join(format!("{BATCH_MANIFEST_NAME}.tmp"));
+    tokio::fs::write(&temp_path, lines.concat())
+        .await
+        .with_context(|| format!("Failed to write {}", temp_path.display()))?;
+    tokio::fs::rename(&temp_path, &path)
+        .await
+        .with_context(|| format!("Failed to replace {}", path.display()))
+}
+
+/// Choose the output path of every batch input, resolving collisions in input order.
+///
+/// Returns the plan of each input, with the content hash of the input when it was
+/// needed for the name template or an incremental check.
+async fn plan_batch_outputs(
+    input_paths: &[PathBuf],
+    output_dir: &Path,
+    output_format: ImageFormat,
+    batch_options: &BatchOptions,
+    manifest: &HashMap<PathBuf, String>,
+) -> Result<Vec<(BatchPlan, Option<String>)>> {
+    let extension = format_extension(output_format)
+        .with_context(|| format!("No file extension is known for {output_format:?}"))?;
+    render_name_template(&batch_options.name_template, |_| String::new())?;
+
+    let mut planner = BatchPlanner {
+        input_paths,
+        output_dir,
+        output_format,
+        extension,
+        options: batch_options,
+        manifest,
+        taken: HashMap::new(),
+    };
+
+    // Hashing reads every input, so it runs as concurrently as the conversions
+    let needs_hash = batch_options.incremental == IncrementalMode::ContentHash
+        || batch_options.name_template.contains("{hash}");
+    let mut hashes = if needs_hash {
+        hash_batch_inputs(input_paths, batch_options.concurrency.max(1)).await?
+    } else {
+        Vec::new()
+    };
+
+    let mut plans = Vec::with_capacity(input_paths.len());
+    for i in 0..input_paths.len() {
+        let plan = match hashes.get_mut(i).and_then(Option::take).transpose() {
+            Ok(input_hash) => planner.plan(i, input_hash).await,
+            Err(e) => Err(e),
+        };
+        plans.push(plan.unwrap_or_else(|e| (BatchPlan::Fail(e), None)));
+    }
+    Ok(plans)
+}
+
+/// Chooses the output paths of a batch, one input at a time in input order.
+struct BatchPlanner<'a> {
+    input_paths: &'a [PathBuf],
+    output_dir: &'a Path,
+    output_format: ImageFormat,
+    extension: &'static str,
+    options: &'a BatchOptions,
+    manifest: &'a HashMap<PathBuf, String>,
+    /// Output paths claimed so far, with the input claiming them
+    taken: HashMap<PathBuf, usize>,
+}
+
+impl BatchPlanner<'_> {
+    /// Plan input `i` with its content hash, if one is needed; errors fail only that input.
+    async fn plan(
+        &mut self,
+        i: usize,
+        input_hash: Option<String>,
+    ) -> Result<(BatchPlan, Option<String>)> {
+        let input_path = &self.input_paths[i];
+        let output_path = self.output_path(i, input_hash.as_deref())?;
+
+        let plan = if self
+            .is_up_to_date(input_path, &output_path, input_hash.as_deref())
+            .await
+        {
+            BatchPlan::Skip(BatchSkipReason::UpToDate(output_path.clone()))
+        } else {
+            self.resolve_collision(i, output_path).await?
+        };
+        match &plan {
+            BatchPlan::Convert(output_path)
+            | BatchPlan::Skip(BatchSkipReason::UpToDate(output_path)) => {
+                self.taken.insert(output_path.clone(), i);
+            }
+            _ => {}
+        }
+        Ok((plan, input_hash))
+    }
+
+    /// Build the output path of input `i` from the name template, below the
+    /// mirrored directory of the input if an input root is set.
+    fn output_path(&self, i: usize, input_hash: Option<&str>) -> Result<PathBuf> {
+        let input_path = &self.input_paths[i];
+        let stem = input_path
+            .file_stem()
+            .context("Input path has no file name")?
+            .to_string_lossy();
+        let parent = input_path.parent().unwrap_or(Path::new(""));
+
+        let mut dir = self.output_dir.to_path_buf();
+        if let Some(root) = &self.options.input_root {
+            let relative = parent.strip_prefix(root).with_context(|| {
+                format!("Input is not inside the input root {}", root.display())
+            })?;
+            dir.push(relative);
+        }
+
+        let name = render_name_template(&self.options.name_template, |key| match key {
+            "stem" => stem.to_string(),
+            "parent" => parent
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            "index" => (i + 1).to_string(),
+            "format" => format!("{:?}", self.output_format).to_lowercase(),
+            _ => input_hash
+                .map(|hash| hash[..16].to_string())
+                .unwrap_or_default(),
+        })?;
+
+        // The name must stay below the output directory
+        let relative = Path::new(&name);
+        if name.is_empty()
+            || !relative
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+        {
+            anyhow::bail!("Name template renders {name:?}, which is not a relative file name");
+        }
+        Ok(dir.join(format!("{name}.{}", self.extension)))
+    }
+
+    /// Whether an incremental batch can keep the existing output of an input.
+    async fn is_up_to_date(
+        &self,
+        input_path: &Path,
+        output_path: &Path,
+        input_hash: Option<&str>,
+    ) -> bool {
+        match self.options.incremental {
+            IncrementalMode::Off => false,
+            IncrementalMode::Mtime => {
+                let modified = |path: &Path| {
+                    let path = path.to_path_buf();
+                    async move { tokio::fs::metadata(path).await?.modified() }
+                };
+                match (modified(input_path).await, modified(output_path).await) {
+                    (Ok(input), Ok(output)) => output >= input,
+                    _ => false,
+                }
+            }
+            IncrementalMode::ContentHash => {
+                self.manifest.get(output_path).map(String::as_str) == input_hash
+                    && tokio::fs::try_exists(output_path).await.unwrap_or(false)
+            }
+        }
+    }
+
+    /// Whether `path` is taken by a file from before the batch.
+    ///
+    /// Incremental batches treat the outputs listed in their manifest as their
+    /// own earlier results and replace them when they are out of date; any other
+    /// file is taken.
+    async fn exists(&self, path: &Path) -> bool {
+        !self.manifest.contains_key(path) && tokio::fs::try_exists(path).await.unwrap_or(false)
+    }
+
+    /// Apply the collision policy to the output path of input `i`.
+    async fn resolve_collision(&self, i: usize, output_path: PathBuf) -> Result<BatchPlan> {
+        let claimed_by = self.taken.get(&output_path).copied();
+        if claimed_by.is_none() && !self.exists(&output_path).await {
+            return Ok(BatchPlan::Convert(output_path));
+        }
+        match self.options.collision_policy {
+            OutputCollisionPolicy::Overwrite => {
+                if let Some(j) = claimed_by {
+                    warn!(
+                        "{} and {} are both converted to {}; the later one wins",
+                        self.input_paths[j].display(),
+                        self.input_paths[i].display(),
+                        output_path.display()
+                    );
+                }
+                Ok(BatchPlan::Convert(output_path))
+            }
+            OutputCollisionPolicy::Error => match claimed_by {
+                Some(j) => Err(anyhow::anyhow!(
+                    "Output {} is also the output of {}",
+                    output_path.display(),
+                    self.input_paths[j].display()
+                )),
+                None => Err(anyhow::anyhow!(
+                    "Output {} already exists",
+                    output_path.display()
+                )),
+            },
+            OutputCollisionPolicy::Skip => {
+                Ok(BatchPlan::Skip(BatchSkipReason::OutputExists(output_path)))
+            }
+            OutputCollisionPolicy::Suffix => {
+                let stem = output_path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let mut n = 1;
+                loop {
+                    let candidate =
+                        output_path.with_file_name(format!("{stem}_{n}.{}", self.extension));
+                    // The manifest cannot tell which input a suffixed name was for,
+                    // so any file on disk rules it out
+                    if !self.taken.contains_key(&candidate)
+                        && !tokio::fs::try_exists(&candidate).await.unwrap_or(false)
+                    {
+                        return Ok(BatchPlan::Convert(candidate));
+                    }
+                    n += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_processing::open_image;
+    use image::{DynamicImage, GenericImageView};
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_convert_png_to_jpeg() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input = temp_dir.path().join("test.png");
+        let output = temp_dir.path().join("test.jpg");
+
+        // Create a test PNG image
+        let img = DynamicImage::new_rgb8(100, 100);
+        img.save(&input)?;
+
+        // Convert to JPEG
+        convert_image(&input, &output, None).await?;
+
+        // Verify the output exists and is a valid JPEG
+        assert!(output.exists());
         let format = detect_format_from_extension(&output);
         assert_eq!(format, Some(ImageFormat::Jpeg));
 
@@ -335,26 +2418,1222 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_convert_with_options() -> Result<()> {
+    async fn test_convert_with_options() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input = temp_dir.path().join("test.png");
+        let output = temp_dir.path().join("test.webp");
+
+        // Create a test PNG image
+        let img = DynamicImage::new_rgb8(100, 100);
+        img.save(&input)?;
+
+        // Convert to WebP with custom options
+        let options = ImageFormatOptions::webp()
+            .with_quality(85)
+            .with_lossless(true);
+        convert_image(&input, &output, Some(options)).await?;
+
+        // Verify the output exists and is a valid WebP
+        assert!(output.exists());
+        let format = detect_format_from_extension(&output);
+        assert_eq!(format, Some(ImageFormat::WebP));
+
+        Ok(())
+    }
+
+    /// Build a noisy gradient image so lossy and lossless output differ
+    #[allow(clippy::cast_possible_truncation)]
+    fn gradient_image(alpha: bool) -> DynamicImage {
+        let img = image::RgbaImage::from_fn(128, 128, |x, y| {
+            // Integer hash of the pixel index gives reproducible noise
+            let mut h = (y * 128 + x).wrapping_mul(0x9E37_79B9);
+            h ^= h >> 15;
+            h = h.wrapping_mul(0x85EB_CA6B);
+            let noise = (h >> 27) % 32;
+            image::Rgba([
+                (x * 2 / 3 + noise) as u8,
+                (y * 2 / 3 + noise) as u8,
+                ((x + y) / 3 + noise) as u8,
+                if alpha { (x + y) as u8 } else { 255 },
+            ])
+        });
+        if alpha {
+            DynamicImage::ImageRgba8(img)
+        } else {
+            DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(img).to_rgb8())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_webp_lossy_smaller_than_lossless() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input = temp_dir.path().join("test.png");
+        let lossy = temp_dir.path().join("lossy.webp");
+        let lossless = temp_dir.path().join("lossless.webp");
+        gradient_image(false).save(&input)?;
+
+        convert_image(&input, &lossy, Some(ImageFormatOptions::webp())).await?;
+        convert_image(
+            &input,
+            &lossless,
+            Some(ImageFormatOptions::webp().with_lossless(true)),
+        )
+        .await?;
+
+        let lossy_size = std::fs::metadata(&lossy)?.len();
+        let lossless_size = std::fs::metadata(&lossless)?.len();
+        assert!(
+            lossy_size < lossless_size,
+            "lossy {lossy_size} should be smaller than lossless {lossless_size}"
+        );
+
+        // Lossless output must round-trip exactly
+        let original = image::open(&input)?.to_rgb8();
+        let decoded = image::open(&lossless)?.to_rgb8();
+        assert_eq!(original, decoded);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_webp_quality_and_alpha_options() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input = temp_dir.path().join("test.png");
+        gradient_image(true).save(&input)?;
+
+        let high = temp_dir.path().join("high.webp");
+        let low = temp_dir.path().join("low.webp");
+        let options = ImageFormatOptions::webp()
+            .with_option("method", "6")
+            .with_option("alpha_quality", "100");
+        convert_image(&input, &high, Some(options.clone().with_quality(95))).await?;
+        convert_image(
+            &input,
+            &low,
+            Some(options.with_quality(10).with_option("alpha_quality", "10")),
+        )
+        .await?;
+
+        assert!(std::fs::metadata(&low)?.len() < std::fs::metadata(&high)?.len());
+        let decoded = image::open(&low)?;
+        assert!(decoded.color().has_alpha());
+        assert_eq!(decoded.width(), 128);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_webp_rejects_invalid_options() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input = temp_dir.path().join("test.png");
+        let output = temp_dir.path().join("test.webp");
+        DynamicImage::new_rgb8(16, 16).save(&input)?;
+
+        let options = ImageFormatOptions::webp().with_option("method", "9");
+        assert!(convert_image(&input, &output, Some(options)).await.is_err());
+        let options = ImageFormatOptions::webp().with_option("alpha_quality", "high");
+        assert!(convert_image(&input, &output, Some(options)).await.is_err());
+
+        Ok(())
+    }
+
+    /// Convert `input` to `name` in the temporary directory, returning the output size.
+    async fn converted_size(
+        input: &Path,
+        temp_dir: &TempDir,
+        name: &str,
+        options: ImageFormatOptions,
+    ) -> Result<u64> {
+        let output = temp_dir.path().join(name);
+        convert_image(input, &output, Some(options)).await?;
+        Ok(std::fs::metadata(&output)?.len())
+    }
+
+    #[tokio::test]
+    async fn test_png_options_change_output() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input = temp_dir.path().join("test.png");
+        gradient_image(true).save(&input)?;
+
+        let png = ImageFormatOptions::png;
+        let fast = converted_size(&input, &temp_dir, "fast.png", png()).await?;
+        let best = converted_size(
+            &input,
+            &temp_dir,
+            "best.png",
+            png().with_option("compression", "best"),
+        )
+        .await?;
+        assert!(
+            best < fast,
+            "best {best} should be smaller than fast {fast}"
+        );
+
+        let unfiltered = converted_size(
+            &input,
+            &temp_dir,
+            "none.png",
+            png().with_option("filter", "None"),
+        )
+        .await?;
+        let paeth = converted_size(
+            &input,
+            &temp_dir,
+            "paeth.png",
+            png().with_option("filter", "paeth"),
+        )
+        .await?;
+        assert_ne!(unfiltered, paeth);
+
+        // PNG stays lossless whatever the settings
+        let original = image::open(&input)?.to_rgba8();
+        for name in ["best.png", "none.png", "paeth.png"] {
+            assert_eq!(
+                image::open(temp_dir.path().join(name))?.to_rgba8(),
+                original
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_jpeg_options_change_output() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input = temp_dir.path().join("test.png");
+        gradient_image(false).save(&input)?;
+        let jpeg = || ImageFormatOptions::jpeg().with_quality(95);
+
+        let full = converted_size(
+            &input,
+            &temp_dir,
+            "444.jpg",
+            jpeg().with_option("subsampling", "4:4:4"),
+        )
+        .await?;
+        let subsampled = converted_size(
+            &input,
+            &temp_dir,
+            "420.jpg",
+            jpeg().with_option("subsampling", "420"),
+        )
+        .await?;
+        assert!(
+            subsampled < full,
+            "4:2:0 {subsampled} should be smaller than 4:4:4 {full}"
+        );
+
+        let optimized = converted_size(
+            &input,
+            &temp_dir,
+            "optimized.jpg",
+            jpeg()
+                .with_option("subsampling", "444")
+                .with_option("optimize_huffman", "true"),
+        )
+        .await?;
+        assert!(
+            optimized < full,
+            "optimized {optimized} should be smaller than {full}"
+        );
+
+        // Progressive files use the SOF2 frame marker instead of baseline SOF0
+        let progressive = temp_dir.path().join("progressive.jpg");
+        let options = jpeg().with_option("progressive", "true");
+        convert_image(&input, &progressive, Some(options)).await?;
+        let data = std::fs::read(&progressive)?;
+        assert!(data.windows(2).any(|marker| marker == [0xFF, 0xC2]));
+        let baseline = std::fs::read(temp_dir.path().join("444.jpg"))?;
+        assert!(!baseline.windows(2).any(|marker| marker == [0xFF, 0xC2]));
+        assert_eq!(image::open(&progressive)?.width(), 128);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_webp_method_changes_output() -> Result<()> {
         let temp_dir = TempDir::new()?;
         let input = temp_dir.path().join("test.png");
-        let output = temp_dir.path().join("test.webp");
+        gradient_image(false).save(&input)?;
 
-        // Create a test PNG image
-        let img = DynamicImage::new_rgb8(100, 100);
+        let webp = || ImageFormatOptions::webp().with_lossless(true);
+        let fastest = converted_size(
+            &input,
+            &temp_dir,
+            "m0.webp",
+            webp().with_option("method", "0"),
+        )
+        .await?;
+        let slowest = converted_size(
+            &input,
+            &temp_dir,
+            "m6.webp",
+            webp().with_option("method", "6"),
+        )
+        .await?;
+        assert!(
+            slowest < fastest,
+            "method 6 {slowest} should beat method 0 {fastest}"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rejects_unknown_options() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input = temp_dir.path().join("test.png");
+        DynamicImage::new_rgb8(16, 16).save(&input)?;
+
+        // Typos, and options of another format, are errors
+        let cases = [
+            (
+                "out.jpg",
+                ImageFormatOptions::jpeg().with_option("progresive", "true"),
+            ),
+            (
+                "out.png",
+                ImageFormatOptions::png().with_option("effort", "5"),
+            ),
+            (
+                "out.webp",
+                ImageFormatOptions::webp().with_option("subsampling", "420"),
+            ),
+            (
+                "out.jxl",
+                ImageFormatOptions::jxl().with_option("method", "4"),
+            ),
+            (
+                "out.bmp",
+                ImageFormatOptions::default().with_option("quality", "5"),
+            ),
+        ];
+        for (name, options) in cases {
+            let result = convert_image(&input, &temp_dir.path().join(name), Some(options)).await;
+            assert!(result.is_err(), "{name} accepted an unknown option");
+        }
+
+        // Known keys with bad values are errors too
+        let options = ImageFormatOptions::png().with_option("compression", "max");
+        let output = temp_dir.path().join("bad.png");
+        let error = convert_image(&input, &output, Some(options))
+            .await
+            .unwrap_err();
+        assert!(format!("{error:#}").contains("fast, default, best"));
+        let options = ImageFormatOptions::jpeg().with_option("progressive", "yes");
+        let output = temp_dir.path().join("bad.jpg");
+        assert!(convert_image(&input, &output, Some(options)).await.is_err());
+        Ok(())
+    }
+
+    /// Decode a JPEG XL file with jxl-oxide's `image` integration
+    fn decode_jxl(path: &Path) -> Result<DynamicImage> {
+        let decoder = jxl_oxide::integration::JxlDecoder::new(std::fs::File::open(path)?)?;
+        Ok(DynamicImage::from_decoder(decoder)?)
+    }
+
+    #[test]
+    fn test_jxl_distance_for_quality() {
+        assert!(jxl_distance_for_quality(100).abs() < f32::EPSILON);
+        assert!((jxl_distance_for_quality(90) - 1.0).abs() < 1e-5);
+        assert!((jxl_distance_for_quality(30) - 6.4).abs() < 1e-4);
+        assert!((jxl_distance_for_quality(0) - 25.0).abs() < 1e-4);
+        // Lower quality always means a larger distance
+        for q in 0..100 {
+            assert!(jxl_distance_for_quality(q) > jxl_distance_for_quality(q + 1));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_jxl_lossless_round_trip() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+
+        for (name, img) in [
+            ("rgb", gradient_image(false)),
+            ("rgba", gradient_image(true)),
+            (
+                "gray",
+                DynamicImage::ImageLuma8(gradient_image(false).to_luma8()),
+            ),
+            (
+                "rgb16",
+                DynamicImage::ImageRgb16(gradient_image(false).to_rgb16()),
+            ),
+        ] {
+            let input = temp_dir.path().join(format!("{name}.png"));
+            let output = temp_dir.path().join(format!("{name}.jxl"));
+            img.save(&input)?;
+
+            convert_image(&input, &output, Some(ImageFormatOptions::jxl())).await?;
+
+            let decoded = decode_jxl(&output)?;
+            assert_eq!(decoded.dimensions(), img.dimensions(), "{name}");
+            assert_eq!(decoded.color(), img.color(), "{name}");
+            assert_eq!(decoded.as_bytes(), img.as_bytes(), "{name}");
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_jxl_output_without_options_is_lossless() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input = temp_dir.path().join("input.png");
+        let img = gradient_image(true);
         img.save(&input)?;
 
-        // Convert to WebP with custom options
-        let options = ImageFormatOptions::webp()
-            .with_quality(85)
-            .with_lossless(true);
-        convert_image(&input, &output, Some(options)).await?;
+        // Without options the jxl() preset applies, which needs no cargo feature
+        let output = temp_dir.path().join("default.jxl");
+        convert_image(&input, &output, None).await?;
+        assert_eq!(decode_jxl(&output)?.as_bytes(), img.as_bytes());
 
-        // Verify the output exists and is a valid WebP
-        assert!(output.exists());
-        let format = detect_format_from_extension(&output);
-        assert_eq!(format, Some(ImageFormat::WebP));
+        let measured = temp_dir.path().join("measured.jxl");
+        let metrics = convert_image_with_metrics(&input, &measured, None).await?;
+        assert!(metrics.psnr.is_infinite());
+
+        // Other formats keep the plain defaults rather than their preset
+        let default = temp_dir.path().join("default.jpg");
+        let explicit = temp_dir.path().join("explicit.jpg");
+        convert_image(&input, &default, None).await?;
+        convert_image(&input, &explicit, Some(ImageFormatOptions::default())).await?;
+        assert_eq!(std::fs::read(&default)?, std::fs::read(&explicit)?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_jxl_output_readable_by_convert_jxl_to_png() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input = temp_dir.path().join("test.png");
+        let jxl = temp_dir.path().join("test.JXL");
+        let png = temp_dir.path().join("back.png");
+        gradient_image(true).save(&input)?;
+
+        let options = ImageFormatOptions::jxl().with_option("effort", "9");
+        convert_image(&input, &jxl, Some(options)).await?;
+        crate::jxl::convert_jxl_to_png(&jxl, &png).await?;
+
+        assert_eq!(image::open(&png)?.dimensions(), (128, 128));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_jxl_rejects_invalid_effort() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input = temp_dir.path().join("test.png");
+        let output = temp_dir.path().join("test.jxl");
+        DynamicImage::new_rgb8(16, 16).save(&input)?;
+
+        let options = ImageFormatOptions::jxl().with_option("effort", "0");
+        assert!(convert_image(&input, &output, Some(options)).await.is_err());
+        Ok(())
+    }
+
+    #[cfg(not(feature = "jxl-lossy"))]
+    #[tokio::test]
+    async fn test_jxl_lossy_requires_feature() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input = temp_dir.path().join("test.png");
+        let output = temp_dir.path().join("test.jxl");
+        DynamicImage::new_rgb8(16, 16).save(&input)?;
+
+        let options = ImageFormatOptions::jxl()
+            .with_lossless(false)
+            .with_quality(80);
+        let err = convert_image(&input, &output, Some(options))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("jxl-lossy"));
+        Ok(())
+    }
+
+    #[cfg(feature = "jxl-lossy")]
+    #[tokio::test]
+    async fn test_jxl_lossy_round_trip() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input = temp_dir.path().join("test.png");
+        let lossy = temp_dir.path().join("lossy.jxl");
+        let lossless = temp_dir.path().join("lossless.jxl");
+        let img = gradient_image(false);
+        img.save(&input)?;
+
+        let options = ImageFormatOptions::jxl()
+            .with_lossless(false)
+            .with_quality(80)
+            .with_option("effort", "5");
+        convert_image(&input, &lossy, Some(options)).await?;
+        convert_image(&input, &lossless, Some(ImageFormatOptions::jxl())).await?;
+
+        assert!(std::fs::metadata(&lossy)?.len() < std::fs::metadata(&lossless)?.len());
+
+        // Lossy output should still be close to the original
+        let decoded = decode_jxl(&lossy)?.to_rgb8();
+        assert_eq!(decoded.dimensions(), (128, 128));
+        let total_error: u64 = decoded
+            .as_raw()
+            .iter()
+            .zip(img.as_bytes())
+            .map(|(a, b)| u64::from(a.abs_diff(*b)))
+            .sum();
+        let mean_error = total_error / decoded.as_raw().len() as u64;
+        assert!(mean_error < 16, "mean error {mean_error} too large");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_convert_jxl_input() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let png = temp_dir.path().join("source.png");
+        let jxl = temp_dir.path().join("source.jxl");
+        let img = gradient_image(true);
+        img.save(&png)?;
+        convert_image(&png, &jxl, Some(ImageFormatOptions::jxl())).await?;
+
+        for name in ["out.png", "out.webp", "out.jpg", "out.bmp"] {
+            let output = temp_dir.path().join(name);
+            convert_image(&jxl, &output, Some(ImageFormatOptions::png())).await?;
+            assert_eq!(open_image(&output)?.dimensions(), (128, 128), "{name}");
+        }
+        // Lossless outputs keep the exact pixels
+        assert_eq!(
+            open_image(&temp_dir.path().join("out.png"))?.to_rgba8(),
+            img.to_rgba8()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_convert_images_batch_jxl_inputs() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut inputs = Vec::new();
+        for name in ["a", "b"] {
+            let png = temp_dir.path().join(format!("{name}.png"));
+            let jxl = temp_dir.path().join(format!("{name}.jxl"));
+            gradient_image(false).save(&png)?;
+            convert_image(&png, &jxl, Some(ImageFormatOptions::jxl())).await?;
+            inputs.push(jxl);
+        }
+
+        let output_dir = temp_dir.path().join("out");
+        convert_images_batch(&inputs, &output_dir, ImageFormat::WebP, None).await?;
+
+        for name in ["a", "b"] {
+            let output = output_dir.join(format!("{name}.webp"));
+            assert_eq!(open_image(&output)?.dimensions(), (128, 128));
+        }
+        Ok(())
+    }
+
+    /// Whether the colour item of an AVIF file is high bit depth, from its `av1C` box.
+    fn avif_high_bit_depth(data: &[u8]) -> bool {
+        let tag = data
+            .windows(4)
+            .position(|window| window == b"av1C")
+            .expect("AVIF file has an av1C box");
+        // Marker and version, profile and level, then tier and the high bit depth flag
+        data[tag + 6] & 0x40 != 0
+    }
+
+    /// Whether an AVIF file has an alpha auxiliary image.
+    fn avif_has_alpha(data: &[u8]) -> bool {
+        let urn = b"urn:mpeg:mpegB:cicp:systems:auxiliary:alpha";
+        data.windows(urn.len()).any(|window| window == urn)
+    }
+
+    #[tokio::test]
+    async fn test_avif_output_and_options() -> Result<()> {
+        use crate::image_processing::{DetectedImageFormat, detect_image_format};
+
+        let temp_dir = TempDir::new()?;
+        let input = temp_dir.path().join("test.png");
+        gradient_image(true).save(&input)?;
+
+        let high = temp_dir.path().join("high.avif");
+        let low = temp_dir.path().join("low.avif");
+        let options = ImageFormatOptions::avif().with_option("speed", "10");
+        convert_image(&input, &high, Some(options.clone().with_quality(95))).await?;
+        convert_image(
+            &input,
+            &low,
+            Some(options.with_quality(20).with_option("alpha_quality", "20")),
+        )
+        .await?;
+
+        let data = std::fs::read(&low)?;
+        assert_eq!(
+            detect_image_format(data[..12].try_into()?),
+            Some(DetectedImageFormat::Avif)
+        );
+        assert!(avif_has_alpha(&data));
+        assert!(data.len() < std::fs::read(&high)?.len());
+
+        // Opaque images leave the alpha channel out
+        let opaque = temp_dir.path().join("opaque.png");
+        let output = temp_dir.path().join("opaque.avif");
+        gradient_image(false).save(&opaque)?;
+        let speed = AvifOptions {
+            speed: Some(10),
+            ..AvifOptions::default()
+        };
+        let options = ImageFormatOptions::default().with_encoder_options(speed);
+        convert_image(&opaque, &output, Some(options)).await?;
+        assert!(!avif_has_alpha(&std::fs::read(&output)?));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_avif_bit_depth() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input = temp_dir.path().join("deep.png");
+        DynamicImage::ImageRgba16(gradient_image(true).to_rgba16()).save(&input)?;
+
+        // 16-bit input keeps 10 bits unless asked for 8
+        let options = ImageFormatOptions::avif().with_option("speed", "10");
+        let deep = temp_dir.path().join("deep.avif");
+        convert_image(&input, &deep, Some(options.clone())).await?;
+        let data = std::fs::read(&deep)?;
+        assert!(avif_high_bit_depth(&data));
+        assert!(avif_has_alpha(&data));
+
+        let shallow = temp_dir.path().join("shallow.avif");
+        let options = options.with_option("bit_depth", "8");
+        convert_image(&input, &shallow, Some(options)).await?;
+        assert!(!avif_high_bit_depth(&std::fs::read(&shallow)?));
+
+        for (key, value) in [("bit_depth", "12"), ("speed", "0"), ("effort", "5")] {
+            let options = ImageFormatOptions::avif().with_option(key, value);
+            let output = temp_dir.path().join("invalid.avif");
+            assert!(
+                convert_image(&input, &output, Some(options)).await.is_err(),
+                "{key}={value}"
+            );
+            assert!(!output.exists());
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_avif_batch_and_input() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut inputs = Vec::new();
+        for name in ["a", "b"] {
+            let input = temp_dir.path().join(format!("{name}.png"));
+            gradient_image(false).save(&input)?;
+            inputs.push(input);
+        }
+
+        let output_dir = temp_dir.path().join("out");
+        let options = ImageFormatOptions::avif().with_option("speed", "10");
+        convert_images_batch(&inputs, &output_dir, ImageFormat::Avif, Some(options)).await?;
+
+        // Decoding AVIF input needs the `avif-decode` feature
+        let avif = output_dir.join("a.avif");
+        assert!(avif.exists() && output_dir.join("b.avif").exists());
+        let output = temp_dir.path().join("back.png");
+        let converted = convert_image(&avif, &output, None).await;
+        if cfg!(feature = "avif-decode") {
+            converted?;
+            assert_eq!(image::image_dimensions(&output)?, (128, 128));
+        } else {
+            let error = converted.unwrap_err();
+            assert!(format!("{error:#}").contains("avif-decode"), "{error:#}");
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "avif-decode")]
+    #[tokio::test]
+    async fn test_avif_decode_alpha_and_10_bit() -> Result<()> {
+        use crate::image_processing::open_image;
+
+        let temp_dir = TempDir::new()?;
+        let options = ImageFormatOptions::avif()
+            .with_quality(100)
+            .with_option("speed", "10");
+        let deep = DynamicImage::ImageRgba16(gradient_image(true).to_rgba16());
+        for (name, img, bit_depth, expected) in [
+            ("rgba8", gradient_image(true), "8", ColorType::Rgba8),
+            ("rgba16", deep, "10", ColorType::Rgba16),
+        ] {
+            let input = temp_dir.path().join(format!("{name}.png"));
+            let output = temp_dir.path().join(format!("{name}.avif"));
+            img.save(&input)?;
+            let options = options.clone().with_option("bit_depth", bit_depth);
+            convert_image(&input, &output, Some(options)).await?;
+
+            // 10-bit AVIF decodes to 16-bit samples, and alpha survives either way
+            let decoded = open_image(&output)?;
+            assert_eq!(decoded.color(), expected, "{name}");
+            let metrics = compare_images(&img, &decoded)?;
+            assert!(metrics.psnr > 30.0, "{name}: {metrics:?}");
+            let (decoded, original) = (decoded.to_rgba8(), img.to_rgba8());
+            for (x, y) in [(0, 0), (100, 20), (64, 127)] {
+                let (a, b) = (decoded.get_pixel(x, y), original.get_pixel(x, y));
+                assert!(a[3].abs_diff(b[3]) <= 2, "{name} {a:?} {b:?}");
+            }
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_convert_image_to_size() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input = temp_dir.path().join("test.png");
+        gradient_image(false).save(&input)?;
+
+        // A generous budget keeps the preset's quality
+        let output = temp_dir.path().join("roomy.jpg");
+        let report =
+            convert_image_to_size(&input, &output, None, &TargetSizeOptions::new(1 << 20)).await?;
+        assert_eq!(report.quality, 85);
+        assert_eq!(report.size, std::fs::metadata(&output)?.len());
+
+        let full_size = report.size;
+        for name in ["tight.jpg", "tight.webp"] {
+            let output = temp_dir.path().join(name);
+            let target = TargetSizeOptions::new(full_size / 2);
+            let report = convert_image_to_size(&input, &output, None, &target).await?;
+            assert!(report.size <= full_size / 2, "{name}: {report:?}");
+            assert_eq!(report.size, std::fs::metadata(&output)?.len());
+            assert!((1..85).contains(&report.quality), "{name}: {report:?}");
+            assert!(!report.downscaled);
+        }
+
+        // Typed options are searched too
+        let avif = AvifOptions {
+            speed: Some(10),
+            ..AvifOptions::default()
+        };
+        let options = ImageFormatOptions::default().with_encoder_options(avif);
+        let output = temp_dir.path().join("tight.avif");
+        let target = TargetSizeOptions::new(full_size / 4);
+        let report = convert_image_to_size(&input, &output, Some(options), &target).await?;
+        assert!(
+            report.size <= full_size / 4 && report.quality < 80,
+            "{report:?}"
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "jxl-lossy")]
+    #[tokio::test]
+    async fn test_convert_image_to_size_jxl() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input = temp_dir.path().join("test.png");
+        let lossless = temp_dir.path().join("lossless.jxl");
+        gradient_image(false).save(&input)?;
+        convert_image(&input, &lossless, Some(ImageFormatOptions::jxl())).await?;
+
+        // The lossless preset is searched in lossy mode below quality 100
+        let budget = std::fs::metadata(&lossless)?.len() / 3;
+        let output = temp_dir.path().join("tight.jxl");
+        let report =
+            convert_image_to_size(&input, &output, None, &TargetSizeOptions::new(budget)).await?;
+        assert!(report.size <= budget && report.quality < 100, "{report:?}");
+        assert_eq!(decode_jxl(&output)?.dimensions(), (128, 128));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_convert_image_to_size_limits() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input = temp_dir.path().join("test.png");
+        gradient_image(false).save(&input)?;
+
+        // The quality floor can leave the budget out of reach
+        let output = temp_dir.path().join("small.jpg");
+        let target = TargetSizeOptions::new(1500).with_min_quality(70);
+        let error = convert_image_to_size(&input, &output, None, &target)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("down to 70"), "{error}");
+        assert!(!output.exists());
+
+        // unless the image may shrink
+        let report =
+            convert_image_to_size(&input, &output, None, &target.with_downscale(true)).await?;
+        assert!(report.downscaled && report.size <= 1500, "{report:?}");
+        assert!(report.quality >= 70);
+        assert_eq!(
+            open_image(&output)?.dimensions(),
+            (report.width, report.height)
+        );
+        assert!(report.width < 128 && report.width >= MIN_DOWNSCALE_SIDE);
+
+        // Formats without a quality setting are rejected
+        let output = temp_dir.path().join("small.png");
+        let target = TargetSizeOptions::new(1500);
+        assert!(
+            convert_image_to_size(&input, &output, None, &target)
+                .await
+                .is_err()
+        );
+
+        // and so is JPEG XL when it can only be encoded lossless
+        if cfg!(not(feature = "jxl-lossy")) {
+            let output = temp_dir.path().join("small.jxl");
+            let error = convert_image_to_size(&input, &output, None, &target)
+                .await
+                .unwrap_err();
+            assert!(error.to_string().contains("jxl-lossy"), "{error}");
+            assert!(!output.exists());
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_convert_images_batch_concurrent() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut inputs = Vec::new();
+        for i in 0..8u8 {
+            let input = temp_dir.path().join(format!("image{i}.png"));
+            image::RgbImage::from_pixel(16, 16, image::Rgb([i * 30, 0, 0])).save(&input)?;
+            inputs.push(input);
+        }
+        // Both map to `image3.png`; the later input wins as in a sequential run
+        let duplicate = temp_dir.path().join("image3.bmp");
+        image::RgbImage::from_pixel(16, 16, image::Rgb([0, 0, 255])).save(&duplicate)?;
+        inputs.push(duplicate);
+
+        let output_dir = temp_dir.path().join("out");
+        let batch_options = BatchOptions::default().with_concurrency(3);
+        convert_images_batch_with_options(
+            &inputs,
+            &output_dir,
+            ImageFormat::Png,
+            None,
+            &batch_options,
+        )
+        .await?;
+        for i in 0..8u8 {
+            let output = open_image(&output_dir.join(format!("image{i}.png")))?.to_rgb8();
+            let expected = if i == 3 { [0, 0, 255] } else { [i * 30, 0, 0] };
+            assert_eq!(output.get_pixel(0, 0).0, expected);
+        }
+
+        // The first failing input in input order is reported
+        for name in ["broken1.png", "broken2.png"] {
+            std::fs::write(temp_dir.path().join(name), b"not an image")?;
+        }
+        let mut inputs = inputs[..4].to_vec();
+        inputs.insert(1, temp_dir.path().join("broken1.png"));
+        inputs.push(temp_dir.path().join("broken2.png"));
+        let error = convert_images_batch(&inputs, &output_dir, ImageFormat::Png, None)
+            .await
+            .unwrap_err();
+        assert!(format!("{error:#}").contains("broken1.png"), "{error:#}");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_convert_images_batch_report() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut inputs = Vec::new();
+        for name in ["a", "broken", "b", "c"] {
+            let input = temp_dir.path().join(format!("{name}.png"));
+            if name == "broken" {
+                std::fs::write(&input, b"not an image")?;
+            } else {
+                gradient_image(false).save(&input)?;
+            }
+            inputs.push(input);
+        }
+        let output_dir = temp_dir.path().join("out");
+
+        // Stopping at the failure skips what was not started yet
+        let batch_options = BatchOptions::default().with_concurrency(1);
+        let report = convert_images_batch_with_options(
+            &inputs,
+            &output_dir,
+            ImageFormat::WebP,
+            None,
+            &batch_options,
+        )
+        .await?;
+        assert!(!report.is_success());
+        assert_eq!(report.converted.len(), 1);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].input, inputs[1]);
+        let skipped: Vec<_> = report.skipped.iter().map(|s| s.input.clone()).collect();
+        assert_eq!(skipped, inputs[2..]);
+        assert!(
+            report
+                .skipped
+                .iter()
+                .all(|s| s.reason == BatchSkipReason::EarlierFailure)
+        );
+        assert_eq!(report.retry_inputs(), inputs[1..]);
+
+        // Continuing converts everything else, in input order
+        let batch_options = batch_options.with_continue_on_error(true);
+        let report = convert_images_batch_with_options(
+            &inputs,
+            &output_dir,
+            ImageFormat::WebP,
+            None,
+            &batch_options,
+        )
+        .await?;
+        let converted: Vec<_> = report.converted.iter().map(|c| &c.input).collect();
+        assert_eq!(converted, [&inputs[0], &inputs[2], &inputs[3]]);
+        assert!(report.skipped.is_empty());
+        assert_eq!(report.retry_inputs(), [inputs[1].clone()]);
+        let first = &report.converted[0];
+        assert_eq!(first.output, output_dir.join("a.webp"));
+        assert_eq!(first.input_bytes, std::fs::metadata(&inputs[0])?.len());
+        assert_eq!(first.output_bytes, std::fs::metadata(&first.output)?.len());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_convert_images_batch_naming() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input_root = temp_dir.path().join("in");
+        let mut inputs = Vec::new();
+        for name in ["photo.v2.final.png", "albums/2024/beach.png"] {
+            let input = input_root.join(name);
+            std::fs::create_dir_all(input.parent().unwrap())?;
+            gradient_image(false).save(&input)?;
+            inputs.push(input);
+        }
+
+        // Only the last extension is replaced, and the input tree is mirrored
+        let output_dir = temp_dir.path().join("mirror");
+        let batch_options = BatchOptions::default().with_input_root(&input_root);
+        let report = convert_images_batch_with_options(
+            &inputs,
+            &output_dir,
+            ImageFormat::Tiff,
+            None,
+            &batch_options,
+        )
+        .await?;
+        let outputs: Vec<_> = report.converted.iter().map(|c| c.output.clone()).collect();
+        assert_eq!(
+            outputs,
+            [
+                output_dir.join("photo.v2.final.tiff"),
+                output_dir.join("albums/2024/beach.tiff")
+            ]
+        );
+        assert!(outputs.iter().all(|output| output.exists()));
+
+        // Templates
+        let output_dir = temp_dir.path().join("templated");
+        let batch_options =
+            BatchOptions::default().with_name_template("{parent}/{index}-{stem}.{format}");
+        let report = convert_images_batch_with_options(
+            &inputs,
+            &output_dir,
+            ImageFormat::Png,
+            None,
+            &batch_options,
+        )
+        .await?;
+        assert_eq!(
+            report.converted[0].output,
+            output_dir.join("in/1-photo.v2.final.png.png")
+        );
+        assert_eq!(
+            report.converted[1].output,
+            output_dir.join("2024/2-beach.png.png")
+        );
+
+        let batch_options = BatchOptions::default().with_name_template("{hash}");
+        let report = convert_images_batch_with_options(
+            &inputs[..1],
+            &output_dir,
+            ImageFormat::Png,
+            None,
+            &batch_options,
+        )
+        .await?;
+        let hash = content_hash(&std::fs::read(&inputs[0])?);
+        assert_eq!(
+            report.converted[0].output,
+            output_dir.join(format!("{}.png", &hash[..16]))
+        );
+
+        // Invalid templates fail the batch, inputs outside the root fail the image
+        for template in ["{name}", "{stem", "../{stem}"] {
+            let batch_options = BatchOptions::default()
+                .with_name_template(template)
+                .with_continue_on_error(true);
+            let result = convert_images_batch_with_options(
+                &inputs,
+                &output_dir,
+                ImageFormat::Png,
+                None,
+                &batch_options,
+            )
+            .await;
+            if template.starts_with('{') {
+                assert!(result.is_err(), "{template}");
+            } else {
+                assert_eq!(result?.failed.len(), 2, "{template}");
+            }
+        }
+        let batch_options = BatchOptions::default().with_input_root(input_root.join("albums"));
+        let report = convert_images_batch_with_options(
+            &inputs,
+            &output_dir,
+            ImageFormat::Png,
+            None,
+            &batch_options,
+        )
+        .await?;
+        assert_eq!(report.failed[0].input, inputs[0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_extension() {
+        for format in ImageFormat::all() {
+            let extension = format_extension(format).unwrap();
+            let path = PathBuf::from(format!("image.{extension}"));
+            assert_eq!(detect_format_from_extension(&path), Some(format));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_convert_images_batch_collisions() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut inputs = Vec::new();
+        for (name, color) in [("a.png", 0), ("a.bmp", 100), ("b.png", 200)] {
+            let input = temp_dir.path().join(name);
+            image::RgbImage::from_pixel(4, 4, image::Rgb([color, 0, 0])).save(&input)?;
+            inputs.push(input);
+        }
+        let red =
+            |path: &Path| -> Result<u8> { Ok(open_image(path)?.to_rgb8().get_pixel(0, 0)[0]) };
+        let run = |policy: OutputCollisionPolicy, output_dir: PathBuf| {
+            let inputs = inputs.clone();
+            async move {
+                let batch_options = BatchOptions::default()
+                    .with_collision_policy(policy)
+                    .with_continue_on_error(true);
+                convert_images_batch_with_options(
+                    &inputs,
+                    &output_dir,
+                    ImageFormat::Png,
+                    None,
+                    &batch_options,
+                )
+                .await
+            }
+        };
+
+        // `b.png` exists before the batch in every output directory
+        for (policy, dir) in [
+            (OutputCollisionPolicy::Overwrite, "overwrite"),
+            (OutputCollisionPolicy::Error, "error"),
+            (OutputCollisionPolicy::Suffix, "suffix"),
+            (OutputCollisionPolicy::Skip, "skip"),
+        ] {
+            let output_dir = temp_dir.path().join(dir);
+            std::fs::create_dir_all(&output_dir)?;
+            image::RgbImage::from_pixel(4, 4, image::Rgb([1, 0, 0]))
+                .save(output_dir.join("b.png"))?;
+            let report = run(policy, output_dir.clone()).await?;
+            let outputs: Vec<_> = report.converted.iter().map(|c| c.output.clone()).collect();
+            match policy {
+                OutputCollisionPolicy::Overwrite => {
+                    assert_eq!(report.converted.len(), 3);
+                    assert_eq!(red(&output_dir.join("a.png"))?, 100);
+                    assert_eq!(red(&output_dir.join("b.png"))?, 200);
+                }
+                OutputCollisionPolicy::Error => {
+                    assert_eq!(outputs, [output_dir.join("a.png")]);
+                    let failed: Vec<_> = report.failed.iter().map(|f| f.input.clone()).collect();
+                    assert_eq!(failed, inputs[1..]);
+                    assert_eq!(red(&output_dir.join("b.png"))?, 1);
+                }
+                OutputCollisionPolicy::Suffix => {
+                    assert_eq!(
+                        outputs,
+                        [
+                            output_dir.join("a.png"),
+                            output_dir.join("a_1.png"),
+                            output_dir.join("b_1.png")
+                        ]
+                    );
+                    assert_eq!(red(&output_dir.join("a.png"))?, 0);
+                    assert_eq!(red(&output_dir.join("a_1.png"))?, 100);
+                }
+                OutputCollisionPolicy::Skip => {
+                    assert_eq!(outputs, [output_dir.join("a.png")]);
+                    let reasons: Vec<_> = report.skipped.iter().map(|s| s.reason.clone()).collect();
+                    assert_eq!(
+                        reasons,
+                        [
+                            BatchSkipReason::OutputExists(output_dir.join("a.png")),
+                            BatchSkipReason::OutputExists(output_dir.join("b.png"))
+                        ]
+                    );
+                    assert_eq!(red(&output_dir.join("b.png"))?, 1);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_convert_images_batch_incremental_collisions() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let inputs: Vec<_> = ["a.png", "b.png"]
+            .iter()
+            .map(|name| temp_dir.path().join(name))
+            .collect();
+        for input in &inputs {
+            gradient_image(false).save(input)?;
+        }
+        let names = |paths: Vec<&PathBuf>| -> Vec<String> {
+            paths
+                .iter()
+                .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+                .collect()
+        };
+
+        for mode in [IncrementalMode::Mtime, IncrementalMode::ContentHash] {
+            let output_dir = temp_dir.path().join(format!("{mode:?}"));
+            std::fs::create_dir_all(&output_dir)?;
+            // Old files the batch did not write
+            for name in ["b.png", "b_1.png"] {
+                let file = std::fs::File::create(output_dir.join(name))?;
+                file.set_modified(std::time::UNIX_EPOCH + Duration::from_hours(24))?;
+            }
+            let run = |policy: OutputCollisionPolicy| {
+                let batch_options = BatchOptions::default()
+                    .with_incremental(mode)
+                    .with_collision_policy(policy)
+                    .with_continue_on_error(true);
+                let output_dir = output_dir.clone();
+                let inputs = inputs.clone();
+                async move {
+                    convert_images_batch_with_options(
+                        &inputs,
+                        &output_dir,
+                        ImageFormat::Png,
+                        None,
+                        &batch_options,
+                    )
+                    .await
+                }
+            };
+
+            // The existing `b.png` is a collision, not an earlier output
+            let report = run(OutputCollisionPolicy::Error).await?;
+            let failed: Vec<_> = report.failed.iter().map(|f| &f.input).collect();
+            assert_eq!(names(failed), ["b.png"], "{mode:?}");
+            assert_eq!(report.retry_inputs(), inputs[1..], "{mode:?}");
+            assert_eq!(std::fs::metadata(output_dir.join("b.png"))?.len(), 0);
+
+            // Inputs skipped because they are up to date or their output is
+            // taken are not retried
+            let report = run(OutputCollisionPolicy::Skip).await?;
+            let reasons: Vec<_> = report.skipped.iter().map(|s| s.reason.clone()).collect();
+            assert_eq!(
+                reasons,
+                [
+                    BatchSkipReason::UpToDate(output_dir.join("a.png")),
+                    BatchSkipReason::OutputExists(output_dir.join("b.png")),
+                ],
+                "{mode:?}"
+            );
+            assert!(report.retry_inputs().is_empty(), "{mode:?}");
+
+            // Suffixes skip names taken on disk
+            let report = run(OutputCollisionPolicy::Suffix).await?;
+            let outputs: Vec<_> = report.converted.iter().map(|c| &c.output).collect();
+            assert_eq!(names(outputs), ["b_2.png"], "{mode:?}");
+            assert_eq!(std::fs::metadata(output_dir.join("b_1.png"))?.len(), 0);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_convert_images_batch_incremental() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut inputs = Vec::new();
+        for name in ["a", "b", "c"] {
+            let input = temp_dir.path().join(format!("{name}.png"));
+            gradient_image(false).save(&input)?;
+            inputs.push(input);
+        }
+        let counts = |report: &BatchReport| {
+            let up_to_date = report
+                .skipped
+                .iter()
+                .filter(|s| matches!(s.reason, BatchSkipReason::UpToDate(_)))
+                .count();
+            (report.converted.len(), up_to_date)
+        };
+
+        for (size, mode) in [
+            (8, IncrementalMode::Mtime),
+            (9, IncrementalMode::ContentHash),
+        ] {
+            let output_dir = temp_dir.path().join(format!("{mode:?}"));
+            // Existing outputs are earlier results, not collisions
+            let batch_options = BatchOptions::default()
+                .with_incremental(mode)
+                .with_collision_policy(OutputCollisionPolicy::Error);
+            let run = || {
+                convert_images_batch_with_options(
+                    &inputs,
+                    &output_dir,
+                    ImageFormat::Png,
+                    None,
+                    &batch_options,
+                )
+            };
+            assert_eq!(counts(&run().await?), (3, 0), "{mode:?}");
+            assert_eq!(counts(&run().await?), (0, 3), "{mode:?}");
+
+            // A changed input is converted again by both modes
+            DynamicImage::new_rgb8(size, size).save(&inputs[1])?;
+            let report = run().await?;
+            assert_eq!(counts(&report), (1, 2), "{mode:?}");
+            assert_eq!(report.converted[0].input, inputs[1]);
+
+            // Touching an input without changing it only affects the mtime mode
+            std::fs::File::options()
+                .write(true)
+                .open(&inputs[2])?
+                .set_modified(std::time::SystemTime::now())?;
+            let expected = match mode {
+                IncrementalMode::Mtime => (1, 2),
+                _ => (0, 3),
+            };
+            assert_eq!(counts(&run().await?), expected, "{mode:?}");
+
+            // Outputs are moved into place, so no temporary files are left
+            let mut names: Vec<_> = std::fs::read_dir(&output_dir)?
+                .map(|entry| entry.map(|entry| entry.file_name()))
+                .collect::<std::io::Result<_>>()?;
+            names.retain(|name| name != BATCH_MANIFEST_NAME);
+            names.sort();
+            assert_eq!(names, ["a.png", "b.png", "c.png"], "{mode:?}");
+        }
+
+        // A failed write leaves neither the output nor a temporary file
+        let blocked = temp_dir.path().join("blocked");
+        std::fs::create_dir_all(blocked.join("out.png"))?;
+        assert!(
+            convert_image(&inputs[0], &blocked.join("out.png"), None)
+                .await
+                .is_err()
+        );
+        assert_eq!(std::fs::read_dir(&blocked)?.count(), 1);
 
+        // The manifest records the input hash of every output
+        let manifest = std::fs::read_to_string(
+            temp_dir
+                .path()
+                .join("ContentHash")
+                .join(BATCH_MANIFEST_NAME),
+        )?;
+        let hash = content_hash(&std::fs::read(&inputs[1])?);
+        assert_eq!(manifest.lines().count(), 3);
+        assert!(manifest.contains(&format!("{hash}\tb.png")), "{manifest}");
         Ok(())
     }
 }
@@ -28,10 +28,19 @@
 
 #![warn(clippy::all, clippy::pedantic)]
 
+use crate::formats::{
+    default_options_for, detect_output_format, encode_image, write_image, write_output,
+};
+use crate::jxl::{JXL_CONTAINER_SIGNATURE, decode_jxl_reader, read_jxl_header};
+use crate::metadata::{ImageMetadata, MetadataPolicy, read_container_metadata};
 use anyhow::{Context, Result};
-use image::{GenericImageView, ImageBuffer, ImageFormat, Rgba};
+use image::metadata::Orientation;
+use image::{
+    ColorType, DynamicImage, GenericImageView, ImageBuffer, ImageDecoder, ImageFormat, ImageReader,
+    Rgba,
+};
 use log::{info, warn};
-use std::io::Read;
+use std::io::{BufReader, Cursor, Read, Seek};
 use std::path::{Path, PathBuf};
 use tokio::fs;
 
@@ -39,7 +48,7 @@ use tokio::fs;
 ///
 /// This enum provides a type-safe way to handle different image formats
 /// and includes methods for working with file extensions and format conversion.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum DetectedImageFormat {
     /// JPEG image format (magic numbers: FF D8 FF)
     Jpeg,
@@ -47,11 +56,41 @@ pub enum DetectedImageFormat {
     Png,
     /// WebP image format (magic numbers: 52 49 46 46 ... 57 45 42 50)
     WebP,
-    /// JPEG XL image format (magic numbers: FF 0A)
+    /// JPEG XL image format (magic numbers: FF 0A for a bare codestream,
+    /// 00 00 00 0C 4A 58 4C 20 0D 0A 87 0A for the ISOBMFF container)
     Jxl,
+    /// GIF image format (magic numbers: 47 49 46 38 37 61 or 47 49 46 38 39 61)
+    Gif,
+    /// BMP image format (magic numbers: 42 4D)
+    Bmp,
+    /// TIFF image format (magic numbers: 49 49 2A 00 or 4D 4D 00 2A)
+    Tiff,
+    /// AVIF image format (ISOBMFF `ftyp` box with an `avif` or `avis` brand)
+    Avif,
+    /// QOI image format (magic numbers: 71 6F 69 66)
+    Qoi,
+    /// ICO image format (magic numbers: 00 00 01 00)
+    Ico,
+    /// HEIC/HEIF image format (ISOBMFF `ftyp` box with a HEIF brand)
+    Heic,
 }
 
 impl DetectedImageFormat {
+    /// All formats that can be detected, in detection order
+    pub const ALL: [Self; 11] = [
+        Self::Jpeg,
+        Self::Png,
+        Self::WebP,
+        Self::Jxl,
+        Self::Gif,
+        Self::Bmp,
+        Self::Tiff,
+        Self::Avif,
+        Self::Qoi,
+        Self::Ico,
+        Self::Heic,
+    ];
+
     /// Get the standard file extension for this format
     #[must_use]
     pub fn extension(&self) -> &'static str {
@@ -60,6 +99,13 @@ impl DetectedImageFormat {
             Self::Png => "png",
             Self::WebP => "webp",
             Self::Jxl => "jxl",
+            Self::Gif => "gif",
+            Self::Bmp => "bmp",
+            Self::Tiff => "tiff",
+            Self::Avif => "avif",
+            Self::Qoi => "qoi",
+            Self::Ico => "ico",
+            Self::Heic => "heic",
         }
     }
 
@@ -71,9 +117,35 @@ impl DetectedImageFormat {
             Self::Png => &["png"],
             Self::WebP => &["webp"],
             Self::Jxl => &["jxl"],
+            Self::Gif => &["gif"],
+            Self::Bmp => &["bmp"],
+            Self::Tiff => &["tif", "tiff"],
+            Self::Avif => &["avif"],
+            Self::Qoi => &["qoi"],
+            Self::Ico => &["ico"],
+            Self::Heic => &["heic", "heif"],
         }
     }
 
+    /// Look up the format claimed by a file extension (case-insensitive)
+    ///
+    /// # Arguments
+    ///
+    /// * `ext` - File extension without the leading dot
+    ///
+    /// # Returns
+    ///
+    /// Returns `Some(DetectedImageFormat)` if the extension belongs to a known format, `None` otherwise
+    #[must_use]
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|format| {
+            format
+                .all_extensions()
+                .iter()
+                .any(|candidate| candidate.eq_ignore_ascii_case(ext))
+        })
+    }
+
     /// Convert to the corresponding `image::ImageFormat`
     #[must_use]
     pub fn to_image_format(&self) -> Option<ImageFormat> {
@@ -81,13 +153,401 @@ impl DetectedImageFormat {
             Self::Jpeg => Some(ImageFormat::Jpeg),
             Self::Png => Some(ImageFormat::Png),
             Self::WebP => Some(ImageFormat::WebP),
-            Self::Jxl => None, // image crate might not support JXL
+            Self::Gif => Some(ImageFormat::Gif),
+            Self::Bmp => Some(ImageFormat::Bmp),
+            Self::Tiff => Some(ImageFormat::Tiff),
+            Self::Avif => Some(ImageFormat::Avif),
+            Self::Qoi => Some(ImageFormat::Qoi),
+            Self::Ico => Some(ImageFormat::Ico),
+            Self::Jxl | Self::Heic => None, // image crate does not support these
+        }
+    }
+}
+
+/// How much a sniffed format can be trusted.
+///
+/// Long, distinctive signatures such as the PNG header give `High` confidence,
+/// while short or generic ones such as the two-byte BMP header give `Low`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SniffConfidence {
+    /// Short or generic signature that also occurs in non-image data
+    Low,
+    /// Signature that is reasonably specific but only a few bytes long
+    Medium,
+    /// Long, unambiguous signature
+    High,
+}
+
+/// Why a format was chosen by the sniffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SniffReason {
+    /// A fixed magic-number signature matched at the start of the data
+    MagicNumber,
+    /// The major brand of an ISOBMFF `ftyp` box identified the format
+    MajorBrand([u8; 4]),
+    /// A compatible brand listed in an ISOBMFF `ftyp` box identified the format
+    CompatibleBrand([u8; 4]),
+}
+
+impl std::fmt::Display for SniffReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MagicNumber => write!(f, "magic number"),
+            Self::MajorBrand(brand) => {
+                write!(f, "ftyp major brand '{}'", String::from_utf8_lossy(brand))
+            }
+            Self::CompatibleBrand(brand) => {
+                write!(
+                    f,
+                    "ftyp compatible brand '{}'",
+                    String::from_utf8_lossy(brand)
+                )
+            }
+        }
+    }
+}
+
+/// A format identified by the sniffer, together with how and how reliably it was identified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FormatMatch {
+    /// The detected format
+    pub format: DetectedImageFormat,
+    /// How much the detection can be trusted
+    pub confidence: SniffConfidence,
+    /// What the detection was based on
+    pub reason: SniffReason,
+}
+
+/// Outcome of sniffing the start of a byte stream for an image format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SniffOutcome {
+    /// A format was detected
+    Detected(FormatMatch),
+    /// The data is too short to decide
+    Inconclusive {
+        /// Total number of bytes (counted from the start of the stream) needed to decide
+        needed: usize,
+        /// Best guess from the bytes seen so far, if any
+        best_guess: Option<FormatMatch>,
+    },
+    /// The data does not start with any known image signature
+    Unknown,
+}
+
+impl SniffOutcome {
+    /// Get the detected format, if the outcome is conclusive
+    #[must_use]
+    pub fn format(&self) -> Option<DetectedImageFormat> {
+        match self {
+            Self::Detected(found) => Some(found.format),
+            Self::Inconclusive { .. } | Self::Unknown => None,
+        }
+    }
+
+    /// Get the detected format, falling back to the best guess of an inconclusive outcome
+    #[must_use]
+    pub fn best_match(&self) -> Option<FormatMatch> {
+        match self {
+            Self::Detected(found) => Some(*found),
+            Self::Inconclusive { best_guess, .. } => *best_guess,
+            Self::Unknown => None,
+        }
+    }
+}
+
+/// Number of bytes that is enough to decide on every fixed magic-number signature
+pub const SNIFF_HEADER_LEN: usize = 12;
+
+/// Upper bound on how much of an ISOBMFF `ftyp` box is read to look at compatible brands
+const FTYP_SNIFF_LIMIT: usize = 256;
+
+/// A fixed signature made of byte strings at given offsets.
+struct Signature {
+    format: DetectedImageFormat,
+    confidence: SniffConfidence,
+    parts: &'static [(usize, &'static [u8])],
+}
+
+/// Fixed signatures, in priority order.
+const SIGNATURES: &[Signature] = &[
+    Signature {
+        format: DetectedImageFormat::Jpeg,
+        confidence: SniffConfidence::Medium,
+        parts: &[(0, &[0xFF, 0xD8, 0xFF])],
+    },
+    Signature {
+        format: DetectedImageFormat::Png,
+        confidence: SniffConfidence::High,
+        parts: &[(0, &[0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A])],
+    },
+    Signature {
+        format: DetectedImageFormat::WebP,
+        confidence: SniffConfidence::High,
+        parts: &[(0, b"RIFF"), (8, b"WEBP")],
+    },
+    Signature {
+        format: DetectedImageFormat::Jxl,
+        confidence: SniffConfidence::Medium,
+        parts: &[(0, &[0xFF, 0x0A])],
+    },
+    Signature {
+        format: DetectedImageFormat::Jxl,
+        confidence: SniffConfidence::High,
+        parts: &[(0, &JXL_CONTAINER_SIGNATURE)],
+    },
+    Signature {
+        format: DetectedImageFormat::Gif,
+        confidence: SniffConfidence::High,
+        parts: &[(0, b"GIF87a")],
+    },
+    Signature {
+        format: DetectedImageFormat::Gif,
+        confidence: SniffConfidence::High,
+        parts: &[(0, b"GIF89a")],
+    },
+    Signature {
+        format: DetectedImageFormat::Bmp,
+        confidence: SniffConfidence::Low,
+        parts: &[(0, b"BM")],
+    },
+    Signature {
+        format: DetectedImageFormat::Tiff,
+        confidence: SniffConfidence::High,
+        parts: &[(0, b"II*\0")],
+    },
+    Signature {
+        format: DetectedImageFormat::Tiff,
+        confidence: SniffConfidence::High,
+        parts: &[(0, b"MM\0*")],
+    },
+    Signature {
+        format: DetectedImageFormat::Tiff,
+        confidence: SniffConfidence::High,
+        parts: &[(0, b"II+\0")],
+    },
+    Signature {
+        format: DetectedImageFormat::Tiff,
+        confidence: SniffConfidence::High,
+        parts: &[(0, b"MM\0+")],
+    },
+    Signature {
+        format: DetectedImageFormat::Qoi,
+        confidence: SniffConfidence::High,
+        parts: &[(0, b"qoif")],
+    },
+];
+
+/// Matches the given data against a fixed signature.
+fn match_signature(data: &[u8], signature: &Signature) -> SniffOutcome {
+    let mut needed = 0;
+    for &(offset, bytes) in signature.parts {
+        let end = offset + bytes.len();
+        needed = needed.max(end);
+        let available = data.get(offset..end.min(data.len())).unwrap_or_default();
+        if !bytes.starts_with(available) {
+            return SniffOutcome::Unknown;
+        }
+    }
+
+    if data.len() < needed {
+        return SniffOutcome::Inconclusive {
+            needed,
+            best_guess: None,
+        };
+    }
+
+    SniffOutcome::Detected(FormatMatch {
+        format: signature.format,
+        confidence: signature.confidence,
+        reason: SniffReason::MagicNumber,
+    })
+}
+
+/// Determines the format identified by an ISOBMFF brand.
+///
+/// Generic HEIF brands (`mif1`, `msf1`, `miaf`) are not specific enough on their own
+/// and return `None`.
+fn detect_ftyp_brand(brand: [u8; 4]) -> Option<DetectedImageFormat> {
+    match &brand {
+        b"avif" | b"avis" => Some(DetectedImageFormat::Avif),
+        b"heic" | b"heix" | b"heim" | b"heis" | b"hevc" | b"hevx" => {
+            Some(DetectedImageFormat::Heic)
+        }
+        _ => None,
+    }
+}
+
+/// Matches the given data against an ISOBMFF `ftyp` box, looking at the major brand
+/// first and then at the compatible brands.
+fn match_ftyp(data: &[u8]) -> SniffOutcome {
+    let available = data.get(4..data.len().min(8)).unwrap_or_default();
+    if !b"ftyp".starts_with(available) {
+        return SniffOutcome::Unknown;
+    }
+    let Some(header) = data.get(..SNIFF_HEADER_LEN) else {
+        return SniffOutcome::Inconclusive {
+            needed: SNIFF_HEADER_LEN,
+            best_guess: None,
+        };
+    };
+
+    let major = [header[8], header[9], header[10], header[11]];
+    if let Some(format) = detect_ftyp_brand(major) {
+        return SniffOutcome::Detected(FormatMatch {
+            format,
+            confidence: SniffConfidence::High,
+            reason: SniffReason::MajorBrand(major),
+        });
+    }
+
+    // Compatible brands follow the major brand and the 4-byte minor version
+    let box_size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let box_end = box_size.min(FTYP_SNIFF_LIMIT);
+    let brands = data.get(16..data.len().min(box_end)).unwrap_or_default();
+    for brand in brands.chunks_exact(4) {
+        let brand = [brand[0], brand[1], brand[2], brand[3]];
+        if let Some(format) = detect_ftyp_brand(brand) {
+            return SniffOutcome::Detected(FormatMatch {
+                format,
+                confidence: SniffConfidence::Medium,
+                reason: SniffReason::CompatibleBrand(brand),
+            });
+        }
+    }
+
+    let generic_heif = matches!(&major, b"mif1" | b"msf1" | b"miaf").then_some(FormatMatch {
+        format: DetectedImageFormat::Heic,
+        confidence: SniffConfidence::Low,
+        reason: SniffReason::MajorBrand(major),
+    });
+
+    if data.len() < box_end {
+        SniffOutcome::Inconclusive {
+            needed: box_end,
+            best_guess: generic_heif,
+        }
+    } else {
+        generic_heif.map_or(SniffOutcome::Unknown, SniffOutcome::Detected)
+    }
+}
+
+/// Matches the given data against the ICO header, which also requires a non-zero image count.
+fn match_ico(data: &[u8]) -> SniffOutcome {
+    const ICO_HEADER: [u8; 4] = [0x00, 0x00, 0x01, 0x00];
+
+    let available = &data[..data.len().min(ICO_HEADER.len())];
+    if !ICO_HEADER.starts_with(available) {
+        return SniffOutcome::Unknown;
+    }
+    match data.get(4..6) {
+        None => SniffOutcome::Inconclusive {
+            needed: 6,
+            best_guess: None,
+        },
+        Some([0, 0]) => SniffOutcome::Unknown,
+        Some(_) => SniffOutcome::Detected(FormatMatch {
+            format: DetectedImageFormat::Ico,
+            confidence: SniffConfidence::Low,
+            reason: SniffReason::MagicNumber,
+        }),
+    }
+}
+
+/// Sniffs the image format from the start of a byte slice of any length.
+///
+/// Signatures are checked in priority order and the first one that is not ruled
+/// out decides the outcome. If the data is too short to decide, the outcome is
+/// `SniffOutcome::Inconclusive` with the number of bytes needed to make progress;
+/// [`SNIFF_HEADER_LEN`] bytes are always enough for the fixed signatures, while
+/// ISOBMFF-based formats may need the rest of their `ftyp` box.
+///
+/// # Arguments
+///
+/// * `data` - The first bytes of the file or stream
+///
+/// # Returns
+///
+/// Returns the `SniffOutcome` for the available data
+///
+/// # Examples
+///
+/// ```rust
+/// use imx::image_processing::{sniff_image_format, DetectedImageFormat, SniffOutcome};
+///
+/// let outcome = sniff_image_format(b"\x89PNG\r\n\x1a\n");
+/// assert_eq!(outcome.format(), Some(DetectedImageFormat::Png));
+///
+/// assert!(matches!(
+///     sniff_image_format(b"\x89PN"),
+///     SniffOutcome::Inconclusive { needed: 8, .. }
+/// ));
+/// ```
+#[must_use]
+pub fn sniff_image_format(data: &[u8]) -> SniffOutcome {
+    if data.is_empty() {
+        return SniffOutcome::Inconclusive {
+            needed: SNIFF_HEADER_LEN,
+            best_guess: None,
+        };
+    }
+
+    SIGNATURES
+        .iter()
+        .map(|signature| match_signature(data, signature))
+        .chain([match_ftyp(data), match_ico(data)])
+        .find(|outcome| *outcome != SniffOutcome::Unknown)
+        .unwrap_or(SniffOutcome::Unknown)
+}
+
+/// Sniffs the image format from a reader, reading only as many bytes as needed.
+///
+/// The bytes consumed from the reader are returned alongside the outcome, so they can
+/// be chained back in front of the rest of the stream (for example with
+/// `std::io::Cursor::new(header).chain(reader)`). When the stream ends before the
+/// sniffer can decide, the best guess is returned as `SniffOutcome::Detected` if there
+/// is one, and `SniffOutcome::Unknown` otherwise.
+///
+/// # Arguments
+///
+/// * `reader` - Reader positioned at the start of the image data
+///
+/// # Returns
+///
+/// Returns the `SniffOutcome` and the bytes read from `reader`
+///
+/// # Errors
+///
+/// Returns an error if reading from `reader` fails
+pub fn sniff_image_format_from_reader<R: Read>(
+    reader: &mut R,
+) -> std::io::Result<(SniffOutcome, Vec<u8>)> {
+    let mut header = Vec::with_capacity(SNIFF_HEADER_LEN);
+    let mut target = SNIFF_HEADER_LEN;
+
+    loop {
+        let missing = target.saturating_sub(header.len()) as u64;
+        let read = reader.by_ref().take(missing).read_to_end(&mut header)?;
+        let at_eof = (read as u64) < missing;
+
+        match sniff_image_format(&header) {
+            SniffOutcome::Inconclusive { needed, .. } if !at_eof && needed > header.len() => {
+                target = needed;
+            }
+            SniffOutcome::Inconclusive { best_guess, .. } => {
+                let outcome = best_guess.map_or(SniffOutcome::Unknown, SniffOutcome::Detected);
+                return Ok((outcome, header));
+            }
+            outcome => return Ok((outcome, header)),
         }
     }
 }
 
 /// Determines the actual image format from file magic numbers.
 ///
+/// This is a convenience wrapper around [`sniff_image_format`] for fixed-size headers.
+/// Generic HEIF brands are reported as `DetectedImageFormat::Heic`, as the compatible
+/// brands that could refine them do not fit in 12 bytes.
+///
 /// # Arguments
 ///
 /// * `buffer` - A buffer containing at least the first 12 bytes of the file
@@ -97,66 +557,266 @@ impl DetectedImageFormat {
 /// Returns `Some(DetectedImageFormat)` if a known image format is detected, `None` otherwise
 #[must_use]
 pub fn detect_image_format(buffer: &[u8; 12]) -> Option<DetectedImageFormat> {
-    match buffer {
-        [0xFF, 0xD8, 0xFF, ..] => Some(DetectedImageFormat::Jpeg),
-        [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some(DetectedImageFormat::Png),
-        [0x52, 0x49, 0x46, 0x46, _, _, _, _, 0x57, 0x45, 0x42, 0x50] => {
-            Some(DetectedImageFormat::WebP)
-        }
-        [0xFF, 0x0A, ..] => Some(DetectedImageFormat::Jxl),
-        _ => None,
-    }
+    sniff_image_format(buffer)
+        .best_match()
+        .map(|found| found.format)
 }
 
 /// Determines if the given path is an image file by checking both extension and file contents.
 #[must_use = "Determines if the path is an image file and the result should be checked"]
 pub fn is_image_file(path: &Path) -> bool {
-    // Get the file extension
-    let extension = path
+    // Get the format claimed by the file extension
+    let Some(claimed_format) = path
         .extension()
         .and_then(|e| e.to_str())
-        .map(str::to_lowercase);
-
-    // Check if it's a supported extension
-    let has_valid_extension = extension
-        .as_deref()
-        .is_some_and(|ext| matches!(ext, "jpg" | "jpeg" | "png" | "jxl" | "webp"));
-
-    if !has_valid_extension {
+        .and_then(DetectedImageFormat::from_extension)
+    else {
         return false;
-    }
+    };
 
     // Then verify file contents
-    if let Ok(mut file) = std::fs::File::open(path) {
-        let mut buffer = [0u8; 12];
-        if file.read_exact(&mut buffer).is_ok() {
-            // Detect actual format from magic numbers
-            if let Some(actual_format) = detect_image_format(&buffer) {
-                // Check for extension mismatch
-                if let Some(ext) = extension {
-                    let claimed_format = if ext == "jpg" { "jpeg" } else { &ext };
-                    if claimed_format != actual_format.extension() {
-                        warn!(
-                            "File extension mismatch for {}: claims to be {} but appears to be {}",
-                            path.display(),
-                            claimed_format.to_uppercase(),
-                            actual_format.extension().to_uppercase()
-                        );
-                    }
-                }
-                return true;
+    let Ok(mut file) = std::fs::File::open(path) else {
+        return false;
+    };
+    match sniff_image_format_from_reader(&mut file) {
+        Ok((SniffOutcome::Detected(found), _)) => {
+            // Check for extension mismatch
+            if claimed_format != found.format {
+                warn!(
+                    "File extension mismatch for {}: claims to be {} but appears to be {} ({})",
+                    path.display(),
+                    claimed_format.extension().to_uppercase(),
+                    found.format.extension().to_uppercase(),
+                    found.reason
+                );
             }
-
-            // Try to open with image crate as fallback
-            return image::open(path).is_ok();
+            true
+        }
+        // Try to open with image crate as fallback
+        Ok((SniffOutcome::Unknown | SniffOutcome::Inconclusive { .. }, header)) => {
+            !header.is_empty() && image::open(path).is_ok()
         }
+        Err(_) => false,
     }
+}
+
+/// Opens and decodes an image, choosing the decoder from the file contents.
+///
+/// JPEG XL files are decoded with `jxl-oxide`; everything else goes through the `image`
+/// crate. The extension is only consulted when the contents are not recognized, so
+/// misnamed files still open.
+///
+/// # Arguments
+///
+/// * `path` - Path to the image file
+///
+/// # Returns
+///
+/// Returns the decoded image
+///
+/// # Errors
+///
+/// Returns an error if:
+/// * The file cannot be opened or read
+/// * The format is not supported
+/// * The image data is invalid or corrupted
+pub fn open_image(path: &Path) -> Result<DynamicImage> {
+    open_image_with_icc_profile(path).map(|(img, _)| img)
+}
+
+/// Opens and decodes an image like [`open_image`], also returning its embedded ICC profile.
+///
+/// Profiles are read from PNG, JPEG, WebP, TIFF and AVIF files. For JPEG XL the
+/// profile describes the decoded pixels and is `None` when they are sRGB.
+///
+/// # Arguments
+///
+/// * `path` - Path to the image file
+///
+/// # Returns
+///
+/// Returns the decoded image and its ICC profile, if it has one
+///
+/// # Errors
+///
+/// Returns an error if:
+/// * The file cannot be opened or read
+/// * The format is not supported
+/// * The image data is invalid or corrupted
+pub fn open_image_with_icc_profile(path: &Path) -> Result<(DynamicImage, Option<Vec<u8>>)> {
+    decode_image_file(path).map(|decoded| (decoded.image, decoded.metadata.icc_profile))
+}
+
+/// Opens and decodes an image like [`open_image`], rotating and flipping it upright
+/// according to its EXIF orientation.
+///
+/// The orientation is read from JPEG, WebP and TIFF files. JPEG XL images are
+/// always decoded upright.
+///
+/// # Arguments
+///
+/// * `path` - Path to the image file
+///
+/// # Returns
+///
+/// Returns the decoded image in display orientation
+///
+/// # Errors
+///
+/// Returns an error if:
+/// * The file cannot be opened or read
+/// * The format is not supported
+/// * The image data is invalid or corrupted
+pub fn open_image_oriented(path: &Path) -> Result<DynamicImage> {
+    let decoded = decode_image_file(path)?;
+    let mut image = decoded.image;
+    image.apply_orientation(decoded.orientation);
+    Ok(image)
+}
+
+/// An image decoded by [`decode_image_file`], with the metadata needed to write it back.
+pub(crate) struct DecodedImage {
+    pub(crate) image: DynamicImage,
+    /// ICC profile, EXIF, XMP and PNG text of the file
+    pub(crate) metadata: ImageMetadata,
+    /// EXIF orientation that has not been applied to `image` yet
+    pub(crate) orientation: Orientation,
+}
+
+/// Reads the EXIF orientation from a decoder, ignoring malformed metadata.
+fn decoder_orientation(decoder: &mut impl ImageDecoder, path: &Path) -> Orientation {
+    decoder.orientation().unwrap_or_else(|e| {
+        warn!(
+            "Ignoring unreadable EXIF orientation in {}: {e}",
+            path.display()
+        );
+        Orientation::NoTransforms
+    })
+}
+
+/// Decodes an image file chosen by content, see [`open_image`].
+pub(crate) fn decode_image_file(path: &Path) -> Result<DecodedImage> {
+    // Metadata is read from the raw container, so the whole file is loaded once
+    let data = std::fs::read(path)
+        .with_context(|| format!("Failed to open image file: {}", path.display()))?;
+    let mut reader = Cursor::new(data.as_slice());
+    let (outcome, _) = sniff_image_format_from_reader(&mut reader)
+        .with_context(|| format!("Failed to read image file: {}", path.display()))?;
+    reader.rewind()?;
+
+    if outcome.format() == Some(DetectedImageFormat::Jxl) {
+        let (image, metadata) = decode_jxl_reader(reader)
+            .with_context(|| format!("Failed to decode JXL image: {}", path.display()))?;
+        // jxl-oxide renders frames with the codestream orientation applied
+        return Ok(DecodedImage {
+            image,
+            metadata,
+            orientation: Orientation::NoTransforms,
+        });
+    }
+
+    let mut metadata = read_container_metadata(&data);
+    let mut image_reader = ImageReader::new(reader).with_guessed_format()?;
+    if image_reader.format().is_none()
+        && let Ok(format) = ImageFormat::from_path(path)
+    {
+        image_reader.set_format(format);
+    }
+    let mut decoder = image_reader.into_decoder().with_context(|| {
+        if cfg!(not(feature = "avif-decode")) && outcome.format() == Some(DetectedImageFormat::Avif)
+        {
+            // The `image` crate only decodes AVIF with its dav1d-backed `avif-native` feature
+            format!(
+                "Failed to decode AVIF image (AVIF decoding needs the `avif-decode` feature): {}",
+                path.display()
+            )
+        } else {
+            format!("Failed to decode image: {}", path.display())
+        }
+    })?;
+    metadata.icc_profile = decoder.icc_profile().unwrap_or_else(|e| {
+        warn!("Ignoring unreadable ICC profile in {}: {e}", path.display());
+        None
+    });
+    let orientation = decoder_orientation(&mut decoder, path);
+    let image = DynamicImage::from_decoder(decoder)
+        .with_context(|| format!("Failed to decode image: {}", path.display()))?;
+    Ok(DecodedImage {
+        image,
+        metadata,
+        orientation,
+    })
+}
+
+/// Rotates and flips an image file upright according to its EXIF orientation.
+///
+/// Images that are already upright are left untouched. Otherwise the pixels are
+/// transformed and the file is rewritten in the format of its extension with the
+/// orientation tag reset, keeping its other metadata where the format can hold it.
+///
+/// Rewriting encodes the pixels again with the preset of the format, so JPEG,
+/// lossy WebP and AVIF files lose a generation of quality (JPEG is written at the
+/// quality of [`ImageFormatOptions::jpeg`](crate::formats::ImageFormatOptions::jpeg)).
+/// To choose the quality, convert the file with
+/// [`convert_image`](crate::formats::convert_image) and
+/// [`ImageFormatOptions::with_auto_orient`](crate::formats::ImageFormatOptions::with_auto_orient)
+/// instead.
+///
+/// # Arguments
+///
+/// * `path` - Path to the image file
+///
+/// # Returns
+///
+/// Returns `true` if the image was rotated or flipped and rewritten
+///
+/// # Errors
+///
+/// Returns an error if:
+/// * The image file cannot be opened or decoded
+/// * The modified image cannot be saved
+pub async fn normalize_orientation(path: &Path) -> Result<bool> {
+    // Decoding and encoding are CPU-bound, so keep them off the async executor
+    let input = path.to_path_buf();
+    let oriented = tokio::task::spawn_blocking(move || {
+        let info = get_image_info(&input)?;
+        if info.orientation == Orientation::NoTransforms {
+            return Ok(None);
+        }
 
-    false
+        info!(
+            "Applying orientation {:?} to {}",
+            info.orientation,
+            input.display()
+        );
+        let output_format = detect_output_format(&input)?;
+        let decoded = decode_image_file(&input)?;
+        let mut image = decoded.image;
+        image.apply_orientation(decoded.orientation);
+        let mut metadata = decoded.metadata;
+        metadata.reset_orientation();
+        let options = default_options_for(output_format).with_metadata(MetadataPolicy::KeepAll);
+        let encoded = encode_image(image, metadata, output_format, &options)?;
+        Ok::<_, anyhow::Error>(Some((output_format, encoded)))
+    })
+    .await
+    .context("Orientation task failed")??;
+
+    let Some((output_format, encoded)) = oriented else {
+        return Ok(false);
+    };
+    write_output(path, output_format, encoded)
+        .await
+        .context("Failed to save image")?;
+    Ok(true)
 }
 
 /// Removes transparency from an image, making transparent pixels black and fully opaque.
 ///
+/// The image is written back in the format of its extension, keeping its ICC
+/// profile and other metadata where the format can hold them (see
+/// [`ImageFormatOptions`](crate::formats::ImageFormatOptions)).
+///
 /// # Arguments
 ///
 /// * `path` - Path to the image file
@@ -177,7 +837,9 @@ pub async fn remove_transparency(path: &Path) -> Result<()> {
 
     info!("Processing image: {}", path.display());
 
-    let img = image::open(path).context("Failed to open image")?;
+    let output_format = detect_output_format(path)?;
+    let decoded = decode_image_file(path).context("Failed to open image")?;
+    let img = decoded.image;
     let (width, height) = img.dimensions();
 
     let mut new_image = ImageBuffer::new(width, height);
@@ -191,14 +853,136 @@ pub async fn remove_transparency(path: &Path) -> Result<()> {
         new_image.put_pixel(x, y, new_pixel);
     }
 
-    new_image.save(path).context("Failed to save image")?;
+    let options = default_options_for(output_format).with_metadata(MetadataPolicy::KeepAll);
+    write_image(
+        DynamicImage::ImageRgba8(new_image),
+        decoded.metadata,
+        path,
+        output_format,
+        &options,
+    )
+    .await
+    .context("Failed to save image")?;
     info!("Processed and saved: {}", path.display());
 
     Ok(())
 }
 
+/// Header information of an image file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageInfo {
+    /// Width in pixels
+    pub width: u32,
+    /// Height in pixels
+    pub height: u32,
+    /// Format detected from the file contents, or `None` for formats the sniffer
+    /// does not know (such as TGA) that the `image` crate still reads
+    pub format: Option<DetectedImageFormat>,
+    /// Colour type `open_image` would decode the image to
+    pub color_type: ColorType,
+    /// EXIF orientation; always `NoTransforms` for JPEG XL, which is decoded upright
+    pub orientation: Orientation,
+}
+
+impl ImageInfo {
+    /// Returns the dimensions after applying the EXIF orientation.
+    #[must_use]
+    pub fn oriented_dimensions(&self) -> (u32, u32) {
+        match self.orientation {
+            Orientation::Rotate90
+            | Orientation::Rotate270
+            | Orientation::Rotate90FlipH
+            | Orientation::Rotate270FlipH => (self.height, self.width),
+            _ => (self.width, self.height),
+        }
+    }
+}
+
+/// Reads the dimensions, format, colour type and orientation of an image from its headers.
+///
+/// Only as much of the file as the headers need is read; pixel data is never
+/// decoded. JPEG XL headers are parsed with `jxl-oxide`, everything else through
+/// the `image` crate decoders. Like `open_image`, the format is taken from the
+/// contents and the extension is only a fallback.
+///
+/// # Arguments
+///
+/// * `path` - Path to the image file
+///
+/// # Returns
+///
+/// Returns an `ImageInfo` describing the image
+///
+/// # Errors
+///
+/// Returns an error if:
+/// * The file cannot be opened or read
+/// * The format is not supported
+/// * The headers are invalid or truncated
+pub fn get_image_info(path: &Path) -> Result<ImageInfo> {
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open image file: {}", path.display()))?;
+    let mut reader = BufReader::new(file);
+    let (outcome, _) = sniff_image_format_from_reader(&mut reader)
+        .with_context(|| format!("Failed to read image file: {}", path.display()))?;
+    reader.rewind()?;
+    let format = outcome.format();
+
+    if format == Some(DetectedImageFormat::Jxl) {
+        let (width, height, color_type) = read_jxl_header(reader)
+            .with_context(|| format!("Failed to read JXL header: {}", path.display()))?;
+        return Ok(ImageInfo {
+            width,
+            height,
+            format,
+            color_type,
+            orientation: Orientation::NoTransforms,
+        });
+    }
+
+    let mut image_reader = ImageReader::new(reader).with_guessed_format()?;
+    if image_reader.format().is_none()
+        && let Ok(format) = ImageFormat::from_path(path)
+    {
+        image_reader.set_format(format);
+    }
+    let mut decoder = image_reader
+        .into_decoder()
+        .with_context(|| format!("Failed to read image header: {}", path.display()))?;
+    let (width, height) = decoder.dimensions();
+    Ok(ImageInfo {
+        width,
+        height,
+        format,
+        color_type: decoder.color_type(),
+        orientation: decoder_orientation(&mut decoder, path),
+    })
+}
+
+/// Reads the header information of many images, one result per file.
+///
+/// Failures are reported per file, so one unreadable image does not hide the
+/// others. See [`get_image_info`] for how each file is read.
+///
+/// # Arguments
+///
+/// * `paths` - Paths of the image files
+///
+/// # Returns
+///
+/// Returns `(path, result)` pairs in the order of `paths`
+#[must_use]
+pub fn get_image_info_batch(paths: &[PathBuf]) -> Vec<(PathBuf, Result<ImageInfo>)> {
+    paths
+        .iter()
+        .map(|path| (path.clone(), get_image_info(path)))
+        .collect()
+}
+
 /// Gets the dimensions of an image.
 ///
+/// Only the image headers are read (see [`get_image_info`]).
+///
 /// # Arguments
 ///
 /// * `path` - Path to the image file
@@ -209,10 +993,10 @@ pub async fn remove_transparency(path: &Path) -> Result<()> {
 ///
 /// # Errors
 ///
-/// Returns an error if the image file cannot be opened
+/// Returns an error if the image file cannot be opened or its headers cannot be read
 pub fn get_image_dimensions(path: &Path) -> Result<(u32, u32)> {
-    let img = image::open(path).context("Failed to open image")?;
-    Ok(img.dimensions())
+    let info = get_image_info(path).context("Failed to open image")?;
+    Ok((info.width, info.height))
 }
 
 /// Removes letterboxing from an image by cropping black borders.
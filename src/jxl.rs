@@ -2,10 +2,15 @@
 //!
 //! This module provides functionality for working with JPEG XL (JXL) image files, including:
 //! - JXL file detection
-//! - Conversion from JXL to PNG format
+//! - In-memory decoding to `DynamicImage` at the source bit depth
+//! - Header information and EXIF, XMP and ICC metadata without rendering
+//! - Conversion from JXL to PNG format, keeping the metadata
 //! - Processing JXL files with custom transformations
+//! - Policy-driven processing that keeps the original unless the PNG was verified
+//! - Lossless JPEG recompression and bit-exact JPEG reconstruction
 //!
-//! The module uses the `jxl-oxide` crate for JXL decoding and supports both RGB and RGBA color formats.
+//! The module uses the `jxl-oxide` crate for JXL decoding and supports grayscale and RGB images,
+//! with or without alpha.
 //!
 //! # Examples
 //!
@@ -32,17 +37,33 @@
 
 #![warn(clippy::all, clippy::pedantic)]
 
+use crate::extensions::CollisionPolicy;
+use crate::image_processing::{DetectedImageFormat, sniff_image_format_from_reader};
+use crate::metadata::{ImageMetadata, XMP_PNG_KEYWORD};
+use crate::numeric::{f32_to_u8, f32_to_u16};
 use anyhow::{Context, Result};
-use image::{ImageBuffer, Rgba};
-use jxl_oxide::{JxlImage, PixelFormat};
-use log::info;
+use image::{ColorType, DynamicImage, GenericImageView, ImageBuffer};
+use jxl_oxide::image::BitDepth;
+use jxl_oxide::{AuxBoxData, InitializeResult, JxlImage, PixelFormat};
+use log::{info, warn};
+use std::io::Read;
 use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+
+/// Signature of a bare JPEG XL codestream
+pub const JXL_CODESTREAM_SIGNATURE: [u8; 2] = [0xFF, 0x0A];
+
+/// Signature of a JPEG XL file using the ISOBMFF container (a `JXL ` signature box)
+pub const JXL_CONTAINER_SIGNATURE: [u8; 12] = [
+    0x00, 0x00, 0x00, 0x0C, 0x4A, 0x58, 0x4C, 0x20, 0x0D, 0x0A, 0x87, 0x0A,
+];
 
 /// Checks if a file is a JPEG XL image by examining its file extension.
 ///
 /// This function performs a case-insensitive check for the ".jxl" extension.
 /// Note that this is a simple extension check and does not verify the file contents.
-/// For more thorough validation, use `convert_jxl_to_png` which will attempt to decode the file.
+/// Use `is_jxl_file_verified` to also check the file signature, or `convert_jxl_to_png`
+/// which will attempt to decode the file.
 ///
 /// # Arguments
 ///
@@ -71,16 +92,561 @@ pub fn is_jxl_file(path: &Path) -> bool {
         .is_some_and(|ext| ext.eq_ignore_ascii_case("jxl"))
 }
 
-/// Converts a JPEG XL image to PNG format.
+/// Checks if a file is a JPEG XL image by examining both its extension and its contents.
+///
+/// In addition to the extension check performed by `is_jxl_file`, this reads the start
+/// of the file and accepts it only if it begins with either the bare codestream signature
+/// (`FF 0A`) or the ISOBMFF container signature (`00 00 00 0C 4A 58 4C 20 0D 0A 87 0A`).
+///
+/// # Arguments
+///
+/// * `path` - Path to the file to check
+///
+/// # Returns
+///
+/// Returns `true` if the file has a `.jxl` extension and a JPEG XL signature, `false` otherwise
+/// (including when the file cannot be read)
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use std::path::Path;
+/// use imx::jxl::is_jxl_file_verified;
+///
+/// if is_jxl_file_verified(Path::new("image.jxl")) {
+///     // The file really contains JPEG XL data
+/// }
+/// ```
+#[must_use]
+pub fn is_jxl_file_verified(path: &Path) -> bool {
+    is_jxl_file(path) && has_jxl_signature(path)
+}
+
+/// Reads the start of a file and checks it for a JPEG XL signature.
+fn has_jxl_signature(path: &Path) -> bool {
+    std::fs::File::open(path)
+        .and_then(|mut file| sniff_image_format_from_reader(&mut file))
+        .is_ok_and(|(outcome, _)| outcome.format() == Some(DetectedImageFormat::Jxl))
+}
+
+/// Bit depth of PNG files written from JPEG XL images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PngBitDepth {
+    /// 8 bits per channel
+    #[default]
+    Eight,
+    /// 16 bits per channel, keeping the precision of high bit depth sources
+    Sixteen,
+}
+
+/// Decodes JPEG XL data into a `DynamicImage`, keeping the source precision.
+///
+/// The first frame is rendered and mapped to the closest `image` layout (see
+/// [`crate::jxl_animation`] for the other frames of animated images):
+/// * Integer samples up to 8 bits become `Luma8`, `LumaA8`, `Rgb8` or `Rgba8`
+/// * Integer samples up to 16 bits become `Luma16`, `LumaA16`, `Rgb16` or `Rgba16`
+/// * Float samples (and wider integers) become `Rgb32F` or `Rgba32F`; grayscale is
+///   expanded to RGB because `image` has no float grayscale layout
+///
+/// Integer samples are rounded and clamped with the `numeric` module.
+///
+/// # Arguments
+///
+/// * `data` - Complete JPEG XL file contents
+///
+/// # Returns
+///
+/// Returns the decoded image
+///
+/// # Errors
+///
+/// Returns an error if:
+/// * The JXL data is invalid or corrupted
+/// * The JXL frame cannot be rendered
+/// * The pixel format is CMYK, which `image` cannot represent
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use imx::jxl::decode_jxl;
+///
+/// fn example() -> anyhow::Result<()> {
+///     let data = std::fs::read("image.jxl")?;
+///     let image = decode_jxl(&data)?;
+///     println!("{}x{} {:?}", image.width(), image.height(), image.color());
+///     Ok(())
+/// }
+/// ```
+pub fn decode_jxl(data: &[u8]) -> Result<DynamicImage> {
+    decode_jxl_with_metadata(data).map(|(image, _)| image)
+}
+
+/// Decodes JPEG XL data like [`decode_jxl`], also returning the ICC profile of the
+/// decoded pixels and the EXIF and XMP boxes.
+///
+/// The profile is `None` when the pixels are sRGB, and for grayscale float images,
+/// which are expanded to RGB and no longer match their gray profile. The decoded
+/// pixels are already upright, so the EXIF orientation is reset; JPEG XL readers
+/// ignore it anyway.
+pub(crate) fn decode_jxl_with_metadata(data: &[u8]) -> Result<(DynamicImage, ImageMetadata)> {
+    let image = JxlImage::read_with_defaults(data)
+        .map_err(|e| anyhow::anyhow!("Failed to decode JXL data: {e}"))?;
+    let frame_count = image.num_loaded_keyframes();
+    if frame_count > 1 {
+        warn!("JXL image has {frame_count} frames, only the first one is decoded");
+    }
+    let decoded = render_keyframe(&image, 0)?;
+    let expanded_gray = image.pixel_format().is_grayscale() && decoded.color().has_color();
+    let icc_profile = rendered_icc_profile(&image).filter(|_| !expanded_gray);
+    let mut metadata = read_aux_boxes(&image).unwrap_or_else(|e| {
+        warn!("Ignoring JXL metadata boxes: {e}");
+        ImageMetadata::default()
+    });
+    metadata.icc_profile = icc_profile;
+    metadata.reset_orientation();
+    Ok((decoded, metadata))
+}
+
+/// Returns the ICC profile of the colours `jxl-oxide` renders, or `None` when they are sRGB.
+fn rendered_icc_profile(image: &JxlImage) -> Option<Vec<u8>> {
+    (image.rendered_cicp() != Some(SRGB_CICP)).then(|| image.rendered_icc())
+}
+
+/// Renders one displayed frame of a loaded JPEG XL image, keeping the source precision.
+///
+/// The layout rules are the ones documented on [`decode_jxl`].
+pub(crate) fn render_keyframe(image: &JxlImage, index: usize) -> Result<DynamicImage> {
+    let pixel_format = image.pixel_format();
+    let bit_depth = image.image_header().metadata.bit_depth;
+
+    let render = image
+        .render_frame(index)
+        .map_err(|e| anyhow::anyhow!("Failed to render JXL frame {index}: {e}"))?;
+    let mut stream = render.stream();
+    let (width, height) = (stream.width(), stream.height());
+    let mut samples = vec![0.0f32; width as usize * height as usize * stream.channels() as usize];
+    stream.write_to_buffer(&mut samples);
+
+    let decoded = match bit_depth {
+        BitDepth::IntegerSample { bits_per_sample } if bits_per_sample <= 8 => {
+            let data = samples.iter().map(|&v| f32_to_u8(v * 255.0)).collect();
+            integer_image_8(width, height, pixel_format, data)?
+        }
+        BitDepth::IntegerSample { bits_per_sample } if bits_per_sample <= 16 => {
+            let data = samples.iter().map(|&v| f32_to_u16(v * 65535.0)).collect();
+            integer_image_16(width, height, pixel_format, data)?
+        }
+        _ => float_image(width, height, pixel_format, samples)?,
+    };
+    decoded.context("Decoded JXL buffer does not match the image dimensions")
+}
+
+/// Wraps 8-bit samples in the `DynamicImage` variant matching `pixel_format`.
+fn integer_image_8(
+    width: u32,
+    height: u32,
+    pixel_format: PixelFormat,
+    data: Vec<u8>,
+) -> Result<Option<DynamicImage>> {
+    Ok(match pixel_format {
+        PixelFormat::Gray => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLuma8)
+        }
+        PixelFormat::Graya => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLumaA8)
+        }
+        PixelFormat::Rgb => ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb8),
+        PixelFormat::Rgba => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgba8)
+        }
+        other => anyhow::bail!("Unsupported JXL pixel format: {other:?}"),
+    })
+}
+
+/// Wraps 16-bit samples in the `DynamicImage` variant matching `pixel_format`.
+fn integer_image_16(
+    width: u32,
+    height: u32,
+    pixel_format: PixelFormat,
+    data: Vec<u16>,
+) -> Result<Option<DynamicImage>> {
+    Ok(match pixel_format {
+        PixelFormat::Gray => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLuma16)
+        }
+        PixelFormat::Graya => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLumaA16)
+        }
+        PixelFormat::Rgb => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb16)
+        }
+        PixelFormat::Rgba => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgba16)
+        }
+        other => anyhow::bail!("Unsupported JXL pixel format: {other:?}"),
+    })
+}
+
+/// Wraps float samples in an `Rgb32F` or `Rgba32F` image, expanding grayscale to RGB.
+fn float_image(
+    width: u32,
+    height: u32,
+    pixel_format: PixelFormat,
+    samples: Vec<f32>,
+) -> Result<Option<DynamicImage>> {
+    Ok(match pixel_format {
+        PixelFormat::Gray => {
+            let data = samples.iter().flat_map(|&v| [v, v, v]).collect();
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb32F)
+        }
+        PixelFormat::Graya => {
+            let data = samples
+                .chunks_exact(2)
+                .flat_map(|ga| [ga[0], ga[0], ga[0], ga[1]])
+                .collect();
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgba32F)
+        }
+        PixelFormat::Rgb => {
+            ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageRgb32F)
+        }
+        PixelFormat::Rgba => {
+            ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageRgba32F)
+        }
+        other => anyhow::bail!("Unsupported JXL pixel format: {other:?}"),
+    })
+}
+
+/// Decodes a JPEG XL stream and its metadata with [`decode_jxl_with_metadata`].
+pub(crate) fn decode_jxl_reader<R: Read>(mut reader: R) -> Result<(DynamicImage, ImageMetadata)> {
+    let mut data = Vec::new();
+    reader
+        .read_to_end(&mut data)
+        .context("Failed to read JXL data")?;
+    decode_jxl_with_metadata(&data)
+}
+
+/// Reads the dimensions and decoded colour type of a JPEG XL stream from its headers.
+///
+/// Data is read in small chunks only until the image header (and ICC profile, if
+/// any) has been parsed; frames are neither read nor rendered. The colour type is
+/// the one [`decode_jxl`] produces.
+pub(crate) fn read_jxl_header<R: Read>(mut reader: R) -> Result<(u32, u32, ColorType)> {
+    let mut uninit = JxlImage::builder().build_uninit();
+    let mut buffer = [0u8; 4096];
+    let image = loop {
+        let read = reader
+            .read(&mut buffer)
+            .context("Failed to read JXL data")?;
+        if read == 0 {
+            anyhow::bail!("JXL data ends before the image header");
+        }
+        uninit
+            .feed_bytes(&buffer[..read])
+            .map_err(|e| anyhow::anyhow!("Failed to decode JXL data: {e}"))?;
+        match uninit
+            .try_init()
+            .map_err(|e| anyhow::anyhow!("Failed to decode JXL header: {e}"))?
+        {
+            InitializeResult::Initialized(image) => break image,
+            InitializeResult::NeedMoreData(more) => uninit = more,
+        }
+    };
+
+    let bits = match image.image_header().metadata.bit_depth {
+        BitDepth::IntegerSample { bits_per_sample } => Some(bits_per_sample),
+        BitDepth::FloatSample { .. } => None,
+    };
+    let color_type = match (image.pixel_format(), bits) {
+        (PixelFormat::Gray, Some(0..=8)) => ColorType::L8,
+        (PixelFormat::Graya, Some(0..=8)) => ColorType::La8,
+        (PixelFormat::Rgb, Some(0..=8)) => ColorType::Rgb8,
+        (PixelFormat::Rgba, Some(0..=8)) => ColorType::Rgba8,
+        (PixelFormat::Gray, Some(9..=16)) => ColorType::L16,
+        (PixelFormat::Graya, Some(9..=16)) => ColorType::La16,
+        (PixelFormat::Rgb, Some(9..=16)) => ColorType::Rgb16,
+        (PixelFormat::Rgba, Some(9..=16)) => ColorType::Rgba16,
+        (PixelFormat::Gray | PixelFormat::Rgb, _) => ColorType::Rgb32F,
+        (PixelFormat::Graya | PixelFormat::Rgba, _) => ColorType::Rgba32F,
+        (other, _) => anyhow::bail!("Unsupported JXL pixel format: {other:?}"),
+    };
+    Ok((image.width(), image.height(), color_type))
+}
+
+/// CICP code points (primaries, transfer, matrix, full range) of sRGB
+const SRGB_CICP: [u8; 4] = [1, 13, 0, 1];
+
+/// Colour encoding declared in a JPEG XL image header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JxlColorEncoding {
+    /// Whether the colours are described by an embedded ICC profile
+    pub has_icc_profile: bool,
+    /// Whether the declared encoding is sRGB
+    pub is_srgb: bool,
+    /// Whether the samples are stored in the XYB colour space (typical for lossy images)
+    pub xyb_encoded: bool,
+    /// CICP code points (primaries, transfer, matrix, full range) of the
+    /// encoding, when it has a CICP equivalent
+    pub cicp: Option<[u8; 4]>,
+    /// Short description, e.g. `"RGB, D65, sRGB primaries, sRGB transfer"` or
+    /// `"RGB, ICC profile"`
+    pub description: String,
+}
+
+/// Basic information from a JPEG XL image header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct JxlInfo {
+    /// Width in pixels, with the orientation applied
+    pub width: u32,
+    /// Height in pixels, with the orientation applied
+    pub height: u32,
+    /// Bits per colour sample
+    pub bits_per_sample: u32,
+    /// Whether colour samples are floating point
+    pub float_samples: bool,
+    /// Whether the image is grayscale
+    pub grayscale: bool,
+    /// Whether the image has an alpha channel
+    pub has_alpha: bool,
+    /// Whether the header declares an animation (see [`crate::jxl_animation`])
+    pub animated: bool,
+    /// Orientation from the codestream header, using the EXIF values 1-8
+    pub orientation: u32,
+    /// Declared colour encoding
+    pub color_encoding: JxlColorEncoding,
+}
+
+/// Header information and embedded metadata of a JPEG XL image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JxlMetadata {
+    /// Basic header information
+    pub info: JxlInfo,
+    /// EXIF data from the `Exif` box, starting at the TIFF header (`II*\0` or `MM\0*`)
+    pub exif: Option<Vec<u8>>,
+    /// XMP packet from the first `xml ` box
+    pub xmp: Option<Vec<u8>>,
+    /// ICC profile embedded in the codestream
+    pub icc_profile: Option<Vec<u8>>,
+}
+
+/// Describes a built-in colour encoding in a few words.
+fn describe_color_encoding(encoding: &jxl_oxide::color::ColourEncoding) -> String {
+    use jxl_oxide::color::{ColourEncoding, ColourSpace, Primaries, TransferFunction, WhitePoint};
+
+    let color_space = |space: ColourSpace| match space {
+        ColourSpace::Rgb => "RGB",
+        ColourSpace::Grey => "Gray",
+        ColourSpace::Xyb => "XYB",
+        ColourSpace::Unknown => "Unknown",
+    };
+    let enum_encoding = match encoding {
+        ColourEncoding::IccProfile(space) => {
+            return format!("{}, ICC profile", color_space(*space));
+        }
+        ColourEncoding::Enum(enum_encoding) => enum_encoding,
+    };
+
+    let white_point = match enum_encoding.white_point {
+        WhitePoint::D65 => "D65",
+        WhitePoint::Custom(_) => "custom white point",
+        WhitePoint::E => "illuminant E",
+        WhitePoint::Dci => "DCI white point",
+    };
+    let primaries = match enum_encoding.primaries {
+        Primaries::Srgb => "sRGB primaries",
+        Primaries::Custom { .. } => "custom primaries",
+        Primaries::Bt2100 => "BT.2100 primaries",
+        Primaries::P3 => "P3 primaries",
+    };
+    let transfer = match enum_encoding.tf {
+        TransferFunction::Gamma { g, inverted } => {
+            let gamma = f64::from(g) / 1e7;
+            format!("gamma {:.2}", if inverted { 1.0 / gamma } else { gamma })
+        }
+        TransferFunction::Bt709 => "BT.709 transfer".to_string(),
+        TransferFunction::Unknown => "unknown transfer".to_string(),
+        TransferFunction::Linear => "linear transfer".to_string(),
+        TransferFunction::Srgb => "sRGB transfer".to_string(),
+        TransferFunction::Pq => "PQ transfer".to_string(),
+        TransferFunction::Dci => "DCI transfer".to_string(),
+        TransferFunction::Hlg => "HLG transfer".to_string(),
+    };
+    let space = color_space(enum_encoding.colour_space);
+    match enum_encoding.colour_space {
+        // Gray and XYB images have no primaries of their own
+        ColourSpace::Grey => format!("{space}, {white_point}, {transfer}"),
+        ColourSpace::Xyb => space.to_string(),
+        _ => format!("{space}, {white_point}, {primaries}, {transfer}"),
+    }
+}
+
+/// Collects the header information and metadata boxes of a loaded image.
+fn collect_metadata(image: &JxlImage) -> Result<JxlMetadata> {
+    let metadata = &image.image_header().metadata;
+    let (bits_per_sample, float_samples) = match metadata.bit_depth {
+        BitDepth::IntegerSample { bits_per_sample } => (bits_per_sample, false),
+        BitDepth::FloatSample {
+            bits_per_sample, ..
+        } => (bits_per_sample, true),
+    };
+    let encoding = &metadata.colour_encoding;
+    let info = JxlInfo {
+        width: image.width(),
+        height: image.height(),
+        bits_per_sample,
+        float_samples,
+        grayscale: metadata.grayscale(),
+        has_alpha: metadata.alpha().is_some(),
+        animated: metadata.animation.is_some(),
+        orientation: metadata.orientation,
+        color_encoding: JxlColorEncoding {
+            has_icc_profile: encoding.want_icc(),
+            is_srgb: encoding.is_srgb(),
+            xyb_encoded: metadata.xyb_encoded,
+            cicp: encoding.cicp(),
+            description: describe_color_encoding(encoding),
+        },
+    };
+
+    let aux_boxes = read_aux_boxes(image)?;
+    Ok(JxlMetadata {
+        info,
+        exif: aux_boxes.exif,
+        xmp: aux_boxes.xmp,
+        icc_profile: image.original_icc().map(<[u8]>::to_vec),
+    })
+}
+
+/// Reads the EXIF (from its TIFF header on) and XMP boxes of a loaded image.
+fn read_aux_boxes(image: &JxlImage) -> Result<ImageMetadata> {
+    let aux_boxes = image.aux_boxes();
+    let exif = match aux_boxes
+        .first_exif()
+        .map_err(|e| anyhow::anyhow!("Invalid JXL Exif box: {e}"))?
+    {
+        AuxBoxData::Data(exif) => {
+            let offset = exif.tiff_header_offset() as usize;
+            Some(exif.payload()[offset..].to_vec())
+        }
+        AuxBoxData::Decoding | AuxBoxData::NotFound => None,
+    };
+    let xmp = match aux_boxes.first_xml() {
+        AuxBoxData::Data(xmp) => Some(xmp.to_vec()),
+        AuxBoxData::Decoding | AuxBoxData::NotFound => None,
+    };
+    Ok(ImageMetadata {
+        exif,
+        xmp,
+        ..ImageMetadata::default()
+    })
+}
+
+/// Reads the header information, EXIF, XMP and ICC profile of a JPEG XL image.
+///
+/// The codestream is parsed, but no frame is rendered.
+///
+/// # Arguments
+///
+/// * `data` - Complete JPEG XL file contents
+///
+/// # Returns
+///
+/// Returns a `JxlMetadata`; metadata the file does not carry is `None`
+///
+/// # Errors
+///
+/// Returns an error if the JXL data is invalid or its `Exif` box is malformed
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use imx::jxl::read_jxl_metadata;
 ///
-/// This function performs the following steps:
-/// 1. Reads the JXL file from disk
-/// 2. Decodes the JXL data using `jxl-oxide`
-/// 3. Converts the pixel data to RGBA format
-/// 4. Saves the result as a PNG file
+/// fn example() -> anyhow::Result<()> {
+///     let metadata = read_jxl_metadata(&std::fs::read("image.jxl")?)?;
+///     println!(
+///         "{}x{}, {}",
+///         metadata.info.width, metadata.info.height, metadata.info.color_encoding.description
+///     );
+///     if let Some(exif) = &metadata.exif {
+///         println!("{} bytes of EXIF", exif.len());
+///     }
+///     Ok(())
+/// }
+/// ```
+pub fn read_jxl_metadata(data: &[u8]) -> Result<JxlMetadata> {
+    let image = JxlImage::read_with_defaults(data)
+        .map_err(|e| anyhow::anyhow!("Failed to decode JXL data: {e}"))?;
+    collect_metadata(&image)
+}
+
+/// Decodes JPEG XL data and encodes its first frame as an RGBA PNG.
+///
+/// EXIF is written to an `eXIf` chunk and XMP to an `iTXt` chunk; malformed
+/// metadata boxes are skipped with a warning. The ICC profile
+/// of the rendered colours goes into an `iCCP` chunk unless they are sRGB, or
+/// the source is grayscale (a gray profile cannot describe RGBA output).
+///
+/// Returns the PNG data and the image dimensions.
+fn encode_jxl_as_png(data: &[u8], bit_depth: PngBitDepth) -> Result<(Vec<u8>, (u32, u32))> {
+    let image = JxlImage::read_with_defaults(data)
+        .map_err(|e| anyhow::anyhow!("Failed to decode JXL data: {e}"))?;
+    let frame_count = image.num_loaded_keyframes();
+    if frame_count > 1 {
+        warn!("JXL image has {frame_count} frames, only the first one is converted");
+    }
+    // Malformed metadata should not stop the pixels from being converted
+    let metadata = read_aux_boxes(&image).unwrap_or_else(|e| {
+        warn!("Ignoring JXL metadata boxes: {e}");
+        ImageMetadata::default()
+    });
+    let rendered = render_keyframe(&image, 0)?;
+    let (width, height) = (rendered.width(), rendered.height());
+
+    let icc_profile = if image.pixel_format().is_grayscale() {
+        None
+    } else {
+        rendered_icc_profile(&image)
+    };
+
+    let mut info = png::Info::with_size(width, height);
+    info.color_type = png::ColorType::Rgba;
+    info.icc_profile = icc_profile.map(Into::into);
+    info.exif_metadata = metadata.exif.map(Into::into);
+    let (samples, png_depth) = match bit_depth {
+        PngBitDepth::Eight => (rendered.to_rgba8().into_raw(), png::BitDepth::Eight),
+        PngBitDepth::Sixteen => (
+            // PNG stores 16-bit samples big-endian
+            rendered
+                .to_rgba16()
+                .into_raw()
+                .into_iter()
+                .flat_map(u16::to_be_bytes)
+                .collect(),
+            png::BitDepth::Sixteen,
+        ),
+    };
+    info.bit_depth = png_depth;
+
+    let mut output = Vec::new();
+    let mut encoder = png::Encoder::with_info(&mut output, info)?;
+    if let Some(xmp) = metadata.xmp {
+        match String::from_utf8(xmp) {
+            Ok(xmp) => encoder.add_itxt_chunk(XMP_PNG_KEYWORD.to_string(), xmp)?,
+            Err(_) => warn!("Not copying XMP metadata to PNG: it is not valid UTF-8"),
+        }
+    }
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&samples)?;
+    writer.finish()?;
+    Ok((output, (width, height)))
+}
+
+/// Converts a JPEG XL image to an 8-bit RGBA PNG.
 ///
-/// The function supports both RGB and RGBA JXL images. For RGB images,
-/// the alpha channel will be set to fully opaque (255).
+/// This is [`convert_jxl_to_png_with_depth`] with [`PngBitDepth::Eight`]. Grayscale
+/// images are expanded to RGB, and images without alpha get a fully opaque (255)
+/// alpha channel.
 ///
 /// # Arguments
 ///
@@ -97,7 +663,7 @@ pub fn is_jxl_file(path: &Path) -> bool {
 /// * The JXL file cannot be read from disk
 /// * The JXL data is invalid or corrupted
 /// * The JXL frame cannot be rendered
-/// * The pixel format is not RGB or RGBA
+/// * The pixel format is CMYK
 /// * The PNG file cannot be saved to disk
 ///
 /// # Examples
@@ -114,6 +680,37 @@ pub fn is_jxl_file(path: &Path) -> bool {
 /// }
 /// ```
 pub async fn convert_jxl_to_png(input_path: &Path, output_path: &Path) -> Result<()> {
+    convert_jxl_to_png_with_depth(input_path, output_path, PngBitDepth::Eight).await
+}
+
+/// Converts a JPEG XL image to an RGBA PNG with the given bit depth.
+///
+/// The first frame is rendered at the source precision, so 16-bit and float sources
+/// keep it when written with [`PngBitDepth::Sixteen`]. EXIF and XMP metadata are
+/// copied into `eXIf` and `iTXt` chunks, and the ICC profile of images that are not
+/// sRGB into an `iCCP` chunk (see [`read_jxl_metadata`]).
+///
+/// # Arguments
+///
+/// * `input_path` - Path to the input JXL file
+/// * `output_path` - Path where the PNG file should be saved
+/// * `bit_depth` - Bits per channel of the PNG file
+///
+/// # Returns
+///
+/// Returns a `Result<()>` indicating success or failure
+///
+/// # Errors
+///
+/// Returns an error if:
+/// * The JXL file cannot be read from disk
+/// * The JXL data cannot be decoded (see [`decode_jxl`])
+/// * The PNG file cannot be saved to disk
+pub async fn convert_jxl_to_png_with_depth(
+    input_path: &Path,
+    output_path: &Path,
+    bit_depth: PngBitDepth,
+) -> Result<()> {
     info!(
         "Converting JXL to PNG: {} -> {}",
         input_path.display(),
@@ -125,58 +722,13 @@ pub async fn convert_jxl_to_png(input_path: &Path, output_path: &Path) -> Result
         .await
         .with_context(|| format!("Failed to read JXL file: {}", input_path.display()))?;
 
-    // Decode JXL
-    let image = JxlImage::read_with_defaults(&jxl_data[..]).map_err(|e| {
-        anyhow::anyhow!("Failed to decode JXL file {}: {}", input_path.display(), e)
-    })?;
-
-    // Convert to RGBA
-    let (width, height) = (image.width(), image.height());
-    let mut rgba: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::new(width, height);
-
-    let render = image.render_frame(0).map_err(|e| {
-        anyhow::anyhow!("Failed to render JXL frame {}: {}", input_path.display(), e)
-    })?;
-    let mut stream = render.stream();
-
-    // Create a buffer to hold the pixel data
-    let channels = stream.channels() as usize;
-    let mut pixel_data = vec![0.0f32; width as usize * height as usize * channels];
-    stream.write_to_buffer(&mut pixel_data);
-
-    // Convert pixel data to RGBA
-    for y in 0..height {
-        for x in 0..width {
-            let pixel_idx = ((y * width + x) as usize) * channels;
-            let pixel = match image.pixel_format() {
-                PixelFormat::Rgba => {
-                    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-                    let rgba = [
-                        (pixel_data[pixel_idx] * 255.0) as u8,
-                        (pixel_data[pixel_idx + 1] * 255.0) as u8,
-                        (pixel_data[pixel_idx + 2] * 255.0) as u8,
-                        (pixel_data[pixel_idx + 3] * 255.0) as u8,
-                    ];
-                    Rgba(rgba)
-                }
-                PixelFormat::Rgb => {
-                    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-                    let rgb = [
-                        (pixel_data[pixel_idx] * 255.0) as u8,
-                        (pixel_data[pixel_idx + 1] * 255.0) as u8,
-                        (pixel_data[pixel_idx + 2] * 255.0) as u8,
-                        255,
-                    ];
-                    Rgba(rgb)
-                }
-                _ => anyhow::bail!("Unsupported JXL pixel format: {:?}", image.pixel_format()),
-            };
-            rgba.put_pixel(x, y, pixel);
-        }
-    }
+    // Decode JXL and encode it as PNG, keeping its metadata
+    let (png_data, _) = encode_jxl_as_png(&jxl_data, bit_depth)
+        .with_context(|| format!("Failed to convert JXL file {}", input_path.display()))?;
 
     // Save as PNG
-    rgba.save(output_path)
+    tokio::fs::write(output_path, png_data)
+        .await
         .with_context(|| format!("Failed to save PNG file: {}", output_path.display()))?;
 
     info!("Successfully converted JXL to PNG");
@@ -194,6 +746,10 @@ pub async fn convert_jxl_to_png(input_path: &Path, output_path: &Path) -> Result
 /// The processor function is called with the path to the converted PNG file.
 /// This allows for additional transformations to be applied after conversion.
 ///
+/// The original is removed and the processor called even when the conversion
+/// fails, and an existing PNG is overwritten. Use [`process_jxl_file_with_options`]
+/// to keep the original unless the PNG was written and verified.
+///
 /// # Type Parameters
 ///
 /// * `F` - Type of the processor function
@@ -268,3 +824,567 @@ where
     // Return the conversion error if it failed
     conversion_result
 }
+
+/// What to do with the original JXL file once its PNG has been verified.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum OriginalFilePolicy {
+    /// Leave the original where it is (default)
+    #[default]
+    Keep,
+    /// Delete the original
+    Delete,
+    /// Move the original into the given directory, which is created if needed.
+    /// Names already taken there get a `_1`, `_2`, ... suffix.
+    MoveToTrash(PathBuf),
+}
+
+/// Options for `process_jxl_file_with_options`.
+#[derive(Debug, Clone, Default)]
+pub struct JxlProcessOptions {
+    /// What to do with the original JXL file after a verified conversion
+    pub original_policy: OriginalFilePolicy,
+    /// What to do when the PNG path is already taken
+    pub collision_policy: CollisionPolicy,
+    /// Bits per channel of the PNG file
+    pub bit_depth: PngBitDepth,
+}
+
+impl JxlProcessOptions {
+    /// Set what happens to the original JXL file
+    #[must_use]
+    pub fn with_original_policy(mut self, policy: OriginalFilePolicy) -> Self {
+        self.original_policy = policy;
+        self
+    }
+
+    /// Set how an existing file at the PNG path is handled
+    #[must_use]
+    pub fn with_collision_policy(mut self, policy: CollisionPolicy) -> Self {
+        self.collision_policy = policy;
+        self
+    }
+
+    /// Set the bit depth of the PNG file
+    #[must_use]
+    pub fn with_bit_depth(mut self, bit_depth: PngBitDepth) -> Self {
+        self.bit_depth = bit_depth;
+        self
+    }
+}
+
+/// What happened to the original JXL file.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum OriginalFileOutcome {
+    /// The original is still in place
+    #[default]
+    Kept,
+    /// The original was deleted
+    Deleted,
+    /// The original was moved to this path
+    MovedTo(PathBuf),
+}
+
+/// Where `process_jxl_file_with_options` stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JxlProcessStatus {
+    /// Conversion, verification, processing and the original policy all succeeded
+    Completed,
+    /// The PNG path was taken and the collision policy refused to replace it
+    OutputExists(PathBuf),
+    /// The JXL file could not be read, decoded or written as PNG
+    ConversionFailed(String),
+    /// The written PNG could not be re-opened or has the wrong dimensions
+    VerificationFailed(String),
+    /// The processor returned an error
+    ProcessorFailed(String),
+    /// The PNG is fine, but the original could not be deleted or moved
+    OriginalPolicyFailed(String),
+}
+
+/// Report of what `process_jxl_file_with_options` did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JxlProcessReport {
+    /// The JXL file that was processed
+    pub input_path: PathBuf,
+    /// The verified PNG file, if one was written and kept
+    pub png_path: Option<PathBuf>,
+    /// Whether the PNG got a suffixed name because the default one was taken
+    pub renamed: bool,
+    /// Whether the processor was called
+    pub processor_ran: bool,
+    /// What happened to the original JXL file
+    pub original: OriginalFileOutcome,
+    /// Where processing stopped
+    pub status: JxlProcessStatus,
+}
+
+impl JxlProcessReport {
+    /// Returns `true` if every step succeeded
+    #[must_use]
+    pub fn is_success(&self) -> bool {
+        self.status == JxlProcessStatus::Completed
+    }
+}
+
+/// Returns `path` with `_{n}` appended to its file stem, or `path` itself for `n == 0`.
+fn with_stem_suffix(path: &Path, n: u32) -> PathBuf {
+    if n == 0 {
+        return path.to_path_buf();
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{stem}_{n}.{}", ext.to_string_lossy()),
+        None => format!("{stem}_{n}"),
+    };
+    path.with_file_name(name)
+}
+
+/// Creates a new file at `path`, or at a suffixed name if the policy allows it.
+///
+/// The file is created with `create_new`, so an existing file is never replaced,
+/// even if it appears after the name was chosen. Returns `Ok(None)` when the path
+/// is taken and the policy is `CollisionPolicy::Skip`.
+async fn create_new_file(
+    path: &Path,
+    policy: CollisionPolicy,
+) -> std::io::Result<Option<(tokio::fs::File, PathBuf)>> {
+    let mut attempt = 0;
+    loop {
+        let candidate = with_stem_suffix(path, attempt);
+        match tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&candidate)
+            .await
+        {
+            Ok(file) => return Ok(Some((file, candidate))),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => match policy {
+                CollisionPolicy::Skip => return Ok(None),
+                CollisionPolicy::Suffix => attempt += 1,
+            },
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Moves a file into `dir` without replacing anything there.
+///
+/// Falls back to copy and delete when the directory is on another filesystem.
+async fn move_to_directory(path: &Path, dir: &Path) -> Result<PathBuf> {
+    tokio::fs::create_dir_all(dir)
+        .await
+        .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+    let file_name = path
+        .file_name()
+        .with_context(|| format!("Invalid file name: {}", path.display()))?;
+
+    // Reserve the target name first so nothing in the directory is overwritten
+    let (file, target) = create_new_file(&dir.join(file_name), CollisionPolicy::Suffix)
+        .await?
+        .context("No free name in trash directory")?;
+    drop(file);
+
+    if tokio::fs::rename(path, &target).await.is_err() {
+        let moved = async {
+            tokio::fs::copy(path, &target).await.with_context(|| {
+                format!("Failed to copy {} to {}", path.display(), target.display())
+            })?;
+            tokio::fs::remove_file(path)
+                .await
+                .with_context(|| format!("Failed to remove {}", path.display()))
+        };
+        if let Err(e) = moved.await {
+            // The original stays in place, so drop the reserved name and any copy
+            if let Err(remove_error) = tokio::fs::remove_file(&target).await {
+                warn!("Failed to remove {}: {remove_error}", target.display());
+            }
+            return Err(e);
+        }
+    }
+    Ok(target)
+}
+
+/// Decodes a JXL file and writes it as PNG into an already created file.
+///
+/// Returns the dimensions of the decoded image.
+async fn write_jxl_as_png(
+    input_path: &Path,
+    file: tokio::fs::File,
+    bit_depth: PngBitDepth,
+) -> Result<(u32, u32)> {
+    let jxl_data = tokio::fs::read(input_path)
+        .await
+        .with_context(|| format!("Failed to read JXL file: {}", input_path.display()))?;
+    let (png_data, dimensions) = encode_jxl_as_png(&jxl_data, bit_depth)
+        .with_context(|| format!("Failed to convert JXL file {}", input_path.display()))?;
+
+    let mut file = file;
+    file.write_all(&png_data)
+        .await
+        .context("Failed to write PNG file")?;
+    file.sync_all().await.context("Failed to write PNG file")?;
+    Ok(dimensions)
+}
+
+/// Processes a JXL file like `process_jxl_file`, but never loses data on failure.
+///
+/// The steps are:
+/// 1. Verify the input is a JXL file
+/// 2. Create the PNG next to it, applying the `CollisionPolicy` if the name is taken;
+///    existing files are never overwritten
+/// 3. Convert the JXL image into the PNG
+/// 4. Verify the PNG by re-opening it and comparing its dimensions with the JXL image
+/// 5. Run the processor, if any, on the verified PNG
+/// 6. Apply the `OriginalFilePolicy` to the JXL file
+///
+/// Each step only runs if the previous one succeeded, so the original is only
+/// deleted or moved after a verified conversion and a successful processor.
+/// A PNG that fails conversion or verification is removed again; after a
+/// processor failure it is kept for inspection.
+///
+/// # Arguments
+///
+/// * `input_path` - Path to the input JXL file
+/// * `options` - Policies for the original file and output collisions, and the PNG bit depth
+/// * `processor` - Optional async function to process the PNG file after verification
+///
+/// # Returns
+///
+/// Returns a `JxlProcessReport` describing what was written, whether the processor
+/// ran, what happened to the original, and where processing stopped
+///
+/// # Errors
+///
+/// Returns an error only if the input is not a JXL file; every later failure is
+/// recorded in `JxlProcessReport::status`.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use std::path::{Path, PathBuf};
+/// use imx::extensions::CollisionPolicy;
+/// use imx::jxl::{JxlProcessOptions, OriginalFilePolicy, process_jxl_file_with_options};
+///
+/// async fn example() -> anyhow::Result<()> {
+///     let options = JxlProcessOptions::default()
+///         .with_original_policy(OriginalFilePolicy::MoveToTrash(PathBuf::from(".trash")))
+///         .with_collision_policy(CollisionPolicy::Suffix);
+///     let report = process_jxl_file_with_options(
+///         Path::new("image.jxl"),
+///         &options,
+///         Some(|path: PathBuf| async move { Ok(()) }),
+///     )
+///     .await?;
+///     if !report.is_success() {
+///         eprintln!("{:?}", report.status);
+///     }
+///     Ok(())
+/// }
+/// ```
+pub async fn process_jxl_file_with_options<F, Fut>(
+    input_path: &Path,
+    options: &JxlProcessOptions,
+    processor: Option<F>,
+) -> Result<JxlProcessReport>
+where
+    F: FnOnce(PathBuf) -> Fut + Send,
+    Fut: std::future::Future<Output = Result<()>> + Send,
+{
+    if !is_jxl_file(input_path) {
+        anyhow::bail!("Not a JXL file: {}", input_path.display());
+    }
+
+    let mut report = JxlProcessReport {
+        input_path: input_path.to_path_buf(),
+        png_path: None,
+        renamed: false,
+        processor_ran: false,
+        original: OriginalFileOutcome::Kept,
+        status: JxlProcessStatus::Completed,
+    };
+
+    let default_png_path = input_path.with_extension("png");
+    let (file, png_path) = match create_new_file(&default_png_path, options.collision_policy).await
+    {
+        Ok(Some(created)) => created,
+        Ok(None) => {
+            warn!(
+                "Not converting {}: {} already exists",
+                input_path.display(),
+                default_png_path.display()
+            );
+            report.status = JxlProcessStatus::OutputExists(default_png_path);
+            return Ok(report);
+        }
+        Err(e) => {
+            report.status = JxlProcessStatus::ConversionFailed(format!(
+                "Failed to create PNG file {}: {e}",
+                default_png_path.display()
+            ));
+            return Ok(report);
+        }
+    };
+
+    // From here on the PNG file is ours, so it can be removed if it is unusable
+    let failure = match write_jxl_as_png(input_path, file, options.bit_depth).await {
+        Err(e) => Some(JxlProcessStatus::ConversionFailed(format!("{e:#}"))),
+        Ok(expected) => match image::open(&png_path).map(|png| png.dimensions()) {
+            Err(e) => Some(JxlProcessStatus::VerificationFailed(format!(
+                "Failed to re-open {}: {e}",
+                png_path.display()
+            ))),
+            Ok(actual) if actual != expected => {
+                Some(JxlProcessStatus::VerificationFailed(format!(
+                    "{} is {}x{}, expected {}x{}",
+                    png_path.display(),
+                    actual.0,
+                    actual.1,
+                    expected.0,
+                    expected.1
+                )))
+            }
+            Ok(_) => None,
+        },
+    };
+    if let Some(status) = failure {
+        warn!("Failed to convert {}: {status:?}", input_path.display());
+        if let Err(e) = tokio::fs::remove_file(&png_path).await {
+            warn!("Failed to remove {}: {e}", png_path.display());
+        }
+        report.status = status;
+        return Ok(report);
+    }
+    report.renamed = png_path != default_png_path;
+    report.png_path = Some(png_path.clone());
+
+    if let Some(processor) = processor {
+        report.processor_ran = true;
+        if let Err(e) = processor(png_path).await {
+            report.status = JxlProcessStatus::ProcessorFailed(format!("{e:#}"));
+            return Ok(report);
+        }
+    }
+
+    let outcome = match &options.original_policy {
+        OriginalFilePolicy::Keep => Ok(OriginalFileOutcome::Kept),
+        OriginalFilePolicy::Delete => tokio::fs::remove_file(input_path)
+            .await
+            .map(|()| OriginalFileOutcome::Deleted)
+            .with_context(|| {
+                format!(
+                    "Failed to remove original JXL file: {}",
+                    input_path.display()
+                )
+            }),
+        OriginalFilePolicy::MoveToTrash(dir) => move_to_directory(input_path, dir)
+            .await
+            .map(OriginalFileOutcome::MovedTo),
+    };
+    match outcome {
+        Ok(outcome) => report.original = outcome,
+        Err(e) => report.status = JxlProcessStatus::OriginalPolicyFailed(format!("{e:#}")),
+    }
+
+    info!("Processed JXL file: {}", input_path.display());
+    Ok(report)
+}
+
+/// ISOBMFF box type holding JPEG bitstream reconstruction data
+const JBRD_BOX_TYPE: [u8; 4] = *b"jbrd";
+
+/// Checks whether JPEG XL data carries a `jbrd` (JPEG bitstream reconstruction) box.
+///
+/// Only the container box headers are inspected; nothing is decoded. Bare codestreams
+/// cannot hold reconstruction data and always return `false`.
+///
+/// # Arguments
+///
+/// * `data` - Complete JPEG XL file contents
+///
+/// # Returns
+///
+/// Returns `true` if a `jbrd` box is present
+#[must_use]
+pub fn has_jpeg_reconstruction_data(data: &[u8]) -> bool {
+    if !data.starts_with(&JXL_CONTAINER_SIGNATURE) {
+        return false;
+    }
+
+    // Box sizes are untrusted, so offsets that would overflow end the search
+    let mut offset = 0usize;
+    while let Some(header) = offset.checked_add(8).and_then(|end| data.get(offset..end)) {
+        if header[4..8] == JBRD_BOX_TYPE {
+            return true;
+        }
+        let size = match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+            // A zero size means the box runs to the end of the file
+            0 => return false,
+            1 => match offset
+                .checked_add(16)
+                .and_then(|end| data.get(offset + 8..end))
+            {
+                Some(large) => u64::from_be_bytes(large.try_into().unwrap_or_default()),
+                None => return false,
+            },
+            size => u64::from(size),
+        };
+        match usize::try_from(size) {
+            Ok(size) if size >= 8 => match offset.checked_add(size) {
+                Some(next) => offset = next,
+                None => return false,
+            },
+            _ => return false,
+        }
+    }
+    false
+}
+
+/// Reconstructs the original JPEG bytes from a JPEG XL file holding reconstruction data.
+///
+/// # Arguments
+///
+/// * `jxl_data` - Complete JPEG XL file contents
+///
+/// # Returns
+///
+/// Returns the reconstructed JPEG file contents
+///
+/// # Errors
+///
+/// Returns an error if:
+/// * The data has no `jbrd` box
+/// * The JXL data is invalid or corrupted
+/// * The reconstruction data does not match the image
+pub fn reconstruct_jpeg(jxl_data: &[u8]) -> Result<Vec<u8>> {
+    if !has_jpeg_reconstruction_data(jxl_data) {
+        anyhow::bail!("JXL data has no JPEG reconstruction (jbrd) box");
+    }
+
+    let image = JxlImage::read_with_defaults(jxl_data)
+        .map_err(|e| anyhow::anyhow!("Failed to decode JXL data: {e}"))?;
+    let mut jpeg = Vec::new();
+    image
+        .reconstruct_jpeg(&mut jpeg)
+        .map_err(|e| anyhow::anyhow!("Failed to reconstruct JPEG: {e}"))?;
+    Ok(jpeg)
+}
+
+/// Losslessly recompresses a JPEG into JPEG XL, keeping reconstruction data.
+///
+/// The output is a JXL container with a `jbrd` box, so the exact original file can be
+/// recovered with [`reconstruct_jpeg`]. Before returning, the output is reconstructed
+/// and compared against the input to make sure the round trip is byte-identical.
+///
+/// Requires the `jxl-jpeg-transcode` feature.
+///
+/// # Arguments
+///
+/// * `jpeg_data` - Complete JPEG file contents
+///
+/// # Returns
+///
+/// Returns the JPEG XL file contents
+///
+/// # Errors
+///
+/// Returns an error if:
+/// * The JPEG cannot be parsed or uses features JPEG XL cannot represent
+/// * Encoding fails
+/// * Reconstructing the output does not reproduce the input byte for byte
+#[cfg(feature = "jxl-jpeg-transcode")]
+pub fn transcode_jpeg_to_jxl(jpeg_data: &[u8]) -> Result<Vec<u8>> {
+    let parsed = jxl_encoder::jpeg::read_jpeg(jpeg_data)
+        .map_err(|e| anyhow::anyhow!("Failed to parse JPEG: {e}"))?;
+    let jxl_data = jxl_encoder::jpeg::encode_jpeg_to_jxl_container(&parsed)
+        .map_err(|e| anyhow::anyhow!("Failed to encode JPEG as JXL: {e}"))?;
+
+    let reconstructed =
+        reconstruct_jpeg(&jxl_data).context("Failed to verify JPEG XL transcode")?;
+    if reconstructed != jpeg_data {
+        anyhow::bail!("JPEG reconstructed from JXL does not match the original bytes");
+    }
+
+    Ok(jxl_data)
+}
+
+/// Losslessly recompresses a JPEG file into a JPEG XL file.
+///
+/// See [`transcode_jpeg_to_jxl`]; the output file is only written once the round
+/// trip has been verified. Requires the `jxl-jpeg-transcode` feature.
+///
+/// # Arguments
+///
+/// * `input_path` - Path to the input JPEG file
+/// * `output_path` - Path where the JXL file should be saved
+///
+/// # Returns
+///
+/// Returns a `Result<()>` indicating success or failure
+///
+/// # Errors
+///
+/// Returns an error if:
+/// * The JPEG file cannot be read from disk
+/// * Transcoding or verification fails
+/// * The JXL file cannot be written to disk
+#[cfg(feature = "jxl-jpeg-transcode")]
+pub async fn transcode_jpeg_file_to_jxl(input_path: &Path, output_path: &Path) -> Result<()> {
+    info!(
+        "Transcoding JPEG to JXL: {} -> {}",
+        input_path.display(),
+        output_path.display()
+    );
+
+    let jpeg_data = tokio::fs::read(input_path)
+        .await
+        .with_context(|| format!("Failed to read JPEG file: {}", input_path.display()))?;
+    let jxl_data = transcode_jpeg_to_jxl(&jpeg_data)
+        .with_context(|| format!("Failed to transcode {}", input_path.display()))?;
+    tokio::fs::write(output_path, &jxl_data)
+        .await
+        .with_context(|| format!("Failed to write JXL file: {}", output_path.display()))?;
+
+    info!(
+        "Successfully transcoded JPEG to JXL ({} -> {} bytes)",
+        jpeg_data.len(),
+        jxl_data.len()
+    );
+    Ok(())
+}
+
+/// Reconstructs the original JPEG file from a JPEG XL file with reconstruction data.
+///
+/// # Arguments
+///
+/// * `input_path` - Path to the input JXL file
+/// * `output_path` - Path where the JPEG file should be saved
+///
+/// # Returns
+///
+/// Returns a `Result<()>` indicating success or failure
+///
+/// # Errors
+///
+/// Returns an error if:
+/// * The JXL file cannot be read from disk
+/// * The file has no `jbrd` box or reconstruction fails
+/// * The JPEG file cannot be written to disk
+pub async fn reconstruct_jpeg_file(input_path: &Path, output_path: &Path) -> Result<()> {
+    info!(
+        "Reconstructing JPEG from JXL: {} -> {}",
+        input_path.display(),
+        output_path.display()
+    );
+
+    let jxl_data = tokio::fs::read(input_path)
+        .await
+        .with_context(|| format!("Failed to read JXL file: {}", input_path.display()))?;
+    let jpeg_data = reconstruct_jpeg(&jxl_data)
+        .with_context(|| format!("Failed to reconstruct JPEG from {}", input_path.display()))?;
+    tokio::fs::write(output_path, jpeg_data)
+        .await
+        .with_context(|| format!("Failed to write JPEG file: {}", output_path.display()))?;
+
+    info!("Successfully reconstructed JPEG");
+    Ok(())
+}
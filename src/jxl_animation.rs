@@ -0,0 +1,452 @@
+//! Animated and multi-frame JPEG XL support.
+//!
+//! JPEG XL files can hold several displayed frames, each with its own duration,
+//! together with a loop count. This module provides:
+//! - Frame count, per-frame durations and loop count without rendering any pixels
+//! - Decoding of every frame
+//! - Conversion to animated PNG (APNG), animated WebP or GIF
+//! - Extraction of all frames as numbered PNG files
+//!
+//! Frames are fully composited, so every decoded frame covers the whole canvas.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use std::path::Path;
+//! use imx::jxl_animation::{convert_animated_jxl, jxl_animation_info};
+//!
+//! async fn example() -> anyhow::Result<()> {
+//!     let data = tokio::fs::read("animation.jxl").await?;
+//!     let info = jxl_animation_info(&data)?;
+//!     println!("{} frames, {:?} in total", info.frame_count, info.total_duration());
+//!
+//!     convert_animated_jxl(Path::new("animation.jxl"), Path::new("animation.gif"), None).await?;
+//!     Ok(())
+//! }
+//! ```
+
+#![warn(clippy::all, clippy::pedantic)]
+
+use crate::encoder_options::WebpOptions;
+use crate::formats::{ImageFormatOptions, webp_config};
+use crate::jxl::{PngBitDepth, render_keyframe};
+use anyhow::{Context, Result};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, DynamicImage, Frame, RgbaImage};
+use jxl_oxide::JxlImage;
+use log::info;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Frame count, timing and looping of a JPEG XL image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JxlAnimationInfo {
+    /// Number of displayed frames (1 for still images)
+    pub frame_count: usize,
+    /// How long each frame is shown; zero for still images
+    pub frame_durations: Vec<Duration>,
+    /// How many times the animation plays, where 0 means forever
+    pub loop_count: u32,
+    /// Ticks per second as `(numerator, denominator)`, or `None` for still images
+    pub ticks_per_second: Option<(u32, u32)>,
+}
+
+impl JxlAnimationInfo {
+    /// Returns `true` if the image header declares an animation
+    #[must_use]
+    pub fn is_animated(&self) -> bool {
+        self.ticks_per_second.is_some()
+    }
+
+    /// Returns the time one pass through all frames takes
+    #[must_use]
+    pub fn total_duration(&self) -> Duration {
+        self.frame_durations.iter().sum()
+    }
+}
+
+/// A decoded frame of a JPEG XL animation.
+#[derive(Debug, Clone)]
+pub struct JxlFrame {
+    /// The composited frame, at the source precision (see [`crate::jxl::decode_jxl`])
+    pub image: DynamicImage,
+    /// How long the frame is shown
+    pub duration: Duration,
+}
+
+/// Animated formats a JPEG XL animation can be converted to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationFormat {
+    /// Animated PNG
+    Apng,
+    /// Animated WebP
+    WebP,
+    /// GIF, quantized to 256 colors per frame
+    Gif,
+}
+
+impl AnimationFormat {
+    /// Picks the format from a file extension: `png`/`apng`, `webp` or `gif`.
+    ///
+    /// The check is case-insensitive. Returns `None` for any other extension.
+    #[must_use]
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        match ext.as_str() {
+            "png" | "apng" => Some(Self::Apng),
+            "webp" => Some(Self::WebP),
+            "gif" => Some(Self::Gif),
+            _ => None,
+        }
+    }
+}
+
+/// Converts a frame duration in ticks to a `Duration`.
+fn ticks_to_duration(ticks: u32, (numerator, denominator): (u32, u32)) -> Duration {
+    if numerator == 0 {
+        return Duration::ZERO;
+    }
+    Duration::from_secs_f64(f64::from(ticks) * f64::from(denominator) / f64::from(numerator))
+}
+
+/// Reads the animation header and frame headers of a loaded image.
+fn animation_info(image: &JxlImage) -> JxlAnimationInfo {
+    let animation = image.image_header().metadata.animation.as_ref();
+    let ticks_per_second = animation.map(|a| (a.tps_numerator, a.tps_denominator));
+    let frame_count = image.num_loaded_keyframes();
+    let frame_durations = (0..frame_count)
+        .map(
+            |index| match (ticks_per_second, image.frame_header(index)) {
+                (Some(tps), Some(header)) => ticks_to_duration(header.duration, tps),
+                _ => Duration::ZERO,
+            },
+        )
+        .collect();
+
+    JxlAnimationInfo {
+        frame_count,
+        frame_durations,
+        loop_count: animation.map_or(0, |a| a.num_loops),
+        ticks_per_second,
+    }
+}
+
+/// Parses JPEG XL data, failing if it holds no displayed frame.
+fn load_jxl(data: &[u8]) -> Result<JxlImage> {
+    let image = JxlImage::read_with_defaults(data)
+        .map_err(|e| anyhow::anyhow!("Failed to decode JXL data: {e}"))?;
+    if image.num_loaded_keyframes() == 0 {
+        anyhow::bail!("JXL data contains no complete frame");
+    }
+    Ok(image)
+}
+
+/// Reads the frame count, per-frame durations and loop count of a JPEG XL image.
+///
+/// Only headers are parsed; no frame is rendered.
+///
+/// # Arguments
+///
+/// * `data` - Complete JPEG XL file contents
+///
+/// # Returns
+///
+/// Returns a `JxlAnimationInfo`; still images report one frame with a zero duration
+///
+/// # Errors
+///
+/// Returns an error if the JXL data is invalid or holds no complete frame
+pub fn jxl_animation_info(data: &[u8]) -> Result<JxlAnimationInfo> {
+    Ok(animation_info(&load_jxl(data)?))
+}
+
+/// Decodes every displayed frame of a JPEG XL image.
+///
+/// # Arguments
+///
+/// * `data` - Complete JPEG XL file contents
+///
+/// # Returns
+///
+/// Returns the frames in display order with their durations
+///
+/// # Errors
+///
+/// Returns an error if:
+/// * The JXL data is invalid or holds no complete frame
+/// * A frame cannot be rendered
+/// * The pixel format is CMYK
+pub fn decode_jxl_frames(data: &[u8]) -> Result<Vec<JxlFrame>> {
+    let image = load_jxl(data)?;
+    let info = animation_info(&image);
+    info.frame_durations
+        .into_iter()
+        .enumerate()
+        .map(|(index, duration)| {
+            Ok(JxlFrame {
+                image: render_keyframe(&image, index)?,
+                duration,
+            })
+        })
+        .collect()
+}
+
+/// Converts a frame duration to an APNG delay fraction.
+///
+/// Milliseconds are used when they fit in 16 bits, whole seconds otherwise.
+fn apng_delay(duration: Duration) -> (u16, u16) {
+    match u16::try_from(duration.as_millis()) {
+        Ok(millis) => (millis, 1000),
+        Err(_) => (u16::try_from(duration.as_secs()).unwrap_or(u16::MAX), 1),
+    }
+}
+
+/// Encodes frames as an animated PNG.
+fn encode_apng(frames: &[RgbaImage], durations: &[Duration], loop_count: u32) -> Result<Vec<u8>> {
+    let (width, height) = frames[0].dimensions();
+    let frame_count = u32::try_from(frames.len()).context("Too many frames for APNG")?;
+
+    let mut output = Vec::new();
+    let mut encoder = png::Encoder::new(&mut output, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(frame_count, loop_count)?;
+    let mut writer = encoder.write_header()?;
+    for (frame, &duration) in frames.iter().zip(durations) {
+        let (numerator, denominator) = apng_delay(duration);
+        writer.set_frame_delay(numerator, denominator)?;
+        writer.write_image_data(frame.as_raw())?;
+    }
+    writer.finish()?;
+    Ok(output)
+}
+
+/// Encodes frames as an animated WebP with libwebp.
+fn encode_animated_webp(
+    frames: &[RgbaImage],
+    durations: &[Duration],
+    loop_count: u32,
+    options: &ImageFormatOptions,
+) -> Result<Vec<u8>> {
+    let (width, height) = frames[0].dimensions();
+    let config = webp_config(&WebpOptions::try_from(options)?)?;
+
+    // Frames are placed by their start time in milliseconds
+    let mut timestamps = Vec::with_capacity(frames.len());
+    let mut elapsed = Duration::ZERO;
+    for &duration in durations {
+        timestamps.push(i32::try_from(elapsed.as_millis()).context("Animation too long for WebP")?);
+        elapsed += duration;
+    }
+
+    let mut encoder = webp::AnimEncoder::new(width, height, &config);
+    encoder.set_loop_count(i32::try_from(loop_count).unwrap_or(i32::MAX));
+    for (frame, &timestamp) in frames.iter().zip(&timestamps) {
+        encoder.add_frame(webp::AnimFrame::from_rgba(
+            frame.as_raw(),
+            width,
+            height,
+            timestamp,
+        ));
+    }
+    let mut output = encoder
+        .try_encode()
+        .map_err(|e| anyhow::anyhow!("Failed to encode animated WebP: {e:?}"))?
+        .to_vec();
+
+    // The webp crate ends the animation at timestamp 0, so libwebp guesses the
+    // duration of the last frame from the others; the real one is patched in
+    if let Some(&last) = durations.last() {
+        set_last_webp_frame_duration(&mut output, last)?;
+    }
+    Ok(output)
+}
+
+/// Rewrites the duration field of the last `ANMF` chunk of an animated WebP.
+fn set_last_webp_frame_duration(webp_data: &mut [u8], duration: Duration) -> Result<()> {
+    // RIFF header, then chunks of a FourCC, a little-endian size and padded data
+    let mut offset = 12;
+    let mut last_frame = None;
+    while offset + 8 <= webp_data.len() {
+        let size = u32::from_le_bytes([
+            webp_data[offset + 4],
+            webp_data[offset + 5],
+            webp_data[offset + 6],
+            webp_data[offset + 7],
+        ]) as usize;
+        if &webp_data[offset..offset + 4] == b"ANMF" {
+            last_frame = Some(offset + 8);
+        }
+        offset += 8 + size + (size & 1);
+    }
+
+    // ANMF data: X, Y, width - 1 and height - 1 (3 bytes each), then a
+    // 3-byte duration in milliseconds
+    let field = last_frame
+        .map(|data| data + 12)
+        .filter(|&field| field + 3 <= webp_data.len())
+        .context("Encoded WebP has no animation frame")?;
+    let millis = u32::try_from(duration.as_millis())
+        .unwrap_or(u32::MAX)
+        .min(0x00FF_FFFF);
+    webp_data[field..field + 3].copy_from_slice(&millis.to_le_bytes()[..3]);
+    Ok(())
+}
+
+/// Encodes frames as a GIF.
+fn encode_gif(frames: Vec<RgbaImage>, durations: &[Duration], loop_count: u32) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    {
+        // Speed 10 is the NeuQuant setting recommended for animations
+        let mut encoder = GifEncoder::new_with_speed(&mut output, 10);
+        // GIF counts repeats after the first play rather than plays
+        let repeat = match loop_count {
+            0 => Repeat::Infinite,
+            plays => Repeat::Finite(u16::try_from(plays - 1).unwrap_or(u16::MAX)),
+        };
+        encoder.set_repeat(repeat)?;
+        encoder.encode_frames(frames.into_iter().zip(durations).map(
+            |(mut frame, &duration)| {
+                // A GIF frame has a single transparent palette entry, so every
+                // transparent pixel must share one color to map onto it
+                for pixel in frame.pixels_mut().filter(|pixel| pixel[3] == 0) {
+                    *pixel = image::Rgba([0, 0, 0, 0]);
+                }
+                Frame::from_parts(frame, 0, 0, Delay::from_saturating_duration(duration))
+            },
+        ))?;
+    }
+    Ok(output)
+}
+
+/// Converts a JPEG XL animation to APNG, animated WebP or GIF.
+///
+/// The output format is chosen from the extension of `output_path` (see
+/// [`AnimationFormat::from_path`]). Frame durations and the loop count are
+/// carried over; frames are written as 8-bit RGBA. Still images produce a
+/// single-frame animation.
+///
+/// # Arguments
+///
+/// * `input_path` - Path to the input JXL file
+/// * `output_path` - Path of the animation to write
+/// * `options` - Quality settings for WebP output (`ImageFormatOptions::webp()` if `None`);
+///   ignored by APNG and GIF
+///
+/// # Returns
+///
+/// Returns a `Result<()>` indicating success or failure
+///
+/// # Errors
+///
+/// Returns an error if:
+/// * The output extension is not `png`, `apng`, `webp` or `gif`
+/// * The JXL file cannot be read or decoded
+/// * The WebP options are invalid
+/// * The animation cannot be output or written
+pub async fn convert_animated_jxl(
+    input_path: &Path,
+    output_path: &Path,
+    options: Option<ImageFormatOptions>,
+) -> Result<()> {
+    let format = AnimationFormat::from_path(output_path).with_context(|| {
+        format!(
+            "Unsupported animation output format: {}",
+            output_path.display()
+        )
+    })?;
+    info!(
+        "Converting animated JXL to {format:?}: {} -> {}",
+        input_path.display(),
+        output_path.display()
+    );
+
+    let data = tokio::fs::read(input_path)
+        .await
+        .with_context(|| format!("Failed to read JXL file: {}", input_path.display()))?;
+    let image = load_jxl(&data)
+        .with_context(|| format!("Failed to decode JXL file {}", input_path.display()))?;
+    let info = animation_info(&image);
+    let frames = (0..info.frame_count)
+        .map(|index| render_keyframe(&image, index).map(|frame| frame.to_rgba8()))
+        .collect::<Result<Vec<_>>>()?;
+
+    let output = match format {
+        AnimationFormat::Apng => encode_apng(&frames, &info.frame_durations, info.loop_count)?,
+        AnimationFormat::WebP => encode_animated_webp(
+            &frames,
+            &info.frame_durations,
+            info.loop_count,
+            &options.unwrap_or_else(ImageFormatOptions::webp),
+        )?,
+        AnimationFormat::Gif => encode_gif(frames, &info.frame_durations, info.loop_count)?,
+    };
+    tokio::fs::write(output_path, output)
+        .await
+        .with_context(|| format!("Failed to write {}", output_path.display()))?;
+
+    info!("Converted {} frames", info.frame_count);
+    Ok(())
+}
+
+/// Writes every frame of a JPEG XL image as a numbered PNG file.
+///
+/// Files are named `{stem}_{index}.png`, where `stem` is the input file stem and
+/// `index` starts at 0 and is zero-padded to at least four digits, so the files
+/// sort in display order. Existing files with the same names are replaced.
+///
+/// # Arguments
+///
+/// * `input_path` - Path to the input JXL file
+/// * `output_dir` - Directory for the PNG files, created if needed
+/// * `bit_depth` - Bits per channel of the PNG files
+///
+/// # Returns
+///
+/// Returns the paths of the written files in display order
+///
+/// # Errors
+///
+/// Returns an error if:
+/// * The JXL file cannot be read or decoded
+/// * The output directory cannot be created
+/// * A PNG file cannot be saved
+pub async fn extract_jxl_frames(
+    input_path: &Path,
+    output_dir: &Path,
+    bit_depth: PngBitDepth,
+) -> Result<Vec<PathBuf>> {
+    let data = tokio::fs::read(input_path)
+        .await
+        .with_context(|| format!("Failed to read JXL file: {}", input_path.display()))?;
+    let image = load_jxl(&data)
+        .with_context(|| format!("Failed to decode JXL file {}", input_path.display()))?;
+    tokio::fs::create_dir_all(output_dir)
+        .await
+        .with_context(|| format!("Failed to create directory: {}", output_dir.display()))?;
+
+    let stem = input_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("frame");
+    let frame_count = image.num_loaded_keyframes();
+    let width = (frame_count - 1).to_string().len().max(4);
+
+    let mut written = Vec::with_capacity(frame_count);
+    for index in 0..frame_count {
+        let frame = render_keyframe(&image, index)?;
+        let path = output_dir.join(format!("{stem}_{index:0width$}.png"));
+        match bit_depth {
+            PngBitDepth::Eight => frame.to_rgba8().save(&path),
+            PngBitDepth::Sixteen => frame.to_rgba16().save(&path),
+        }
+        .with_context(|| format!("Failed to save PNG file: {}", path.display()))?;
+        written.push(path);
+    }
+
+    info!(
+        "Extracted {frame_count} frames from {}",
+        input_path.display()
+    );
+    Ok(written)
+}
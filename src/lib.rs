@@ -43,6 +43,7 @@
 //!         top_padding: 40,
 //!         left_padding: 40,
 //!         font_size: None,
+//!         auto_orient: false,
 //!     };
 //!     create_plot(&config)?;
 //!     
@@ -140,6 +141,7 @@ pub mod numeric;
 ///         top_padding: 40,
 ///         left_padding: 40,
 ///         font_size: None,
+///         auto_orient: false,
 ///     };
 ///     create_plot(&config)?;
 ///     
@@ -170,6 +172,7 @@ pub mod numeric;
 ///         top_padding: 40,
 ///         left_padding: 40,
 ///         font_size: None,
+///         auto_orient: false,
 ///     };
 ///     create_plot(&config)?;
 ///     Ok(())
@@ -183,21 +186,78 @@ pub mod layout;
 /// Image format conversion module with support for various formats
 pub mod formats;
 
+/// File extension validation and repair module for fixing images saved
+/// under the wrong extension
+pub mod extensions;
+
+/// Colour management module for converting images with embedded ICC
+/// profiles to sRGB
+pub mod color;
+
+/// Animated JPEG XL module for reading frame timing and converting
+/// animations to APNG, animated WebP or GIF
+pub mod jxl_animation;
+
+/// Typed per-format encoder options module, with optional serde support
+pub mod encoder_options;
+
+/// Metadata module for keeping, whitelisting or stripping EXIF, XMP, ICC
+/// and text metadata during conversion
+pub mod metadata;
+
+/// Image quality metrics module for measuring the changes a conversion made,
+/// with PSNR, SSIM, MS-SSIM and per-pixel error maps
+pub mod metrics;
+
 // Re-export commonly used types and functions
-pub use formats::{ImageFormatOptions, convert_image, convert_images_batch};
+pub use color::convert_to_srgb;
+pub use encoder_options::{
+    AvifOptions, ChromaSubsampling, EncoderOptions, JpegOptions, JxlOptions, PngCompression,
+    PngFilter, PngOptions, WebpOptions,
+};
+pub use extensions::{
+    ExtensionCheck, ExtensionFixOptions, ExtensionFixReport, FailedFix, check_image_extension,
+    fix_image_extensions,
+};
+pub use formats::{
+    BATCH_MANIFEST_NAME, BatchOptions, BatchReport, BatchSkipReason, ConvertedImage, FailedImage,
+    ImageFormatOptions, IncrementalMode, OutputCollisionPolicy, SkippedImage, TargetSizeOptions,
+    TargetSizeReport, convert_image, convert_image_to_size, convert_image_with_metrics,
+    convert_images_batch, convert_images_batch_with_options, format_extension,
+};
 pub use image_processing::{
-    get_image_dimensions, is_image_file, process_image, remove_letterbox,
-    remove_letterbox_with_threshold, remove_transparency,
+    ImageInfo, get_image_dimensions, get_image_info, get_image_info_batch, is_image_file,
+    normalize_orientation, open_image, open_image_oriented, open_image_with_icc_profile,
+    process_image, remove_letterbox, remove_letterbox_with_threshold, remove_transparency,
+};
+pub use jxl::{
+    JxlMetadata, JxlProcessOptions, JxlProcessReport, PngBitDepth, convert_jxl_to_png,
+    convert_jxl_to_png_with_depth, decode_jxl, has_jpeg_reconstruction_data, is_jxl_file,
+    is_jxl_file_verified, process_jxl_file, process_jxl_file_with_options, read_jxl_metadata,
+    reconstruct_jpeg, reconstruct_jpeg_file,
+};
+#[cfg(feature = "jxl-jpeg-transcode")]
+pub use jxl::{transcode_jpeg_file_to_jxl, transcode_jpeg_to_jxl};
+pub use jxl_animation::{
+    JxlAnimationInfo, convert_animated_jxl, decode_jxl_frames, extract_jxl_frames,
+    jxl_animation_info,
 };
-pub use jxl::{convert_jxl_to_png, is_jxl_file, process_jxl_file};
 pub use layout::{Layout, LayoutElement, LayoutRect};
+pub use metadata::{MetadataKind, MetadataPolicy};
+pub use metrics::{ImageMetrics, compare_image_files, compare_images};
 pub use xyplot::{LabelAlignment, PlotConfig, create_plot};
 
 #[cfg(test)]
 mod tests {
+    mod color_tests;
+    mod encoder_options_tests;
+    mod extensions_tests;
     mod font_tests;
     mod image_processing_tests;
+    mod jxl_animation_tests;
     mod jxl_tests;
+    mod metadata_tests;
+    mod metrics_tests;
     mod numeric_tests;
     mod xyplot_tests;
 }
@@ -0,0 +1,507 @@
+//! Image metadata handling for format conversion.
+//!
+//! Decoders only return pixels, so metadata is read from the input container and
+//! written back by the JPEG encoder or spliced into the encoded PNG, WebP or JPEG XL
+//! file:
+//! - EXIF and XMP from JPEG `APP1` segments, PNG `eXIf`/`iTXt` chunks, WebP `EXIF`/`XMP `
+//!   chunks and JPEG XL `Exif`/`xml ` boxes
+//! - PNG text chunks (`tEXt`, `zTXt` and `iTXt`), which only PNG output can hold
+//! - ICC profiles, which the decoders and encoders handle themselves
+//!
+//! Which of these reach the output is chosen with a [`MetadataPolicy`] on
+//! [`ImageFormatOptions`](crate::formats::ImageFormatOptions).
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use std::path::Path;
+//! use imx::formats::{convert_image, ImageFormatOptions};
+//! use imx::metadata::{MetadataKind, MetadataPolicy};
+//!
+//! async fn example() -> anyhow::Result<()> {
+//!     // Share a photo without its GPS position, keeping colours and rotation
+//!     let policy = MetadataPolicy::KeepOnly(vec![MetadataKind::IccProfile, MetadataKind::Orientation]);
+//!     let options = ImageFormatOptions::jpeg().with_metadata(policy);
+//!     convert_image(Path::new("photo.jpg"), Path::new("shared.jpg"), Some(options)).await?;
+//!     Ok(())
+//! }
+//! ```
+
+#![warn(clippy::all, clippy::pedantic)]
+
+use crate::jxl::{JXL_CODESTREAM_SIGNATURE, JXL_CONTAINER_SIGNATURE};
+use anyhow::{Context, Result};
+use image::DynamicImage;
+use log::warn;
+
+/// Keyword of the PNG `iTXt` chunk holding an XMP packet.
+pub(crate) const XMP_PNG_KEYWORD: &str = "XML:com.adobe.xmp";
+
+/// Prefix of a JPEG `APP1` segment holding EXIF data.
+const JPEG_EXIF_PREFIX: &[u8] = b"Exif\0\0";
+
+/// Prefix of a JPEG `APP1` segment holding an XMP packet.
+pub(crate) const JPEG_XMP_PREFIX: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// The PNG file signature.
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// EXIF tag number of the orientation.
+const ORIENTATION_TAG: u16 = 0x0112;
+
+/// A kind of metadata that can be carried from the input to the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MetadataKind {
+    /// Embedded ICC colour profile
+    IccProfile,
+    /// The EXIF orientation tag on its own (it is also part of [`MetadataKind::Exif`])
+    Orientation,
+    /// The whole EXIF block: camera settings, timestamps, GPS position and orientation
+    Exif,
+    /// XMP packet
+    Xmp,
+    /// PNG text chunks other than XMP, such as generation parameters; PNG output only
+    Text,
+}
+
+/// Which metadata [`convert_image`](crate::formats::convert_image) copies to the output.
+///
+/// Metadata is written to JPEG, PNG, WebP and JPEG XL output; other formats get
+/// none. Dropping an ICC profile (or writing JPEG XL or AVIF, whose encoders
+/// cannot embed one) converts the pixels to sRGB, so colours stay correct. JPEG
+/// XL readers ignore the EXIF orientation, so kept orientations are applied to
+/// JPEG XL pixels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetadataPolicy {
+    /// Keep every kind of metadata the output format can hold
+    KeepAll,
+    /// Keep only the listed kinds
+    KeepOnly(Vec<MetadataKind>),
+    /// Keep no metadata at all
+    StripAll,
+}
+
+impl Default for MetadataPolicy {
+    /// Keeps only the ICC profile, which is needed to display colours correctly.
+    fn default() -> Self {
+        Self::KeepOnly(vec![MetadataKind::IccProfile])
+    }
+}
+
+impl MetadataPolicy {
+    /// Returns whether this policy keeps a kind of metadata.
+    #[must_use]
+    pub fn keeps(&self, kind: MetadataKind) -> bool {
+        match self {
+            Self::KeepAll => true,
+            Self::KeepOnly(kinds) => {
+                kinds.contains(&kind)
+                    || (kind == MetadataKind::Orientation && kinds.contains(&MetadataKind::Exif))
+            }
+            Self::StripAll => false,
+        }
+    }
+}
+
+/// Metadata read from an input file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct ImageMetadata {
+    /// ICC profile describing the decoded pixels
+    pub(crate) icc_profile: Option<Vec<u8>>,
+    /// EXIF data starting at the TIFF header (`II*\0` or `MM\0*`)
+    pub(crate) exif: Option<Vec<u8>>,
+    /// XMP packet
+    pub(crate) xmp: Option<Vec<u8>>,
+    /// Raw PNG text chunks as (chunk type, chunk data)
+    pub(crate) png_text: Vec<([u8; 4], Vec<u8>)>,
+}
+
+impl ImageMetadata {
+    /// Returns the EXIF orientation (1-8), if the EXIF data has one.
+    pub(crate) fn orientation(&self) -> Option<u16> {
+        let exif = self.exif.as_deref()?;
+        let (offset, big_endian) = find_orientation_entry(exif)?;
+        let value = read_u16(exif, offset + 8, big_endian)?;
+        (1..=8).contains(&value).then_some(value)
+    }
+
+    /// Marks the orientation as applied to the pixels by setting the EXIF tag to 1.
+    pub(crate) fn reset_orientation(&mut self) {
+        if let Some(exif) = self.exif.as_mut()
+            && let Some((offset, big_endian)) = find_orientation_entry(exif)
+        {
+            let value = if big_endian { [0, 1] } else { [1, 0] };
+            exif[offset + 8..offset + 10].copy_from_slice(&value);
+        }
+    }
+
+    /// Keeps only the metadata allowed by `policy`.
+    ///
+    /// A kept orientation without the rest of the EXIF block is written as a minimal
+    /// EXIF block holding just that tag.
+    pub(crate) fn filter(self, policy: &MetadataPolicy) -> Self {
+        let exif = if policy.keeps(MetadataKind::Exif) {
+            self.exif.clone()
+        } else if policy.keeps(MetadataKind::Orientation) {
+            self.orientation()
+                .filter(|&orientation| orientation != 1)
+                .map(orientation_exif)
+        } else {
+            None
+        };
+        Self {
+            icc_profile: self
+                .icc_profile
+                .filter(|_| policy.keeps(MetadataKind::IccProfile)),
+            exif,
+            xmp: self.xmp.filter(|_| policy.keeps(MetadataKind::Xmp)),
+            png_text: if policy.keeps(MetadataKind::Text) {
+                self.png_text
+            } else {
+                Vec::new()
+            },
+        }
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize, big_endian: bool) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?.try_into().ok()?;
+    Some(if big_endian {
+        u16::from_be_bytes(bytes)
+    } else {
+        u16::from_le_bytes(bytes)
+    })
+}
+
+fn read_u32(data: &[u8], offset: usize, big_endian: bool) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?.try_into().ok()?;
+    Some(if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    })
+}
+
+/// Finds the orientation entry in the first IFD of EXIF data.
+///
+/// Returns the offset of the 12-byte entry and whether the data is big-endian.
+fn find_orientation_entry(exif: &[u8]) -> Option<(usize, bool)> {
+    let big_endian = match exif.get(..4)? {
+        b"II*\0" => false,
+        b"MM\0*" => true,
+        _ => return None,
+    };
+    let ifd = read_u32(exif, 4, big_endian)? as usize;
+    let entry_count = usize::from(read_u16(exif, ifd, big_endian)?);
+    (0..entry_count)
+        .map(|i| ifd + 2 + i * 12)
+        .find(|&entry| read_u16(exif, entry, big_endian) == Some(ORIENTATION_TAG))
+        .filter(|&entry| entry + 12 <= exif.len())
+        .map(|entry| (entry, big_endian))
+}
+
+/// Builds a little-endian EXIF block holding only an orientation tag.
+fn orientation_exif(orientation: u16) -> Vec<u8> {
+    let mut exif = b"II*\0\x08\0\0\0\x01\0".to_vec();
+    exif.extend_from_slice(&ORIENTATION_TAG.to_le_bytes());
+    // Type SHORT, count 1, value padded to four bytes, then no next IFD
+    exif.extend_from_slice(&[3, 0, 1, 0, 0, 0]);
+    exif.extend_from_slice(&orientation.to_le_bytes());
+    exif.extend_from_slice(&[0; 6]);
+    exif
+}
+
+/// Strips the `Exif\0\0` prefix some writers put in front of the TIFF header.
+fn strip_exif_prefix(exif: &[u8]) -> Vec<u8> {
+    exif.strip_prefix(JPEG_EXIF_PREFIX).unwrap_or(exif).to_vec()
+}
+
+/// Reads EXIF, XMP and PNG text metadata from JPEG, PNG or WebP data.
+///
+/// Other formats, and malformed containers, yield no metadata. The ICC profile
+/// is left to the decoder.
+pub(crate) fn read_container_metadata(data: &[u8]) -> ImageMetadata {
+    if data.starts_with(&[0xFF, 0xD8]) {
+        read_jpeg_metadata(data)
+    } else if data.starts_with(&PNG_SIGNATURE) {
+        read_png_metadata(data)
+    } else if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        read_webp_metadata(data)
+    } else {
+        ImageMetadata::default()
+    }
+}
+
+/// Walks the JPEG segments before the image data.
+fn read_jpeg_metadata(data: &[u8]) -> ImageMetadata {
+    let mut metadata = ImageMetadata::default();
+    let mut pos = 2;
+    while pos + 4 <= data.len() && data[pos] == 0xFF {
+        let marker = data[pos + 1];
+        // Start of scan or end of image: no more metadata segments
+        if marker == 0xDA || marker == 0xD9 {
+            break;
+        }
+        let length = usize::from(u16::from_be_bytes([data[pos + 2], data[pos + 3]]));
+        let Some(segment) = data.get(pos + 4..pos + 2 + length) else {
+            break;
+        };
+        if marker == 0xE1 {
+            if let Some(exif) = segment.strip_prefix(JPEG_EXIF_PREFIX) {
+                metadata.exif.get_or_insert_with(|| exif.to_vec());
+            } else if let Some(xmp) = segment.strip_prefix(JPEG_XMP_PREFIX) {
+                metadata.xmp.get_or_insert_with(|| xmp.to_vec());
+            }
+        }
+        pos += 2 + length;
+    }
+    metadata
+}
+
+/// Iterates over the (type, data) pairs of the chunks of a PNG file.
+fn png_chunks(data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    let mut pos = PNG_SIGNATURE.len();
+    std::iter::from_fn(move || {
+        let length = read_u32(data, pos, true)? as usize;
+        let chunk_type: [u8; 4] = data.get(pos + 4..pos + 8)?.try_into().ok()?;
+        let chunk = data.get(pos + 8..pos + 8 + length)?;
+        pos += 12 + length;
+        Some((chunk_type, chunk))
+    })
+}
+
+/// Returns the text of an uncompressed XMP `iTXt` chunk.
+fn png_itxt_xmp(chunk: &[u8]) -> Option<&[u8]> {
+    let rest = chunk
+        .strip_prefix(XMP_PNG_KEYWORD.as_bytes())?
+        .strip_prefix(&[0])?;
+    // Compression flag and method, then the language tag and translated keyword
+    let (&[0, _], rest) = rest.split_at_checked(2)? else {
+        return None;
+    };
+    let language_end = rest.iter().position(|&b| b == 0)?;
+    let rest = &rest[language_end + 1..];
+    let translated_end = rest.iter().position(|&b| b == 0)?;
+    Some(&rest[translated_end + 1..])
+}
+
+fn read_png_metadata(data: &[u8]) -> ImageMetadata {
+    let mut metadata = ImageMetadata::default();
+    for (chunk_type, chunk) in png_chunks(data) {
+        match &chunk_type {
+            b"eXIf" => {
+                metadata
+                    .exif
+                    .get_or_insert_with(|| strip_exif_prefix(chunk));
+            }
+            b"iTXt" if metadata.xmp.is_none() && png_itxt_xmp(chunk).is_some() => {
+                metadata.xmp = png_itxt_xmp(chunk).map(<[u8]>::to_vec);
+            }
+            b"tEXt" | b"zTXt" | b"iTXt" => metadata.png_text.push((chunk_type, chunk.to_vec())),
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+    metadata
+}
+
+/// Iterates over the (fourcc, payload) pairs of the chunks of a WebP file.
+fn webp_chunks(data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    let mut pos = 12;
+    std::iter::from_fn(move || {
+        let fourcc: [u8; 4] = data.get(pos..pos + 4)?.try_into().ok()?;
+        let length = read_u32(data, pos + 4, false)? as usize;
+        let payload = data.get(pos + 8..pos + 8 + length)?;
+        pos += 8 + length + length % 2;
+        Some((fourcc, payload))
+    })
+}
+
+fn read_webp_metadata(data: &[u8]) -> ImageMetadata {
+    let mut metadata = ImageMetadata::default();
+    for (fourcc, payload) in webp_chunks(data) {
+        match &fourcc {
+            b"EXIF" => metadata.exif = Some(strip_exif_prefix(payload)),
+            b"XMP " => metadata.xmp = Some(payload.to_vec()),
+            _ => {}
+        }
+    }
+    metadata
+}
+
+/// Appends a PNG chunk with its length and CRC.
+fn push_png_chunk(output: &mut Vec<u8>, chunk_type: [u8; 4], data: &[u8]) -> Result<()> {
+    let length = u32::try_from(data.len()).context("PNG chunk is too large")?;
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(&chunk_type);
+    crc.update(data);
+    output.extend_from_slice(&length.to_be_bytes());
+    output.extend_from_slice(&chunk_type);
+    output.extend_from_slice(data);
+    output.extend_from_slice(&crc.finalize().to_be_bytes());
+    Ok(())
+}
+
+/// Inserts `eXIf`, XMP `iTXt` and text chunks into an encoded PNG before its image data.
+pub(crate) fn embed_png_metadata(png: Vec<u8>, metadata: &ImageMetadata) -> Result<Vec<u8>> {
+    let mut chunks = Vec::new();
+    if let Some(exif) = &metadata.exif {
+        push_png_chunk(&mut chunks, *b"eXIf", exif)?;
+    }
+    if let Some(xmp) = &metadata.xmp {
+        // Keyword, no compression, empty language tag and translated keyword
+        let mut data = XMP_PNG_KEYWORD.as_bytes().to_vec();
+        data.extend_from_slice(&[0, 0, 0, 0, 0]);
+        data.extend_from_slice(xmp);
+        push_png_chunk(&mut chunks, *b"iTXt", &data)?;
+    }
+    for (chunk_type, data) in &metadata.png_text {
+        push_png_chunk(&mut chunks, *chunk_type, data)?;
+    }
+    if chunks.is_empty() {
+        return Ok(png);
+    }
+
+    let mut pos = PNG_SIGNATURE.len();
+    let insert_at = png_chunks(&png)
+        .find_map(|(chunk_type, chunk)| {
+            let start = pos;
+            pos += 12 + chunk.len();
+            (&chunk_type == b"IDAT").then_some(start)
+        })
+        .context("Encoded PNG data has no IDAT chunk")?;
+    let mut output = png;
+    output.splice(insert_at..insert_at, chunks);
+    Ok(output)
+}
+
+/// Appends a RIFF chunk, padding its payload to an even length.
+fn push_riff_chunk(output: &mut Vec<u8>, fourcc: [u8; 4], payload: &[u8]) -> Result<()> {
+    let size = u32::try_from(payload.len()).context("RIFF chunk is too large")?;
+    output.extend_from_slice(&fourcc);
+    output.extend_from_slice(&size.to_le_bytes());
+    output.extend_from_slice(payload);
+    if payload.len() % 2 == 1 {
+        output.push(0);
+    }
+    Ok(())
+}
+
+/// Adds ICC, EXIF and XMP chunks to a WebP file written by libwebp.
+///
+/// Metadata chunks need the extended (`VP8X`) file layout. Simple files are
+/// rewritten with a `VP8X` header describing `img`; the `ICCP` chunk goes before
+/// the image data and the `EXIF` and `XMP ` chunks after it.
+pub(crate) fn embed_webp_metadata(
+    webp: &[u8],
+    metadata: &ImageMetadata,
+    img: &DynamicImage,
+) -> Result<Vec<u8>> {
+    const ICC_FLAG: u8 = 0x20;
+    const ALPHA_FLAG: u8 = 0x10;
+    const EXIF_FLAG: u8 = 0x08;
+    const XMP_FLAG: u8 = 0x04;
+
+    if metadata.icc_profile.is_none() && metadata.exif.is_none() && metadata.xmp.is_none() {
+        return Ok(webp.to_vec());
+    }
+    if webp.len() < 20 || &webp[..4] != b"RIFF" || &webp[8..12] != b"WEBP" {
+        anyhow::bail!("Encoded WebP data has no RIFF header");
+    }
+
+    let mut flags = 0;
+    if metadata.icc_profile.is_some() {
+        flags |= ICC_FLAG;
+    }
+    if metadata.exif.is_some() {
+        flags |= EXIF_FLAG;
+    }
+    if metadata.xmp.is_some() {
+        flags |= XMP_FLAG;
+    }
+
+    let chunks = &webp[12..];
+    let mut output = Vec::with_capacity(webp.len() + 64);
+    output.extend_from_slice(b"RIFF\0\0\0\0WEBP");
+    let image_data = if &chunks[..4] == b"VP8X" {
+        // VP8X is 18 bytes: fourcc, size and a 10-byte payload starting with the flags
+        let mut vp8x = chunks
+            .get(..18)
+            .context("Truncated WebP VP8X chunk")?
+            .to_vec();
+        vp8x[8] |= flags;
+        output.extend_from_slice(&vp8x);
+        &chunks[18..]
+    } else {
+        if img.color().has_alpha() {
+            flags |= ALPHA_FLAG;
+        }
+        let mut payload = vec![flags, 0, 0, 0];
+        payload.extend_from_slice(&(img.width() - 1).to_le_bytes()[..3]);
+        payload.extend_from_slice(&(img.height() - 1).to_le_bytes()[..3]);
+        push_riff_chunk(&mut output, *b"VP8X", &payload)?;
+        chunks
+    };
+    if let Some(profile) = &metadata.icc_profile {
+        push_riff_chunk(&mut output, *b"ICCP", profile)?;
+    }
+    output.extend_from_slice(image_data);
+    if let Some(exif) = &metadata.exif {
+        push_riff_chunk(&mut output, *b"EXIF", exif)?;
+    }
+    if let Some(xmp) = &metadata.xmp {
+        push_riff_chunk(&mut output, *b"XMP ", xmp)?;
+    }
+
+    let riff_size = u32::try_from(output.len() - 8).context("WebP file is too large")?;
+    output[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    Ok(output)
+}
+
+/// Appends an ISO BMFF box.
+fn push_box(output: &mut Vec<u8>, box_type: [u8; 4], payload: &[u8]) -> Result<()> {
+    let size = u32::try_from(8 + payload.len()).context("JPEG XL box is too large")?;
+    output.extend_from_slice(&size.to_be_bytes());
+    output.extend_from_slice(&box_type);
+    output.extend_from_slice(payload);
+    Ok(())
+}
+
+/// Stores EXIF and XMP metadata in `Exif` and `xml ` boxes of a JPEG XL file.
+///
+/// A bare codestream is wrapped in a container first. JPEG XL has no place for
+/// the other kinds of metadata outside the codestream.
+pub(crate) fn embed_jxl_metadata(jxl: Vec<u8>, metadata: &ImageMetadata) -> Result<Vec<u8>> {
+    let mut boxes = Vec::new();
+    if let Some(exif) = &metadata.exif {
+        // The payload starts with the offset of the TIFF header
+        let mut payload = vec![0; 4];
+        payload.extend_from_slice(exif);
+        push_box(&mut boxes, *b"Exif", &payload)?;
+    }
+    if let Some(xmp) = &metadata.xmp {
+        push_box(&mut boxes, *b"xml ", xmp)?;
+    }
+    if boxes.is_empty() {
+        return Ok(jxl);
+    }
+
+    if jxl.starts_with(&JXL_CODESTREAM_SIGNATURE) {
+        let mut output = JXL_CONTAINER_SIGNATURE.to_vec();
+        push_box(&mut output, *b"ftyp", b"jxl \0\0\0\0jxl ")?;
+        output.extend_from_slice(&boxes);
+        push_box(&mut output, *b"jxlc", &jxl)?;
+        return Ok(output);
+    }
+    if !jxl.starts_with(&JXL_CONTAINER_SIGNATURE) {
+        anyhow::bail!("Encoded JPEG XL data has no signature");
+    }
+    // The boxes go after the signature and `ftyp` boxes
+    let ftyp_end = read_u32(&jxl, JXL_CONTAINER_SIGNATURE.len(), true)
+        .map(|size| JXL_CONTAINER_SIGNATURE.len() + size as usize)
+        .filter(|&end| end <= jxl.len())
+        .context("Encoded JPEG XL container has no ftyp box")?;
+    let mut output = jxl;
+    output.splice(ftyp_end..ftyp_end, boxes);
+    Ok(output)
+}
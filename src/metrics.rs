@@ -0,0 +1,284 @@
+//! Image quality metrics module.
+//!
+//! Measures how much a lossy conversion changed an image by comparing it with
+//! its source: PSNR over all channels, SSIM and MS-SSIM over the luma, and a map
+//! of the largest channel difference of each pixel. Colours are compared
+//! premultiplied by alpha, so changes hidden under fully transparent pixels do
+//! not count.
+//!
+//! Use [`convert_image_with_metrics`](crate::formats::convert_image_with_metrics)
+//! to measure a conversion while it is made.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use std::path::Path;
+//! use imx::metrics::compare_image_files;
+//!
+//! fn example() -> anyhow::Result<()> {
+//!     let metrics = compare_image_files(Path::new("source.png"), Path::new("lossy.jxl"))?;
+//!     println!("PSNR {:.2} dB, SSIM {:.4}", metrics.psnr, metrics.ssim);
+//!     metrics.error_map.save("errors.png")?;
+//!     Ok(())
+//! }
+//! ```
+
+#![warn(clippy::all, clippy::pedantic)]
+
+use crate::image_processing::open_image;
+use crate::numeric::f32_to_u8;
+use anyhow::Result;
+use image::{DynamicImage, GrayImage, Rgba32FImage};
+use std::path::Path;
+
+/// Differences between a reference image and a distorted copy of it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageMetrics {
+    /// Peak signal-to-noise ratio in dB; infinite for identical images
+    pub psnr: f64,
+    /// Structural similarity of the luma, up to 1 for identical images
+    pub ssim: f64,
+    /// Multi-scale structural similarity of the luma, from 0 to 1 for identical images
+    pub ms_ssim: f64,
+    /// Largest difference of any channel, in 8-bit levels
+    pub max_error: u8,
+    /// Largest channel difference of each pixel, in 8-bit levels
+    pub error_map: GrayImage,
+}
+
+/// Radius of the Gaussian SSIM window, which is 11 pixels wide.
+const SSIM_RADIUS: usize = 5;
+
+/// Standard deviation of the Gaussian SSIM window.
+const SSIM_SIGMA: f32 = 1.5;
+
+/// Stabilising constants of SSIM for samples in 0-1.
+const SSIM_C1: f32 = 0.01 * 0.01;
+const SSIM_C2: f32 = 0.03 * 0.03;
+
+/// Weights of the MS-SSIM scales, finest first (Wang et al. 2003).
+const MS_SSIM_WEIGHTS: [f64; 5] = [0.0448, 0.2856, 0.3001, 0.2363, 0.1333];
+
+/// Compares a distorted image with its reference.
+///
+/// # Arguments
+///
+/// * `reference` - The original image
+/// * `distorted` - The image to measure, such as a lossy re-encode of `reference`
+///
+/// # Returns
+///
+/// Returns PSNR, SSIM, MS-SSIM and the per-pixel error map
+///
+/// # Errors
+///
+/// Returns an error if the images differ in size or are empty
+pub fn compare_images(reference: &DynamicImage, distorted: &DynamicImage) -> Result<ImageMetrics> {
+    let (width, height) = (reference.width(), reference.height());
+    if (distorted.width(), distorted.height()) != (width, height) {
+        anyhow::bail!(
+            "Cannot compare a {}x{} image with a {width}x{height} reference",
+            distorted.width(),
+            distorted.height()
+        );
+    }
+    if width == 0 || height == 0 {
+        anyhow::bail!("Cannot compare empty images");
+    }
+
+    let reference_pixels = premultiplied(reference);
+    let distorted_pixels = premultiplied(distorted);
+    let channels = if reference.color().has_alpha() || distorted.color().has_alpha() {
+        4
+    } else {
+        3
+    };
+
+    let mut squared_error = 0.0_f64;
+    let mut error_map = GrayImage::new(width, height);
+    for ((a, b), error) in reference_pixels
+        .pixels()
+        .zip(distorted_pixels.pixels())
+        .zip(error_map.pixels_mut())
+    {
+        let mut max_difference = 0.0_f32;
+        for channel in 0..channels {
+            let difference = (a[channel] - b[channel]).abs();
+            squared_error += f64::from(difference * difference);
+            max_difference = max_difference.max(difference);
+        }
+        error[0] = f32_to_u8(max_difference * 255.0);
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let mse = squared_error / (u64::from(width) * u64::from(height) * channels as u64) as f64;
+    let psnr = if mse > 0.0 {
+        -10.0 * mse.log10()
+    } else {
+        f64::INFINITY
+    };
+
+    let (width, height) = (width as usize, height as usize);
+    let reference_luma = luma(&reference_pixels);
+    let distorted_luma = luma(&distorted_pixels);
+    let (ssim, _) = ssim_components(&reference_luma, &distorted_luma, width, height);
+    let ms_ssim = ms_ssim(reference_luma, distorted_luma, width, height);
+
+    Ok(ImageMetrics {
+        psnr,
+        ssim,
+        ms_ssim,
+        max_error: error_map.pixels().map(|p| p[0]).max().unwrap_or(0),
+        error_map,
+    })
+}
+
+/// Opens two image files and compares them with [`compare_images`].
+///
+/// Any format [`open_image`] reads works, including JPEG XL.
+///
+/// # Arguments
+///
+/// * `reference` - Path to the original image
+/// * `distorted` - Path to the image to measure
+///
+/// # Returns
+///
+/// Returns PSNR, SSIM, MS-SSIM and the per-pixel error map
+///
+/// # Errors
+///
+/// Returns an error if either image cannot be decoded, or if they differ in size
+pub fn compare_image_files(reference: &Path, distorted: &Path) -> Result<ImageMetrics> {
+    compare_images(&open_image(reference)?, &open_image(distorted)?)
+}
+
+/// Converts an image to floating point RGBA with the colours multiplied by alpha.
+fn premultiplied(img: &DynamicImage) -> Rgba32FImage {
+    let mut pixels = img.to_rgba32f();
+    for pixel in pixels.pixels_mut() {
+        let alpha = pixel[3].clamp(0.0, 1.0);
+        for channel in 0..3 {
+            pixel[channel] = pixel[channel].clamp(0.0, 1.0) * alpha;
+        }
+        pixel[3] = alpha;
+    }
+    pixels
+}
+
+/// Extracts the BT.601 luma plane of an image.
+fn luma(pixels: &Rgba32FImage) -> Vec<f32> {
+    pixels
+        .pixels()
+        .map(|p| 0.299 * p[0] + 0.587 * p[1] + 0.114 * p[2])
+        .collect()
+}
+
+/// Blurs a plane with the Gaussian SSIM window, clamping at the edges.
+fn gaussian_blur(plane: &[f32], width: usize, height: usize) -> Vec<f32> {
+    let mut kernel: Vec<f32> = (0..=2 * SSIM_RADIUS)
+        .map(|i| {
+            #[allow(clippy::cast_precision_loss)]
+            let offset = i.abs_diff(SSIM_RADIUS) as f32;
+            (-offset * offset / (2.0 * SSIM_SIGMA * SSIM_SIGMA)).exp()
+        })
+        .collect();
+    let sum: f32 = kernel.iter().sum();
+    for weight in &mut kernel {
+        *weight /= sum;
+    }
+
+    // Index of the sample under tap `tap` of a window centred on `at`
+    let clamped =
+        |at: usize, tap: usize, len: usize| (at + tap).saturating_sub(SSIM_RADIUS).min(len - 1);
+
+    let mut horizontal = vec![0.0; plane.len()];
+    for y in 0..height {
+        let row = &plane[y * width..(y + 1) * width];
+        for x in 0..width {
+            horizontal[y * width + x] = kernel
+                .iter()
+                .enumerate()
+                .map(|(tap, weight)| weight * row[clamped(x, tap, width)])
+                .sum();
+        }
+    }
+    let mut blurred = vec![0.0; plane.len()];
+    for y in 0..height {
+        for x in 0..width {
+            blurred[y * width + x] = kernel
+                .iter()
+                .enumerate()
+                .map(|(tap, weight)| weight * horizontal[clamped(y, tap, height) * width + x])
+                .sum();
+        }
+    }
+    blurred
+}
+
+/// Mean SSIM and mean contrast-structure term of two luma planes.
+fn ssim_components(x: &[f32], y: &[f32], width: usize, height: usize) -> (f64, f64) {
+    let product =
+        |a: &[f32], b: &[f32]| -> Vec<f32> { a.iter().zip(b).map(|(a, b)| a * b).collect() };
+    let mean_x = gaussian_blur(x, width, height);
+    let mean_y = gaussian_blur(y, width, height);
+    let power_x = gaussian_blur(&product(x, x), width, height);
+    let power_y = gaussian_blur(&product(y, y), width, height);
+    let cross = gaussian_blur(&product(x, y), width, height);
+
+    let mut ssim_sum = 0.0_f64;
+    let mut cs_sum = 0.0_f64;
+    for i in 0..x.len() {
+        let (mx, my) = (mean_x[i], mean_y[i]);
+        let variance_x = power_x[i] - mx * mx;
+        let variance_y = power_y[i] - my * my;
+        let covariance = cross[i] - mx * my;
+        let luminance = (2.0 * mx * my + SSIM_C1) / (mx * mx + my * my + SSIM_C1);
+        let cs = (2.0 * covariance + SSIM_C2) / (variance_x + variance_y + SSIM_C2);
+        ssim_sum += f64::from(luminance * cs);
+        cs_sum += f64::from(cs);
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let count = x.len() as f64;
+    (ssim_sum / count, cs_sum / count)
+}
+
+/// Halves a plane in each direction by averaging 2x2 blocks.
+fn downsample(plane: &[f32], width: usize, height: usize) -> (Vec<f32>, usize, usize) {
+    let (half_width, half_height) = (width / 2, height / 2);
+    let mut half = Vec::with_capacity(half_width * half_height);
+    for y in 0..half_height {
+        for x in 0..half_width {
+            let top = (2 * y) * width + 2 * x;
+            let bottom = top + width;
+            half.push((plane[top] + plane[top + 1] + plane[bottom] + plane[bottom + 1]) / 4.0);
+        }
+    }
+    (half, half_width, half_height)
+}
+
+/// Multi-scale SSIM of two luma planes.
+///
+/// Images too small for all five scales use as many as fit the SSIM window, with
+/// the weights renormalised.
+fn ms_ssim(mut x: Vec<f32>, mut y: Vec<f32>, mut width: usize, mut height: usize) -> f64 {
+    let window = 2 * SSIM_RADIUS + 1;
+    let mut scales = 1;
+    while scales < MS_SSIM_WEIGHTS.len() && width.min(height) >> scales >= window {
+        scales += 1;
+    }
+    let weights = &MS_SSIM_WEIGHTS[..scales];
+    let total: f64 = weights.iter().sum();
+
+    let mut result = 1.0;
+    for (scale, weight) in weights.iter().enumerate() {
+        let (ssim, cs) = ssim_components(&x, &y, width, height);
+        // Negative similarities have no meaningful fractional power
+        let term = if scale + 1 == scales { ssim } else { cs };
+        result *= term.max(0.0).powf(weight / total);
+        if scale + 1 < scales {
+            (y, _, _) = downsample(&y, width, height);
+            (x, width, height) = downsample(&x, width, height);
+        }
+    }
+    result
+}
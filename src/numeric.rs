@@ -189,6 +189,45 @@ pub fn f32_to_u8(x: f32) -> u8 {
     }
 }
 
+/// Safely converts an f32 to u16, with rounding and range clamping.
+///
+/// This is the 16-bit counterpart of [`f32_to_u8`], used for high bit depth
+/// color channels that need to be constrained to the 0-65535 range.
+///
+/// # Arguments
+///
+/// * `x` - The f32 value to convert
+///
+/// # Returns
+///
+/// Returns the converted u16 value, rounded and clamped to 0..=65535
+///
+/// # Examples
+///
+/// ```rust
+/// use imx::numeric::f32_to_u16;
+///
+/// assert_eq!(f32_to_u16(1000.6), 1001);   // Rounds to nearest
+/// assert_eq!(f32_to_u16(70000.0), 65535); // Clamps to max
+/// assert_eq!(f32_to_u16(-5.0), 0);        // Clamps to min
+/// assert_eq!(f32_to_u16(f32::NAN), 0);    // NaN becomes 0
+/// ```
+#[must_use]
+pub fn f32_to_u16(x: f32) -> u16 {
+    if x.is_nan() {
+        0
+    } else if x >= 65535.0 {
+        65535
+    } else if x <= 0.0 {
+        0
+    } else {
+        // Safe because we've bounded x within u16's range
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let result = x.round() as u16;
+        result
+    }
+}
+
 /// Converts an i32 to f32 for text positioning purposes.
 ///
 /// This function is specifically designed for converting screen coordinates
@@ -0,0 +1,196 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use crate::color;
+use crate::formats::{ImageFormatOptions, convert_image};
+use crate::image_processing::{self, open_image_with_icc_profile};
+use image::{DynamicImage, ImageEncoder, Rgb, RgbImage, Rgba, RgbaImage, codecs::png::PngEncoder};
+use std::path::Path;
+use std::sync::OnceLock;
+use tempfile::TempDir;
+
+/// A Display P3 colour and its sRGB equivalent, worked out with the standard
+/// P3-to-sRGB matrix and the sRGB transfer function.
+pub(super) const P3_ORANGE: [u8; 3] = [200, 100, 50];
+pub(super) const SRGB_ORANGE: [u8; 3] = [215, 93, 31];
+
+/// Returns a Display P3 profile, the same bytes on every call.
+///
+/// Encoding stamps the profile with the current time, so it is encoded only once.
+pub(super) fn display_p3_profile() -> Vec<u8> {
+    static PROFILE: OnceLock<Vec<u8>> = OnceLock::new();
+    PROFILE
+        .get_or_init(|| {
+            moxcms::ColorProfile::new_display_p3()
+                .encode()
+                .expect("Display P3 profile should encode")
+        })
+        .clone()
+}
+
+/// Writes a PNG with an embedded ICC profile.
+fn write_png_with_profile(
+    path: &Path,
+    img: &DynamicImage,
+    icc_profile: &[u8],
+) -> anyhow::Result<()> {
+    let mut encoder = PngEncoder::new(std::fs::File::create(path)?);
+    encoder.set_icc_profile(icc_profile.to_vec())?;
+    encoder.write_image(
+        img.as_bytes(),
+        img.width(),
+        img.height(),
+        img.color().into(),
+    )?;
+    Ok(())
+}
+
+pub(super) fn assert_close(actual: &[u8], expected: &[u8], tolerance: u8) {
+    assert_eq!(actual.len(), expected.len());
+    for (a, e) in actual.iter().zip(expected) {
+        assert!(
+            a.abs_diff(*e) <= tolerance,
+            "{actual:?} is not within {tolerance} of {expected:?}"
+        );
+    }
+}
+
+#[test]
+fn test_convert_to_srgb_display_p3() -> anyhow::Result<()> {
+    let profile = display_p3_profile();
+
+    let rgb = DynamicImage::ImageRgb8(RgbImage::from_pixel(4, 4, Rgb(P3_ORANGE)));
+    let converted = color::convert_to_srgb(&rgb, &profile)?;
+    assert_eq!(converted.color(), image::ColorType::Rgb8);
+    assert_close(&converted.to_rgb8().get_pixel(1, 1).0, &SRGB_ORANGE, 2);
+
+    // Alpha passes through, and 16-bit images keep their precision
+    let [r, g, b] = P3_ORANGE;
+    let rgba = DynamicImage::ImageRgba8(RgbaImage::from_pixel(2, 2, Rgba([r, g, b, 77])));
+    let converted = color::convert_to_srgb(&rgba, &profile)?;
+    assert_close(
+        &converted.to_rgba8().get_pixel(0, 0).0[..3],
+        &SRGB_ORANGE,
+        2,
+    );
+    assert_eq!(converted.to_rgba8().get_pixel(0, 0)[3], 77);
+
+    let deep = DynamicImage::ImageRgb16(rgb.to_rgb16());
+    let converted = color::convert_to_srgb(&deep, &profile)?;
+    assert_eq!(converted.color(), image::ColorType::Rgb16);
+    assert_close(&converted.to_rgb8().get_pixel(0, 0).0, &SRGB_ORANGE, 2);
+    Ok(())
+}
+
+#[test]
+fn test_convert_to_srgb_gray_and_errors() -> anyhow::Result<()> {
+    // A linear gray 128 is 188 after the sRGB transfer function
+    let linear_gray = moxcms::ColorProfile::new_gray_with_gamma(1.0).encode()?;
+    let gray = DynamicImage::ImageLuma8(image::GrayImage::from_pixel(3, 3, image::Luma([128])));
+    let converted = color::convert_to_srgb(&gray, &linear_gray)?;
+    assert_eq!(converted.color(), image::ColorType::L8);
+    assert_close(&converted.to_luma8().get_pixel(0, 0).0, &[188], 2);
+
+    // Profiles must match the colour model of the image
+    assert!(color::convert_to_srgb(&gray, &display_p3_profile()).is_err());
+    let rgb = DynamicImage::ImageRgb8(RgbImage::new(2, 2));
+    assert!(color::convert_to_srgb(&rgb, &linear_gray).is_err());
+    assert!(color::convert_to_srgb(&rgb, b"not an ICC profile").is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_convert_image_preserves_icc_profile() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let profile = display_p3_profile();
+    let input = temp_dir.path().join("p3.png");
+    let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(16, 16, Rgb(P3_ORANGE)));
+    write_png_with_profile(&input, &img, &profile)?;
+
+    let (_, read_back) = open_image_with_icc_profile(&input)?;
+    assert_eq!(read_back.as_deref(), Some(profile.as_slice()));
+
+    let outputs = [
+        ("out.png", None),
+        ("out.jpg", None),
+        ("out.webp", None),
+        (
+            "lossless.webp",
+            Some(ImageFormatOptions::webp().with_lossless(true)),
+        ),
+    ];
+    for (name, options) in outputs {
+        let output = temp_dir.path().join(name);
+        convert_image(&input, &output, options).await?;
+        let (decoded, icc_profile) = open_image_with_icc_profile(&output)?;
+        assert_eq!(icc_profile.as_deref(), Some(profile.as_slice()), "{name}");
+        // The pixels are still P3 values
+        assert_close(&decoded.to_rgb8().get_pixel(8, 8).0, &P3_ORANGE, 4);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_convert_image_srgb_conversion() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let input = temp_dir.path().join("p3.png");
+    let [r, g, b] = P3_ORANGE;
+    let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(16, 16, Rgba([r, g, b, 255])));
+    write_png_with_profile(&input, &img, &display_p3_profile())?;
+
+    let output = temp_dir.path().join("srgb.png");
+    let options = ImageFormatOptions::png().with_srgb_conversion(true);
+    convert_image(&input, &output, Some(options)).await?;
+    let (decoded, icc_profile) = open_image_with_icc_profile(&output)?;
+    assert!(icc_profile.is_none());
+    assert_close(&decoded.to_rgb8().get_pixel(3, 3).0, &SRGB_ORANGE, 2);
+
+    // The JPEG XL encoder cannot embed the profile, so it is converted to sRGB as well
+    let jxl = temp_dir.path().join("out.jxl");
+    convert_image(&input, &jxl, Some(ImageFormatOptions::jxl())).await?;
+    let (decoded, icc_profile) = open_image_with_icc_profile(&jxl)?;
+    assert!(icc_profile.is_none());
+    assert_close(&decoded.to_rgb8().get_pixel(3, 3).0, &SRGB_ORANGE, 2);
+
+    // Inputs without a profile are left alone
+    let plain = temp_dir.path().join("plain.png");
+    img.save(&plain)?;
+    let output = temp_dir.path().join("plain_out.png");
+    let options = ImageFormatOptions::png().with_srgb_conversion(true);
+    convert_image(&plain, &output, Some(options)).await?;
+    assert_eq!(image::open(&output)?.to_rgba8(), img.to_rgba8());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_remove_transparency_keeps_icc_profile() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let profile = display_p3_profile();
+    let path = temp_dir.path().join("p3.png");
+    let img = DynamicImage::ImageRgba8(RgbaImage::from_fn(4, 4, |x, _| {
+        Rgba([200, 100, 50, if x == 0 { 0 } else { 255 }])
+    }));
+    write_png_with_profile(&path, &img, &profile)?;
+
+    image_processing::remove_transparency(&path).await?;
+    let (decoded, icc_profile) = open_image_with_icc_profile(&path)?;
+    assert_eq!(icc_profile.as_deref(), Some(profile.as_slice()));
+    let decoded = decoded.to_rgba8();
+    assert_eq!(decoded.get_pixel(0, 0), &Rgba([0, 0, 0, 255]));
+    assert_eq!(decoded.get_pixel(1, 0), &Rgba([200, 100, 50, 255]));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_convert_image_keeps_jxl_icc_profile() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let input = temp_dir.path().join("metadata.jxl");
+    std::fs::write(&input, include_bytes!("../../assets/metadata.jxl"))?;
+    let (_, source_profile) = open_image_with_icc_profile(&input)?;
+    assert!(source_profile.is_some(), "the fixture has a Display P3 profile");
+
+    let output = temp_dir.path().join("out.png");
+    convert_image(&input, &output, None).await?;
+    let (_, icc_profile) = open_image_with_icc_profile(&output)?;
+    assert_eq!(icc_profile, source_profile);
+    Ok(())
+}
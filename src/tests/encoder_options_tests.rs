@@ -0,0 +1,215 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use crate::encoder_options::{
+    AvifOptions, ChromaSubsampling, EncoderOptions, JpegOptions, JxlOptions, PngCompression,
+    PngFilter, PngOptions, WebpOptions,
+};
+use crate::formats::{ImageFormatOptions, convert_image};
+use image::DynamicImage;
+use tempfile::TempDir;
+
+#[test]
+fn test_encoder_options_validate() {
+    assert!(
+        EncoderOptions::from(JpegOptions::default())
+            .validate()
+            .is_ok()
+    );
+    let jpeg = JpegOptions {
+        quality: 0,
+        ..JpegOptions::default()
+    };
+    assert!(jpeg.validate().is_err());
+
+    let webp = WebpOptions {
+        method: Some(7),
+        ..WebpOptions::default()
+    };
+    assert!(webp.validate().is_err());
+    let webp = WebpOptions {
+        alpha_quality: Some(101),
+        ..WebpOptions::default()
+    };
+    assert!(webp.validate().is_err());
+
+    let jxl = JxlOptions {
+        effort: Some(0),
+        ..JxlOptions::default()
+    };
+    let error = EncoderOptions::from(jxl).validate().unwrap_err();
+    assert!(error.to_string().contains("JPEG XL effort"), "{error}");
+
+    let avif = AvifOptions {
+        speed: Some(11),
+        ..AvifOptions::default()
+    };
+    assert!(avif.validate().is_err());
+    let avif = AvifOptions {
+        bit_depth: Some(12),
+        ..AvifOptions::default()
+    };
+    assert!(avif.validate().is_err());
+    assert!(PngOptions::default().validate().is_ok());
+}
+
+#[test]
+fn test_presets_convert_to_typed_options() -> anyhow::Result<()> {
+    assert_eq!(
+        JpegOptions::try_from(&ImageFormatOptions::jpeg())?,
+        JpegOptions::default()
+    );
+    assert_eq!(
+        PngOptions::try_from(&ImageFormatOptions::png())?,
+        PngOptions::default()
+    );
+    assert_eq!(
+        WebpOptions::try_from(&ImageFormatOptions::webp())?,
+        WebpOptions::default()
+    );
+    assert_eq!(
+        JxlOptions::try_from(&ImageFormatOptions::jxl())?,
+        JxlOptions::default()
+    );
+    assert_eq!(
+        AvifOptions::try_from(&ImageFormatOptions::avif())?,
+        AvifOptions::default()
+    );
+
+    // Builder settings and string options carry over
+    let options = ImageFormatOptions::jpeg()
+        .with_quality(70)
+        .with_option("subsampling", "4:2:2")
+        .with_option("progressive", "true");
+    let jpeg = JpegOptions::try_from(&options)?;
+    assert_eq!(jpeg.quality, 70);
+    assert_eq!(jpeg.subsampling, Some(ChromaSubsampling::Yuv422));
+    assert!(jpeg.progressive && !jpeg.optimize_huffman);
+
+    let options = ImageFormatOptions::png()
+        .with_option("compression", "best")
+        .with_option("filter", "up");
+    let png = PngOptions::try_from(&options)?;
+    assert_eq!(png.compression, PngCompression::Best);
+    assert_eq!(png.filter, PngFilter::Up);
+
+    let options = ImageFormatOptions::webp()
+        .with_lossless(true)
+        .with_option("method", "5");
+    let webp = WebpOptions::try_from(&options)?;
+    assert!(webp.lossless);
+    assert_eq!(webp.method, Some(5));
+
+    let options = ImageFormatOptions::avif()
+        .with_option("speed", "8")
+        .with_option("bit_depth", "10");
+    let avif = AvifOptions::try_from(&options)?;
+    assert_eq!(avif.speed, Some(8));
+    assert_eq!(avif.bit_depth, Some(10));
+    Ok(())
+}
+
+#[test]
+fn test_typed_options_take_precedence() -> anyhow::Result<()> {
+    let png = PngOptions {
+        compression: PngCompression::Best,
+        filter: PngFilter::Paeth,
+    };
+    let options = ImageFormatOptions::jpeg().with_encoder_options(png.clone());
+    assert_eq!(PngOptions::try_from(&options)?, png);
+    assert_eq!(
+        PngOptions::try_from(&ImageFormatOptions::from(EncoderOptions::Png(png.clone())))?,
+        png
+    );
+
+    // Options for another format, or mixed with string options, are rejected
+    let error = JpegOptions::try_from(&options).unwrap_err();
+    assert!(error.to_string().contains("PNG encoder options"), "{error}");
+    let mixed = options.with_option("filter", "up");
+    assert!(PngOptions::try_from(&mixed).is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_convert_image_with_typed_options() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let input = temp_dir.path().join("input.png");
+    DynamicImage::new_rgb8(32, 32).save(&input)?;
+
+    let jpeg = JpegOptions {
+        quality: 90,
+        progressive: true,
+        ..JpegOptions::default()
+    };
+    let output = temp_dir.path().join("progressive.jpg");
+    let options = ImageFormatOptions::default().with_encoder_options(jpeg);
+    convert_image(&input, &output, Some(options)).await?;
+    let data = std::fs::read(&output)?;
+    assert!(data.windows(2).any(|marker| marker == [0xFF, 0xC2]));
+
+    // Mismatched formats and invalid settings fail before anything is written
+    let output = temp_dir.path().join("mismatch.jpg");
+    let options = ImageFormatOptions::default().with_encoder_options(WebpOptions::default());
+    assert!(convert_image(&input, &output, Some(options)).await.is_err());
+    let output = temp_dir.path().join("invalid.webp");
+    let webp = WebpOptions {
+        quality: 200,
+        ..WebpOptions::default()
+    };
+    let options = ImageFormatOptions::default().with_encoder_options(webp);
+    assert!(convert_image(&input, &output, Some(options)).await.is_err());
+    assert!(!output.exists());
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_encoder_options_from_config() -> anyhow::Result<()> {
+    #[derive(serde::Deserialize)]
+    struct PipelineConfig {
+        thumbnails: EncoderOptions,
+        archive: EncoderOptions,
+    }
+
+    let config: PipelineConfig = toml::from_str(
+        r#"
+        [thumbnails]
+        format = "jpeg"
+        quality = 80
+        subsampling = "420"
+        progressive = true
+
+        [archive]
+        format = "png"
+        compression = "best"
+        "#,
+    )?;
+    assert_eq!(
+        config.thumbnails,
+        EncoderOptions::Jpeg(JpegOptions {
+            quality: 80,
+            subsampling: Some(ChromaSubsampling::Yuv420),
+            progressive: true,
+            optimize_huffman: false,
+        })
+    );
+    assert_eq!(
+        config.archive,
+        EncoderOptions::Png(PngOptions {
+            compression: PngCompression::Best,
+            filter: PngFilter::Adaptive,
+        })
+    );
+
+    // Round trip, and typos are errors
+    let webp = EncoderOptions::Webp(WebpOptions {
+        method: Some(6),
+        ..WebpOptions::default()
+    });
+    assert_eq!(
+        toml::from_str::<EncoderOptions>(&toml::to_string(&webp)?)?,
+        webp
+    );
+    assert!(toml::from_str::<EncoderOptions>("format = \"jxl\"\nefort = 5").is_err());
+    assert!(toml::from_str::<EncoderOptions>("format = \"gif\"").is_err());
+    Ok(())
+}
@@ -0,0 +1,194 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use crate::extensions::{
+    CollisionPolicy, ExtensionCheck, ExtensionFixOptions, SkipReason, check_image_extension,
+    fix_image_extensions,
+};
+use crate::image_processing::{DetectedImageFormat, SniffConfidence};
+use std::fs;
+use tempfile::TempDir;
+
+const PNG_HEADER: &[u8] = &[
+    0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D,
+];
+const JPEG_HEADER: &[u8] = &[
+    0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, 0x4A, 0x46, 0x49, 0x46, 0x00, 0x01,
+];
+
+#[test]
+fn test_check_image_extension() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+
+    let consistent = temp_dir.path().join("image.png");
+    fs::write(&consistent, PNG_HEADER)?;
+    assert!(matches!(
+        check_image_extension(&consistent)?,
+        ExtensionCheck::Consistent(found) if found.format == DetectedImageFormat::Png
+    ));
+
+    let png_as_jpg = temp_dir.path().join("image.jpg");
+    fs::write(&png_as_jpg, PNG_HEADER)?;
+    let ExtensionCheck::Mismatch(mismatch) = check_image_extension(&png_as_jpg)? else {
+        panic!("Expected a mismatch for a PNG saved as .jpg");
+    };
+    assert_eq!(mismatch.claimed, DetectedImageFormat::Jpeg);
+    assert_eq!(mismatch.actual.format, DetectedImageFormat::Png);
+    assert_eq!(mismatch.actual.confidence, SniffConfidence::High);
+
+    let garbage = temp_dir.path().join("garbage.webp");
+    fs::write(&garbage, b"not an image at all")?;
+    assert_eq!(
+        check_image_extension(&garbage)?,
+        ExtensionCheck::UnknownContent {
+            claimed: DetectedImageFormat::WebP
+        }
+    );
+
+    let text = temp_dir.path().join("notes.txt");
+    fs::write(&text, PNG_HEADER)?;
+    assert_eq!(
+        check_image_extension(&text)?,
+        ExtensionCheck::UnknownExtension
+    );
+
+    assert!(check_image_extension(&temp_dir.path().join("missing.png")).is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fix_image_extensions_renames_with_sidecars() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let dir = temp_dir.path();
+    fs::write(dir.join("photo.jpg"), PNG_HEADER)?;
+    fs::write(dir.join("photo.txt"), b"a caption")?;
+    fs::write(dir.join("photo.jpg.json"), b"{}")?;
+    fs::write(dir.join("correct.jpeg"), JPEG_HEADER)?;
+
+    // Dry run reports without touching anything
+    let report =
+        fix_image_extensions(dir, &ExtensionFixOptions::default().with_dry_run(true)).await?;
+    assert!(report.dry_run);
+    assert_eq!(report.checked, 2);
+    assert_eq!(report.renamed.len(), 1);
+    assert_eq!(report.renamed[0].to, dir.join("photo.png"));
+    assert!(dir.join("photo.jpg").exists());
+    assert!(!dir.join("photo.png").exists());
+
+    let report = fix_image_extensions(dir, &ExtensionFixOptions::default()).await?;
+    assert_eq!(report.renamed.len(), 1);
+    let rename = &report.renamed[0];
+    assert_eq!(rename.from, dir.join("photo.jpg"));
+    assert_eq!(rename.to, dir.join("photo.png"));
+    assert_eq!(rename.mismatch.claimed, DetectedImageFormat::Jpeg);
+    assert_eq!(
+        rename.sidecars,
+        vec![(dir.join("photo.jpg.json"), dir.join("photo.png.json"))]
+    );
+
+    assert!(dir.join("photo.png").exists());
+    assert!(dir.join("photo.png.json").exists());
+    // Same-stem sidecars already match the renamed image
+    assert!(dir.join("photo.txt").exists());
+    assert!(dir.join("correct.jpeg").exists());
+
+    // A second run finds nothing left to fix
+    let report = fix_image_extensions(dir, &ExtensionFixOptions::default()).await?;
+    assert!(report.renamed.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fix_image_extensions_collisions() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let dir = temp_dir.path();
+    fs::write(dir.join("photo.png"), PNG_HEADER)?;
+    fs::write(dir.join("photo.jpg"), PNG_HEADER)?;
+    fs::write(dir.join("photo.txt"), b"a caption")?;
+
+    // Skip leaves the file alone
+    let report = fix_image_extensions(dir, &ExtensionFixOptions::default()).await?;
+    assert!(report.renamed.is_empty());
+    assert_eq!(report.skipped.len(), 1);
+    assert_eq!(
+        report.skipped[0].reason,
+        SkipReason::Collision(dir.join("photo.png"))
+    );
+    assert!(dir.join("photo.jpg").exists());
+
+    // Suffix picks a free stem and moves the sidecars with it
+    let options = ExtensionFixOptions::default().with_collision_policy(CollisionPolicy::Suffix);
+    let report = fix_image_extensions(dir, &options).await?;
+    assert_eq!(report.renamed.len(), 1);
+    assert_eq!(report.renamed[0].to, dir.join("photo_1.png"));
+    assert_eq!(
+        report.renamed[0].sidecars,
+        vec![(dir.join("photo.txt"), dir.join("photo_1.txt"))]
+    );
+    assert!(dir.join("photo.png").exists());
+    assert!(dir.join("photo_1.png").exists());
+    assert!(dir.join("photo_1.txt").exists());
+    assert!(!dir.join("photo.jpg").exists());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fix_image_extensions_options() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let dir = temp_dir.path();
+    let nested = dir.join("nested");
+    fs::create_dir(&nested)?;
+    fs::write(nested.join("deep.webp"), PNG_HEADER)?;
+    // "BM" is a weak BMP signature
+    fs::write(dir.join("weak.png"), b"BM not really a bitmap")?;
+
+    let report = fix_image_extensions(dir, &ExtensionFixOptions::default()).await?;
+    assert!(report.renamed.is_empty());
+    assert_eq!(report.skipped.len(), 1);
+    assert_eq!(report.skipped[0].reason, SkipReason::LowConfidence);
+
+    let options = ExtensionFixOptions::default()
+        .with_recursive(true)
+        .with_min_confidence(SniffConfidence::Low);
+    let report = fix_image_extensions(dir, &options).await?;
+    let mut targets: Vec<_> = report.renamed.iter().map(|r| r.to.clone()).collect();
+    targets.sort();
+    assert_eq!(
+        targets,
+        vec![dir.join("nested/deep.png"), dir.join("weak.bmp")]
+    );
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_fix_image_extensions_links_and_failures() -> anyhow::Result<()> {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::symlink;
+
+    let temp_dir = TempDir::new()?;
+    let dir = temp_dir.path();
+    let nested = dir.join("nested");
+    fs::create_dir(&nested)?;
+    fs::write(nested.join("photo.jpg"), PNG_HEADER)?;
+    // A cycle back to the root, a dangling link and a name that is not UTF-8
+    symlink(dir, nested.join("loop"))?;
+    symlink(dir.join("missing.png"), dir.join("dangling.jpg"))?;
+    let invalid = dir.join(OsStr::from_bytes(b"bad\xff.png"));
+    fs::write(&invalid, JPEG_HEADER)?;
+
+    let options = ExtensionFixOptions::default().with_recursive(true);
+    let report = fix_image_extensions(dir, &options).await?;
+    assert_eq!(report.renamed.len(), 1);
+    assert_eq!(report.renamed[0].to, nested.join("photo.png"));
+    assert_eq!(report.failed.len(), 1);
+    assert_eq!(report.failed[0].path, invalid);
+    assert!(invalid.exists());
+
+    assert!(
+        fix_image_extensions(&dir.join("absent"), &options)
+            .await
+            .is_err()
+    );
+    Ok(())
+}
@@ -4,6 +4,7 @@ use crate::image_processing;
 use image::{GenericImageView, ImageBuffer, Rgba};
 use std::fs::File;
 use std::io::Write;
+use std::path::Path;
 use tempfile::TempDir;
 
 #[tokio::test]
@@ -41,6 +42,7 @@ async fn test_remove_letterbox() -> anyhow::Result<()> {
 }
 
 #[test]
+#[allow(clippy::too_many_lines)]
 fn test_is_image_file() {
     let temp_dir = TempDir::new().unwrap();
 
@@ -175,3 +177,443 @@ fn test_is_image_file() {
         "Non-existent file accepted"
     );
 }
+
+#[test]
+fn test_detect_extended_formats() {
+    use image_processing::{DetectedImageFormat, detect_image_format};
+
+    let cases: [([u8; 12], DetectedImageFormat); 10] = [
+        (*b"GIF89a\x01\x00\x01\x00\x00\x00", DetectedImageFormat::Gif),
+        (*b"GIF87a\x01\x00\x01\x00\x00\x00", DetectedImageFormat::Gif),
+        (
+            *b"BM\x3A\x00\x00\x00\x00\x00\x00\x00\x36\x00",
+            DetectedImageFormat::Bmp,
+        ),
+        (
+            *b"II*\x00\x08\x00\x00\x00\x00\x00\x00\x00",
+            DetectedImageFormat::Tiff,
+        ),
+        (
+            *b"MM\x00*\x00\x00\x00\x08\x00\x00\x00\x00",
+            DetectedImageFormat::Tiff,
+        ),
+        (*b"\x00\x00\x00\x1Cftypavif", DetectedImageFormat::Avif),
+        (*b"\x00\x00\x00\x18ftypheic", DetectedImageFormat::Heic),
+        (*b"\x00\x00\x00\x18ftypmif1", DetectedImageFormat::Heic),
+        (
+            *b"qoif\x00\x00\x00\x01\x00\x00\x00\x01",
+            DetectedImageFormat::Qoi,
+        ),
+        (
+            *b"\x00\x00\x01\x00\x01\x00\x10\x10\x00\x00\x01\x00",
+            DetectedImageFormat::Ico,
+        ),
+    ];
+
+    for (header, expected) in cases {
+        assert_eq!(
+            detect_image_format(&header),
+            Some(expected),
+            "Failed to detect {expected:?}"
+        );
+    }
+
+    // An ICO header without any images is not accepted
+    assert_eq!(
+        detect_image_format(b"\x00\x00\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00"),
+        None
+    );
+    // Unknown ISOBMFF brands are not images
+    assert_eq!(detect_image_format(b"\x00\x00\x00\x18ftypisom"), None);
+}
+
+#[test]
+fn test_detected_format_extensions_consistent() {
+    use image_processing::DetectedImageFormat;
+
+    for format in DetectedImageFormat::ALL {
+        assert!(format.all_extensions().contains(&format.extension()));
+        for ext in format.all_extensions() {
+            assert_eq!(DetectedImageFormat::from_extension(ext), Some(format));
+            assert_eq!(
+                DetectedImageFormat::from_extension(&ext.to_uppercase()),
+                Some(format)
+            );
+        }
+        if let Some(image_format) = format.to_image_format() {
+            assert!(
+                image_format.extensions_str().contains(&format.extension()),
+                "{format:?} extension not known to image crate"
+            );
+        }
+    }
+    assert_eq!(DetectedImageFormat::from_extension("txt"), None);
+}
+
+#[test]
+fn test_is_image_file_extended_formats() -> anyhow::Result<()> {
+    use image::{DynamicImage, ImageFormat};
+
+    let temp_dir = TempDir::new()?;
+    let img = DynamicImage::new_rgba8(8, 8);
+
+    for (name, format) in [
+        ("test.gif", ImageFormat::Gif),
+        ("test.bmp", ImageFormat::Bmp),
+        ("test.tif", ImageFormat::Tiff),
+        ("test.tiff", ImageFormat::Tiff),
+        ("test.qoi", ImageFormat::Qoi),
+        ("test.ico", ImageFormat::Ico),
+    ] {
+        let path = temp_dir.path().join(name);
+        img.save_with_format(&path, format)?;
+        assert!(
+            image_processing::is_image_file(&path),
+            "{name} not recognized as image"
+        );
+    }
+
+    // A GIF saved with a BMP extension is still an image
+    let gif_as_bmp = temp_dir.path().join("actually_gif.bmp");
+    img.save_with_format(&gif_as_bmp, ImageFormat::Gif)?;
+    assert!(image_processing::is_image_file(&gif_as_bmp));
+
+    Ok(())
+}
+
+#[test]
+fn test_is_image_file_jxl_container() -> anyhow::Result<()> {
+    use image_processing::{DetectedImageFormat, detect_image_format};
+
+    assert_eq!(
+        detect_image_format(&crate::jxl::JXL_CONTAINER_SIGNATURE),
+        Some(DetectedImageFormat::Jxl)
+    );
+
+    let temp_dir = TempDir::new()?;
+    let path = temp_dir.path().join("container.jxl");
+    let mut data = crate::jxl::JXL_CONTAINER_SIGNATURE.to_vec();
+    data.extend_from_slice(b"\x00\x00\x00\x14ftypjxl \x00\x00\x00\x00jxl ");
+    std::fs::write(&path, data)?;
+    assert!(image_processing::is_image_file(&path));
+    Ok(())
+}
+
+#[test]
+fn test_sniff_image_format_slices() {
+    use image_processing::{
+        DetectedImageFormat, SniffConfidence, SniffOutcome, SniffReason, sniff_image_format,
+    };
+
+    // Short but complete signatures are detected without padding
+    let png = sniff_image_format(b"\x89PNG\r\n\x1a\n");
+    assert_eq!(png.format(), Some(DetectedImageFormat::Png));
+    assert_eq!(
+        png.best_match()
+            .map(|found| (found.confidence, found.reason)),
+        Some((SniffConfidence::High, SniffReason::MagicNumber))
+    );
+    assert_eq!(
+        sniff_image_format(&[0xFF, 0x0A]).format(),
+        Some(DetectedImageFormat::Jxl)
+    );
+    assert_eq!(
+        sniff_image_format(b"BM")
+            .best_match()
+            .map(|found| found.confidence),
+        Some(SniffConfidence::Low)
+    );
+
+    // Truncated signatures report how many bytes are needed
+    assert!(matches!(
+        sniff_image_format(b"\x89PN"),
+        SniffOutcome::Inconclusive { needed: 8, .. }
+    ));
+    assert!(matches!(
+        sniff_image_format(b"RIFF\x24\x00\x00\x00WE"),
+        SniffOutcome::Inconclusive { needed: 12, .. }
+    ));
+    assert!(matches!(
+        sniff_image_format(b""),
+        SniffOutcome::Inconclusive { needed: 12, .. }
+    ));
+
+    // Non-image data is rejected once enough bytes are available
+    assert_eq!(
+        sniff_image_format(b"This is a text file"),
+        SniffOutcome::Unknown
+    );
+}
+
+#[test]
+fn test_sniff_image_format_ftyp_brands() {
+    use image_processing::{
+        DetectedImageFormat, SniffConfidence, SniffOutcome, SniffReason, sniff_image_format,
+    };
+
+    // Generic HEIF major brand refined by an AVIF compatible brand
+    let generic = b"\x00\x00\x00\x1Cftypmif1\x00\x00\x00\x00mif1miafavif";
+    let outcome = sniff_image_format(generic);
+    assert_eq!(outcome.format(), Some(DetectedImageFormat::Avif));
+    assert_eq!(
+        outcome.best_match().map(|found| found.reason),
+        Some(SniffReason::CompatibleBrand(*b"avif"))
+    );
+
+    // Without the compatible brands the sniffer asks for the rest of the box
+    match sniff_image_format(&generic[..12]) {
+        SniffOutcome::Inconclusive { needed, best_guess } => {
+            assert_eq!(needed, 0x1C);
+            let guess = best_guess.expect("generic HEIF brand should give a guess");
+            assert_eq!(guess.format, DetectedImageFormat::Heic);
+            assert_eq!(guess.confidence, SniffConfidence::Low);
+        }
+        other => panic!("Expected inconclusive outcome, got {other:?}"),
+    }
+
+    // Major brands are conclusive on their own
+    assert_eq!(
+        sniff_image_format(b"\x00\x00\x00\x18ftypavis").format(),
+        Some(DetectedImageFormat::Avif)
+    );
+    assert_eq!(
+        sniff_image_format(b"\x00\x00\x00\x14ftypisom\x00\x00\x00\x00isom"),
+        SniffOutcome::Unknown
+    );
+}
+
+#[test]
+fn test_sniff_image_format_from_reader() -> anyhow::Result<()> {
+    use image_processing::{DetectedImageFormat, SniffOutcome, sniff_image_format_from_reader};
+    use std::io::{Cursor, Read};
+
+    // Only the bytes needed are consumed, and they can be chained back
+    let data = b"\x00\x00\x00\x1Cftypmif1\x00\x00\x00\x00mif1miafavifTRAILING";
+    let mut reader = Cursor::new(&data[..]);
+    let (outcome, header) = sniff_image_format_from_reader(&mut reader)?;
+    assert_eq!(outcome.format(), Some(DetectedImageFormat::Avif));
+    assert_eq!(header.len(), 0x1C);
+    let mut rejoined = Vec::new();
+    Cursor::new(header)
+        .chain(reader)
+        .read_to_end(&mut rejoined)?;
+    assert_eq!(rejoined, data);
+
+    // A stream that ends early falls back to the best guess
+    let (outcome, _) =
+        sniff_image_format_from_reader(&mut Cursor::new(&b"\x00\x00\x00\x1Cftypmif1"[..]))?;
+    assert_eq!(outcome.format(), Some(DetectedImageFormat::Heic));
+
+    // Or to unknown when there is none
+    let (outcome, header) = sniff_image_format_from_reader(&mut Cursor::new(&b"\x89PN"[..]))?;
+    assert_eq!(outcome, SniffOutcome::Unknown);
+    assert_eq!(header, b"\x89PN");
+    Ok(())
+}
+
+#[test]
+fn test_is_image_file_short_header() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+
+    // Fewer than 12 bytes, but a complete signature
+    let short_png = temp_dir.path().join("short.png");
+    std::fs::write(&short_png, b"\x89PNG\r\n\x1a\n")?;
+    assert!(image_processing::is_image_file(&short_png));
+
+    let short_jxl = temp_dir.path().join("short.jxl");
+    std::fs::write(&short_jxl, [0xFF, 0x0A, 0x00])?;
+    assert!(image_processing::is_image_file(&short_jxl));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_open_image_jxl_by_content() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let png_path = temp_dir.path().join("source.png");
+    let jxl_path = temp_dir.path().join("image.jxl");
+
+    let img = ImageBuffer::from_fn(40, 30, |x, y| {
+        #[allow(clippy::cast_possible_truncation)]
+        Rgba([(x * 6) as u8, (y * 8) as u8, 77, 200])
+    });
+    img.save(&png_path)?;
+    let options = crate::formats::ImageFormatOptions::jxl();
+    crate::formats::convert_image(&png_path, &jxl_path, Some(options)).await?;
+
+    let decoded = image_processing::open_image(&jxl_path)?;
+    assert_eq!(decoded.to_rgba8(), img);
+    assert_eq!(image_processing::get_image_dimensions(&jxl_path)?, (40, 30));
+
+    // A JXL saved under the wrong extension is still decoded by content
+    let misnamed = temp_dir.path().join("actually_jxl.png");
+    std::fs::copy(&jxl_path, &misnamed)?;
+    assert_eq!(image_processing::open_image(&misnamed)?.to_rgba8(), img);
+
+    // And other formats are detected by content too
+    let misnamed_png = temp_dir.path().join("actually_png.jpg");
+    std::fs::copy(&png_path, &misnamed_png)?;
+    assert_eq!(image_processing::open_image(&misnamed_png)?.to_rgba8(), img);
+
+    assert!(image_processing::open_image(&temp_dir.path().join("missing.jxl")).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_get_image_info() -> anyhow::Result<()> {
+    use image_processing::DetectedImageFormat;
+
+    let temp_dir = TempDir::new()?;
+    let png_path = temp_dir.path().join("deep.png");
+    image::ImageBuffer::<image::Rgb<u16>, _>::from_pixel(12, 7, image::Rgb([1000, 2000, 3000]))
+        .save(&png_path)?;
+    let info = image_processing::get_image_info(&png_path)?;
+    assert_eq!((info.width, info.height), (12, 7));
+    assert_eq!(info.format, Some(DetectedImageFormat::Png));
+    assert_eq!(info.color_type, image::ColorType::Rgb16);
+
+    let jpeg_path = temp_dir.path().join("photo.jpg");
+    image::GrayImage::from_pixel(33, 21, image::Luma([90])).save(&jpeg_path)?;
+    let info = image_processing::get_image_info(&jpeg_path)?;
+    assert_eq!((info.width, info.height), (33, 21));
+    assert_eq!(info.format, Some(DetectedImageFormat::Jpeg));
+    assert_eq!(info.color_type, image::ColorType::L8);
+
+    let jxl_path = temp_dir.path().join("animated.jxl");
+    std::fs::write(&jxl_path, include_bytes!("../../assets/animated.jxl"))?;
+    let info = image_processing::get_image_info(&jxl_path)?;
+    assert_eq!((info.width, info.height), (24, 16));
+    assert_eq!(info.format, Some(DetectedImageFormat::Jxl));
+    assert_eq!(info.color_type, image::ColorType::Rgba8);
+
+    // Only the headers are needed, so a file cut off in its pixel data still works
+    let png_data = std::fs::read(&png_path)?;
+    let truncated = temp_dir.path().join("truncated.png");
+    let idat = png_data
+        .windows(4)
+        .position(|w| w == b"IDAT")
+        .expect("PNG has no IDAT chunk");
+    std::fs::write(&truncated, &png_data[..idat + 8])?;
+    assert_eq!(image_processing::get_image_dimensions(&truncated)?, (12, 7));
+    let jxl_data = std::fs::read(&jxl_path)?;
+    let truncated_jxl = temp_dir.path().join("truncated.jxl");
+    std::fs::write(&truncated_jxl, &jxl_data[..jxl_data.len() / 2])?;
+    assert_eq!(
+        image_processing::get_image_dimensions(&truncated_jxl)?,
+        (24, 16)
+    );
+
+    // Formats are detected by content, not extension
+    let misnamed = temp_dir.path().join("actually_jxl.png");
+    std::fs::copy(&jxl_path, &misnamed)?;
+    assert_eq!(
+        image_processing::get_image_info(&misnamed)?.format,
+        Some(DetectedImageFormat::Jxl)
+    );
+    Ok(())
+}
+
+#[test]
+fn test_get_image_info_batch() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let good = temp_dir.path().join("good.png");
+    image::RgbaImage::new(5, 4).save(&good)?;
+    let bad = temp_dir.path().join("bad.png");
+    std::fs::write(&bad, b"not an image at all")?;
+    let missing = temp_dir.path().join("missing.png");
+
+    let paths = vec![bad.clone(), good.clone(), missing.clone()];
+    let results = image_processing::get_image_info_batch(&paths);
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].0, bad);
+    assert!(results[0].1.is_err());
+    assert_eq!(results[1].0, good);
+    let info = results[1].1.as_ref().expect("good.png should be readable");
+    assert_eq!((info.width, info.height), (5, 4));
+    assert_eq!(info.color_type, image::ColorType::Rgba8);
+    assert_eq!(results[2].0, missing);
+    assert!(results[2].1.is_err());
+    Ok(())
+}
+
+/// Writes a 32x16 JPEG, red on the left half and blue on the right, with an EXIF
+/// orientation tag.
+pub(super) fn write_jpeg_with_orientation(path: &Path, orientation: u16) -> anyhow::Result<()> {
+    let img = image::RgbImage::from_fn(32, 16, |x, _| {
+        if x < 16 {
+            image::Rgb([255, 0, 0])
+        } else {
+            image::Rgb([0, 0, 255])
+        }
+    });
+    let mut jpeg = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, 95).encode_image(&img)?;
+
+    // A little-endian TIFF header with one IFD entry: Orientation (SHORT)
+    let mut exif = b"Exif\0\0II*\0\x08\0\0\0\x01\0\x12\x01\x03\0\x01\0\0\0".to_vec();
+    exif.extend_from_slice(&orientation.to_le_bytes());
+    exif.extend_from_slice(&[0; 6]);
+    let mut segment = vec![0xFF, 0xE1];
+    segment.extend_from_slice(&u16::try_from(exif.len() + 2)?.to_be_bytes());
+    segment.extend_from_slice(&exif);
+    jpeg.splice(2..2, segment);
+    std::fs::write(path, jpeg)?;
+    Ok(())
+}
+
+/// Checks that an upright version of the image from [`write_jpeg_with_orientation`]
+/// (rotated 90 degrees clockwise) has red on top and blue at the bottom.
+pub(super) fn assert_upright(img: &image::RgbImage) {
+    assert_eq!(img.dimensions(), (16, 32));
+    let top = img.get_pixel(8, 4);
+    let bottom = img.get_pixel(8, 28);
+    assert!(top[0] > 200 && top[2] < 60, "top is not red: {top:?}");
+    assert!(
+        bottom[2] > 200 && bottom[0] < 60,
+        "bottom is not blue: {bottom:?}"
+    );
+}
+
+#[tokio::test]
+async fn test_exif_orientation() -> anyhow::Result<()> {
+    use image::metadata::Orientation;
+
+    let temp_dir = TempDir::new()?;
+    let path = temp_dir.path().join("phone.jpg");
+    write_jpeg_with_orientation(&path, 6)?;
+
+    let info = image_processing::get_image_info(&path)?;
+    assert_eq!(info.orientation, Orientation::Rotate90);
+    assert_eq!((info.width, info.height), (32, 16));
+    assert_eq!(info.oriented_dimensions(), (16, 32));
+
+    // Plain opening keeps the stored orientation
+    assert_eq!(image_processing::open_image(&path)?.dimensions(), (32, 16));
+    assert_upright(&image_processing::open_image_oriented(&path)?.to_rgb8());
+
+    assert!(image_processing::normalize_orientation(&path).await?);
+    let info = image_processing::get_image_info(&path)?;
+    assert_eq!(info.orientation, Orientation::NoTransforms);
+    assert_upright(&image_processing::open_image(&path)?.to_rgb8());
+    // The tag is gone, so a second pass leaves the file alone
+    let modified = std::fs::metadata(&path)?.modified()?;
+    assert!(!image_processing::normalize_orientation(&path).await?);
+    assert_eq!(std::fs::metadata(&path)?.modified()?, modified);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_convert_image_auto_orient() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let input = temp_dir.path().join("phone.jpg");
+    write_jpeg_with_orientation(&input, 6)?;
+
+    let stored = temp_dir.path().join("stored.png");
+    crate::formats::convert_image(&input, &stored, None).await?;
+    assert_eq!(image::open(&stored)?.dimensions(), (32, 16));
+
+    let upright = temp_dir.path().join("upright.png");
+    let options = crate::formats::ImageFormatOptions::png().with_auto_orient(true);
+    crate::formats::convert_image(&input, &upright, Some(options)).await?;
+    assert_upright(&image::open(&upright)?.to_rgb8());
+    Ok(())
+}
@@ -0,0 +1,203 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use crate::jxl::{self, PngBitDepth};
+use crate::jxl_animation::{self, AnimationFormat};
+use image::AnimationDecoder;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+use std::time::Duration;
+use tempfile::TempDir;
+
+/// A lossless 24x16 RGBA animation: three frames shown for 10, 20 and 30 ticks
+/// at 100 ticks per second, played twice. Frame `i` has a red band over
+/// columns `8 * i .. 8 * i + 8`, and the first two columns are transparent.
+const ANIMATED_JXL: &[u8] = include_bytes!("../../assets/animated.jxl");
+
+const FRAME_DELAYS_MS: [u32; 3] = [100, 200, 300];
+
+/// Checks that a decoded frame has its red band in the right place.
+fn assert_frame_content(frame: &image::RgbaImage, index: u32) {
+    assert_eq!(frame.dimensions(), (24, 16));
+    for x in 0..24 {
+        let pixel = frame.get_pixel(x, 5);
+        assert_eq!(pixel[3] == 0, x < 2, "frame {index}, x {x}: {pixel:?}");
+        // Encoders may drop the color of transparent pixels
+        if pixel[3] != 0 {
+            let expected_red = if x / 8 == index { 255 } else { 0 };
+            assert!(
+                pixel[0].abs_diff(expected_red) < 16,
+                "frame {index}, x {x}: {pixel:?}"
+            );
+        }
+    }
+}
+
+/// Returns the frame delays in milliseconds of a decoded animation.
+fn delays_ms(frames: &[image::Frame]) -> Vec<u32> {
+    frames
+        .iter()
+        .map(|frame| {
+            let (numerator, denominator) = frame.delay().numer_denom_ms();
+            numerator / denominator
+        })
+        .collect()
+}
+
+#[test]
+fn test_jxl_animation_info() -> anyhow::Result<()> {
+    let info = jxl_animation::jxl_animation_info(ANIMATED_JXL)?;
+    assert!(info.is_animated());
+    assert_eq!(info.frame_count, 3);
+    assert_eq!(
+        info.frame_durations,
+        FRAME_DELAYS_MS.map(|ms| Duration::from_millis(u64::from(ms)))
+    );
+    assert_eq!(info.loop_count, 2);
+    assert_eq!(info.ticks_per_second, Some((100, 1)));
+    assert_eq!(info.total_duration(), Duration::from_millis(600));
+
+    assert!(jxl_animation::jxl_animation_info(b"not a jxl file").is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_jxl_animation_info_still_image() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let png = temp_dir.path().join("still.png");
+    let jxl_path = temp_dir.path().join("still.jxl");
+    image::RgbImage::from_pixel(8, 8, image::Rgb([10, 20, 30])).save(&png)?;
+    crate::formats::convert_image(
+        &png,
+        &jxl_path,
+        Some(crate::formats::ImageFormatOptions::jxl()),
+    )
+    .await?;
+
+    let info = jxl_animation::jxl_animation_info(&fs::read(&jxl_path)?)?;
+    assert!(!info.is_animated());
+    assert_eq!(info.frame_count, 1);
+    assert_eq!(info.frame_durations, vec![Duration::ZERO]);
+    Ok(())
+}
+
+#[test]
+fn test_decode_jxl_frames() -> anyhow::Result<()> {
+    let frames = jxl_animation::decode_jxl_frames(ANIMATED_JXL)?;
+    assert_eq!(frames.len(), 3);
+    for (index, frame) in (0u32..).zip(&frames) {
+        assert_eq!(frame.image.color(), image::ColorType::Rgba8);
+        assert_eq!(
+            frame.duration,
+            Duration::from_millis(u64::from(FRAME_DELAYS_MS[index as usize]))
+        );
+        assert_frame_content(&frame.image.to_rgba8(), index);
+    }
+
+    // The single-image decoder still returns the first frame
+    assert_eq!(
+        jxl::decode_jxl(ANIMATED_JXL)?.to_rgba8(),
+        frames[0].image.to_rgba8()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_animation_format_from_path() {
+    assert_eq!(
+        AnimationFormat::from_path(Path::new("a.png")),
+        Some(AnimationFormat::Apng)
+    );
+    assert_eq!(
+        AnimationFormat::from_path(Path::new("a.APNG")),
+        Some(AnimationFormat::Apng)
+    );
+    assert_eq!(
+        AnimationFormat::from_path(Path::new("a.webp")),
+        Some(AnimationFormat::WebP)
+    );
+    assert_eq!(
+        AnimationFormat::from_path(Path::new("a.gif")),
+        Some(AnimationFormat::Gif)
+    );
+    assert_eq!(AnimationFormat::from_path(Path::new("a.jpg")), None);
+    assert_eq!(AnimationFormat::from_path(Path::new("a")), None);
+}
+
+#[tokio::test]
+async fn test_convert_animated_jxl() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let input = temp_dir.path().join("animated.jxl");
+    fs::write(&input, ANIMATED_JXL)?;
+
+    let apng = temp_dir.path().join("out.png");
+    jxl_animation::convert_animated_jxl(&input, &apng, None).await?;
+    let decoder = image::codecs::png::PngDecoder::new(Cursor::new(fs::read(&apng)?))?;
+    assert!(decoder.is_apng()?);
+    let frames = decoder.apng()?.into_frames().collect_frames()?;
+    assert_eq!(delays_ms(&frames), FRAME_DELAYS_MS);
+    for (index, frame) in (0u32..).zip(&frames) {
+        assert_frame_content(frame.buffer(), index);
+    }
+
+    let webp = temp_dir.path().join("out.webp");
+    let lossless = crate::formats::ImageFormatOptions::webp().with_lossless(true);
+    jxl_animation::convert_animated_jxl(&input, &webp, Some(lossless)).await?;
+    let decoder = image::codecs::webp::WebPDecoder::new(Cursor::new(fs::read(&webp)?))?;
+    let frames = decoder.into_frames().collect_frames()?;
+    assert_eq!(delays_ms(&frames), FRAME_DELAYS_MS);
+    for (index, frame) in (0u32..).zip(&frames) {
+        assert_frame_content(frame.buffer(), index);
+    }
+
+    let gif = temp_dir.path().join("out.gif");
+    jxl_animation::convert_animated_jxl(&input, &gif, None).await?;
+    let gif_data = fs::read(&gif)?;
+    let decoder = image::codecs::gif::GifDecoder::new(Cursor::new(&gif_data))?;
+    let frames = decoder.into_frames().collect_frames()?;
+    assert_eq!(delays_ms(&frames), FRAME_DELAYS_MS);
+    for (index, frame) in (0u32..).zip(&frames) {
+        assert_frame_content(frame.buffer(), index);
+    }
+    // Two plays are stored as one repeat in the NETSCAPE extension
+    let netscape = gif_data
+        .windows(11)
+        .position(|w| w == b"NETSCAPE2.0")
+        .expect("GIF has no loop extension");
+    assert_eq!(&gif_data[netscape + 13..netscape + 15], &[1, 0]);
+
+    let unsupported = temp_dir.path().join("out.jpg");
+    let result = jxl_animation::convert_animated_jxl(&input, &unsupported, None).await;
+    assert!(
+        result
+            .unwrap_err()
+            .to_string()
+            .contains("Unsupported animation output format")
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_extract_jxl_frames() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let input = temp_dir.path().join("clip.jxl");
+    fs::write(&input, ANIMATED_JXL)?;
+    let output_dir = temp_dir.path().join("frames");
+
+    let paths = jxl_animation::extract_jxl_frames(&input, &output_dir, PngBitDepth::Eight).await?;
+    let names: Vec<_> = paths
+        .iter()
+        .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+        .collect();
+    assert_eq!(names, ["clip_0000.png", "clip_0001.png", "clip_0002.png"]);
+    for (index, path) in (0u32..).zip(&paths) {
+        let frame = image::open(path)?;
+        assert_eq!(frame.color(), image::ColorType::Rgba8);
+        assert_frame_content(&frame.to_rgba8(), index);
+    }
+
+    let paths =
+        jxl_animation::extract_jxl_frames(&input, &output_dir, PngBitDepth::Sixteen).await?;
+    assert_eq!(image::open(&paths[2])?.color(), image::ColorType::Rgba16);
+    Ok(())
+}
@@ -60,3 +60,484 @@ async fn test_process_jxl_file_with_processor() -> anyhow::Result<()> {
     assert!(processed.load(std::sync::atomic::Ordering::SeqCst)); // But processor should still be called
     Ok(())
 }
+
+#[test]
+fn test_is_jxl_file_verified() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+
+    let codestream = temp_dir.path().join("codestream.jxl");
+    fs::write(&codestream, [0xFF, 0x0A, 0xFA, 0x1F])?;
+    assert!(jxl::is_jxl_file_verified(&codestream));
+
+    let mut container_data = jxl::JXL_CONTAINER_SIGNATURE.to_vec();
+    container_data.extend_from_slice(b"\x00\x00\x00\x14ftypjxl \x00\x00\x00\x00jxl ");
+    let container = temp_dir.path().join("container.jxl");
+    fs::write(&container, &container_data)?;
+    assert!(jxl::is_jxl_file_verified(&container));
+
+    // Right extension, wrong contents
+    let fake = temp_dir.path().join("fake.jxl");
+    fs::write(&fake, b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0D")?;
+    assert!(!jxl::is_jxl_file_verified(&fake));
+    assert!(jxl::is_jxl_file(&fake));
+
+    // Right contents, wrong extension
+    let wrong_ext = temp_dir.path().join("container.png");
+    fs::write(&wrong_ext, &container_data)?;
+    assert!(!jxl::is_jxl_file_verified(&wrong_ext));
+
+    assert!(!jxl::is_jxl_file_verified(
+        &temp_dir.path().join("missing.jxl")
+    ));
+    Ok(())
+}
+
+/// Build an ISOBMFF box with the given type and payload
+fn jxl_box(box_type: [u8; 4], payload: &[u8]) -> Vec<u8> {
+    let size = u32::try_from(payload.len() + 8).unwrap();
+    let mut data = size.to_be_bytes().to_vec();
+    data.extend_from_slice(&box_type);
+    data.extend_from_slice(payload);
+    data
+}
+
+#[test]
+fn test_has_jpeg_reconstruction_data() {
+    let mut container = jxl::JXL_CONTAINER_SIGNATURE.to_vec();
+    container.extend(jxl_box(*b"ftyp", b"jxl \0\0\0\0jxl "));
+    container.extend(jxl_box(*b"jxlp", &[0, 0, 0, 0, 0xFF, 0x0A]));
+    assert!(!jxl::has_jpeg_reconstruction_data(&container));
+
+    let mut with_jbrd = container.clone();
+    with_jbrd.extend(jxl_box(*b"jbrd", &[1, 2, 3]));
+    assert!(jxl::has_jpeg_reconstruction_data(&with_jbrd));
+
+    // 64-bit box sizes are followed
+    let mut large = container.clone();
+    large.extend_from_slice(&1u32.to_be_bytes());
+    large.extend_from_slice(b"jxlc");
+    large.extend_from_slice(&18u64.to_be_bytes());
+    large.extend_from_slice(&[0xFF, 0x0A]);
+    large.extend(jxl_box(*b"jbrd", &[]));
+    assert!(jxl::has_jpeg_reconstruction_data(&large));
+
+    // Bare codestreams and malformed boxes never report reconstruction data
+    assert!(!jxl::has_jpeg_reconstruction_data(&[0xFF, 0x0A, 0x00]));
+    let mut malformed = container.clone();
+    malformed.extend_from_slice(&4u32.to_be_bytes());
+    malformed.extend_from_slice(b"junk");
+    malformed.extend(jxl_box(*b"jbrd", &[]));
+    assert!(!jxl::has_jpeg_reconstruction_data(&malformed));
+
+    // 64-bit sizes running past the end of the address space do not overflow
+    let offset = container.len() as u64;
+    for size in [u64::MAX, u64::MAX - offset, u64::MAX - offset - 4] {
+        let mut huge = container.clone();
+        huge.extend_from_slice(&1u32.to_be_bytes());
+        huge.extend_from_slice(b"jxlc");
+        huge.extend_from_slice(&size.to_be_bytes());
+        huge.extend(jxl_box(*b"jbrd", &[]));
+        assert!(!jxl::has_jpeg_reconstruction_data(&huge), "{size}");
+    }
+}
+
+#[tokio::test]
+async fn test_reconstruct_jpeg_requires_jbrd() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let png = temp_dir.path().join("test.png");
+    let jxl_path = temp_dir.path().join("test.jxl");
+    image::DynamicImage::new_rgb8(16, 16).save(&png)?;
+    let options = crate::formats::ImageFormatOptions::jxl();
+    crate::formats::convert_image(&png, &jxl_path, Some(options)).await?;
+
+    let err = jxl::reconstruct_jpeg(&fs::read(&jxl_path)?).unwrap_err();
+    assert!(err.to_string().contains("jbrd"));
+
+    let output = temp_dir.path().join("out.jpg");
+    assert!(
+        jxl::reconstruct_jpeg_file(&jxl_path, &output)
+            .await
+            .is_err()
+    );
+    assert!(!output.exists());
+    Ok(())
+}
+
+#[cfg(feature = "jxl-jpeg-transcode")]
+#[tokio::test]
+async fn test_transcode_jpeg_round_trip() -> anyhow::Result<()> {
+    use image::ImageEncoder;
+    use image::codecs::jpeg::JpegEncoder;
+
+    let temp_dir = TempDir::new()?;
+    #[allow(clippy::cast_possible_truncation)]
+    let img = image::RgbImage::from_fn(96, 64, |x, y| {
+        image::Rgb([(x * 2) as u8, (y * 3) as u8, ((x ^ y) * 4) as u8])
+    });
+
+    for (name, quality) in [("q90", 90), ("q50", 50)] {
+        let mut jpeg = Vec::new();
+        JpegEncoder::new_with_quality(&mut jpeg, quality).write_image(
+            img.as_raw(),
+            img.width(),
+            img.height(),
+            image::ExtendedColorType::Rgb8,
+        )?;
+
+        let jxl_data = jxl::transcode_jpeg_to_jxl(&jpeg)?;
+        assert!(jxl::has_jpeg_reconstruction_data(&jxl_data), "{name}");
+        assert_eq!(jxl::reconstruct_jpeg(&jxl_data)?, jpeg, "{name}");
+
+        // File based helpers produce the same bytes
+        let jpeg_path = temp_dir.path().join(format!("{name}.jpg"));
+        let jxl_path = temp_dir.path().join(format!("{name}.jxl"));
+        let restored_path = temp_dir.path().join(format!("{name}_restored.jpg"));
+        fs::write(&jpeg_path, &jpeg)?;
+        jxl::transcode_jpeg_file_to_jxl(&jpeg_path, &jxl_path).await?;
+        jxl::reconstruct_jpeg_file(&jxl_path, &restored_path).await?;
+        assert_eq!(fs::read(&restored_path)?, jpeg, "{name}");
+    }
+
+    assert!(jxl::transcode_jpeg_to_jxl(b"not a jpeg").is_err());
+    Ok(())
+}
+
+/// Encode an image as lossless JXL bytes through `convert_image`
+async fn encode_lossless_jxl(
+    temp_dir: &TempDir,
+    name: &str,
+    img: &image::DynamicImage,
+) -> anyhow::Result<Vec<u8>> {
+    let png = temp_dir.path().join(format!("{name}.png"));
+    let jxl_path = temp_dir.path().join(format!("{name}.jxl"));
+    img.save(&png)?;
+    let options = crate::formats::ImageFormatOptions::jxl();
+    crate::formats::convert_image(&png, &jxl_path, Some(options)).await?;
+    Ok(fs::read(&jxl_path)?)
+}
+
+#[tokio::test]
+async fn test_decode_jxl_keeps_layout_and_depth() -> anyhow::Result<()> {
+    use image::{DynamicImage, ImageBuffer};
+
+    let temp_dir = TempDir::new()?;
+    #[allow(clippy::cast_possible_truncation)]
+    let rgba16 = ImageBuffer::from_fn(33, 17, |x, y| {
+        image::Rgba([
+            (x * 1987) as u16,
+            (y * 3851) as u16,
+            ((x * y) * 97) as u16,
+            // The lossless encoder keeps alpha at 8 bits
+            ((255 - x * 7) * 257) as u16,
+        ])
+    });
+    let rgba16 = DynamicImage::ImageRgba16(rgba16);
+
+    let cases = [
+        ("gray8", DynamicImage::ImageLuma8(rgba16.to_luma8())),
+        ("graya8", DynamicImage::ImageLumaA8(rgba16.to_luma_alpha8())),
+        ("rgb8", DynamicImage::ImageRgb8(rgba16.to_rgb8())),
+        ("gray16", DynamicImage::ImageLuma16(rgba16.to_luma16())),
+        ("rgb16", DynamicImage::ImageRgb16(rgba16.to_rgb16())),
+        ("rgba16", rgba16.clone()),
+    ];
+    for (name, img) in cases {
+        let data = encode_lossless_jxl(&temp_dir, name, &img).await?;
+        let decoded = jxl::decode_jxl(&data)?;
+        assert_eq!(decoded.color(), img.color(), "{name}");
+        // Rounding (not truncation) makes lossless round trips exact
+        assert_eq!(decoded.as_bytes(), img.as_bytes(), "{name}");
+    }
+
+    assert!(jxl::decode_jxl(b"not a jxl file").is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_convert_jxl_to_png_bit_depth() -> anyhow::Result<()> {
+    use image::{DynamicImage, ImageBuffer};
+
+    let temp_dir = TempDir::new()?;
+    #[allow(clippy::cast_possible_truncation)]
+    let gray16 = ImageBuffer::from_fn(20, 10, |x, y| image::Luma([(x * 3001 + y * 7) as u16]));
+    let gray16 = DynamicImage::ImageLuma16(gray16);
+    let data = encode_lossless_jxl(&temp_dir, "gray16", &gray16).await?;
+    let jxl_path = temp_dir.path().join("input.jxl");
+    fs::write(&jxl_path, data)?;
+
+    // The default conversion writes 8-bit RGBA, even for grayscale sources
+    let png8 = temp_dir.path().join("out8.png");
+    jxl::convert_jxl_to_png(&jxl_path, &png8).await?;
+    let decoded8 = image::open(&png8)?;
+    assert_eq!(decoded8.color(), image::ColorType::Rgba8);
+    assert_eq!(decoded8.to_luma8(), gray16.to_luma8());
+
+    // 16-bit output keeps every sample
+    let png16 = temp_dir.path().join("out16.png");
+    jxl::convert_jxl_to_png_with_depth(&jxl_path, &png16, jxl::PngBitDepth::Sixteen).await?;
+    let decoded16 = image::open(&png16)?;
+    assert_eq!(decoded16.color(), image::ColorType::Rgba16);
+    assert_eq!(decoded16.to_luma16(), gray16.to_luma16());
+    Ok(())
+}
+
+type NoProcessor = fn(PathBuf) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>>;
+
+/// Writes a small valid JXL file into its own directory and returns its path.
+async fn write_sample_jxl(temp_dir: &TempDir) -> anyhow::Result<PathBuf> {
+    #[allow(clippy::cast_possible_truncation)]
+    let img = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(12, 7, |x, y| {
+        image::Rgb([x as u8 * 20, y as u8 * 30, 128])
+    }));
+    let data = encode_lossless_jxl(temp_dir, "sample", &img).await?;
+    let dir = temp_dir.path().join("work");
+    fs::create_dir_all(&dir)?;
+    let path = dir.join("photo.jxl");
+    fs::write(&path, data)?;
+    Ok(path)
+}
+
+#[tokio::test]
+async fn test_process_jxl_file_with_options_keeps_original_on_failure() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let jxl_file = temp_dir.path().join("broken.jxl");
+    fs::write(&jxl_file, b"dummy jxl data")?;
+
+    let options =
+        jxl::JxlProcessOptions::default().with_original_policy(jxl::OriginalFilePolicy::Delete);
+    let report = jxl::process_jxl_file_with_options(
+        &jxl_file,
+        &options,
+        Some(|_: PathBuf| async { panic!("processor must not run") }),
+    )
+    .await?;
+
+    assert!(matches!(
+        report.status,
+        jxl::JxlProcessStatus::ConversionFailed(_)
+    ));
+    assert!(!report.is_success());
+    assert!(!report.processor_ran);
+    assert_eq!(report.png_path, None);
+    assert_eq!(report.original, jxl::OriginalFileOutcome::Kept);
+    assert!(jxl_file.exists());
+    // The unusable PNG is cleaned up
+    assert!(!temp_dir.path().join("broken.png").exists());
+
+    let not_jxl = temp_dir.path().join("image.png");
+    let result =
+        jxl::process_jxl_file_with_options::<NoProcessor, _>(&not_jxl, &options, None).await;
+    assert!(result.unwrap_err().to_string().contains("Not a JXL file"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_process_jxl_file_with_options_collisions() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let jxl_file = write_sample_jxl(&temp_dir).await?;
+    let existing_png = jxl_file.with_extension("png");
+    fs::write(&existing_png, b"keep me")?;
+
+    // The default policy refuses to touch the existing PNG
+    let options =
+        jxl::JxlProcessOptions::default().with_original_policy(jxl::OriginalFilePolicy::Delete);
+    let report =
+        jxl::process_jxl_file_with_options::<NoProcessor, _>(&jxl_file, &options, None).await?;
+    assert_eq!(
+        report.status,
+        jxl::JxlProcessStatus::OutputExists(existing_png.clone())
+    );
+    assert_eq!(report.original, jxl::OriginalFileOutcome::Kept);
+    assert_eq!(fs::read(&existing_png)?, b"keep me");
+    assert!(jxl_file.exists());
+
+    // Suffix writes next to it, then deletes the verified original
+    let options = options.with_collision_policy(crate::extensions::CollisionPolicy::Suffix);
+    let report =
+        jxl::process_jxl_file_with_options::<NoProcessor, _>(&jxl_file, &options, None).await?;
+    let renamed_png = jxl_file.with_file_name("photo_1.png");
+    assert!(report.is_success(), "{:?}", report.status);
+    assert!(report.renamed);
+    assert_eq!(report.png_path, Some(renamed_png.clone()));
+    assert_eq!(report.original, jxl::OriginalFileOutcome::Deleted);
+    assert_eq!(fs::read(&existing_png)?, b"keep me");
+    assert_eq!(image::image_dimensions(&renamed_png)?, (12, 7));
+    assert!(!jxl_file.exists());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_process_jxl_file_with_options_trash_and_processor() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let jxl_file = write_sample_jxl(&temp_dir).await?;
+    let trash = temp_dir.path().join("trash");
+    fs::create_dir_all(&trash)?;
+    fs::write(trash.join("photo.jxl"), b"older file")?;
+    let options = jxl::JxlProcessOptions::default()
+        .with_original_policy(jxl::OriginalFilePolicy::MoveToTrash(trash.clone()))
+        .with_bit_depth(jxl::PngBitDepth::Sixteen);
+
+    // A failing processor keeps the original and the PNG
+    let report = jxl::process_jxl_file_with_options(
+        &jxl_file,
+        &options,
+        Some(|_: PathBuf| async { anyhow::bail!("processor failed") }),
+    )
+    .await?;
+    assert_eq!(
+        report.status,
+        jxl::JxlProcessStatus::ProcessorFailed("processor failed".to_string())
+    );
+    assert!(report.processor_ran);
+    assert_eq!(report.original, jxl::OriginalFileOutcome::Kept);
+    assert!(jxl_file.exists());
+    let png_path = jxl_file.with_extension("png");
+    assert_eq!(report.png_path, Some(png_path.clone()));
+    fs::remove_file(&png_path)?;
+
+    // On success the original moves to the trash without replacing the older file
+    let report = jxl::process_jxl_file_with_options(
+        &jxl_file,
+        &options,
+        Some(|path: PathBuf| async move {
+            assert_eq!(image::open(&path)?.color(), image::ColorType::Rgba16);
+            Ok(())
+        }),
+    )
+    .await?;
+    assert!(report.is_success(), "{:?}", report.status);
+    let trashed = trash.join("photo_1.jxl");
+    assert_eq!(
+        report.original,
+        jxl::OriginalFileOutcome::MovedTo(trashed.clone())
+    );
+    assert!(!jxl_file.exists());
+    assert!(jxl::is_jxl_file_verified(&trashed));
+    assert_eq!(fs::read(trash.join("photo.jxl"))?, b"older file");
+    Ok(())
+}
+
+/// An 8x6 RGB image with a Display P3 ICC profile, an `Exif` box (orientation
+/// 6 and a `DateTime` of 2024:05:06 07:08:09) and an XMP packet.
+const METADATA_JXL: &[u8] = include_bytes!("../../assets/metadata.jxl");
+
+/// Returns the data of the first PNG chunk of the given type.
+fn png_chunk(png_data: &[u8], chunk_type: [u8; 4]) -> Option<&[u8]> {
+    let mut offset = 8;
+    while offset + 8 <= png_data.len() {
+        let length = u32::from_be_bytes(png_data[offset..offset + 4].try_into().ok()?) as usize;
+        let data = png_data.get(offset + 8..offset + 8 + length)?;
+        if png_data[offset + 4..offset + 8] == chunk_type {
+            return Some(data);
+        }
+        // Length, type, data and CRC
+        offset += 12 + length;
+    }
+    None
+}
+
+#[test]
+fn test_read_jxl_metadata() -> anyhow::Result<()> {
+    let metadata = jxl::read_jxl_metadata(METADATA_JXL)?;
+    let info = &metadata.info;
+    assert_eq!((info.width, info.height), (8, 6));
+    assert_eq!(info.bits_per_sample, 8);
+    assert!(!info.float_samples && !info.grayscale && !info.has_alpha && !info.animated);
+    assert_eq!(info.orientation, 1);
+    assert!(info.color_encoding.has_icc_profile);
+    assert!(!info.color_encoding.is_srgb);
+    assert_eq!(info.color_encoding.description, "RGB, ICC profile");
+
+    let exif = metadata.exif.as_deref().expect("missing EXIF");
+    assert!(exif.starts_with(b"II*\0"));
+    assert!(exif.windows(19).any(|w| w == b"2024:05:06 07:08:09"));
+    let xmp = String::from_utf8(metadata.xmp.expect("missing XMP"))?;
+    assert!(xmp.contains("xmp:CreateDate=\"2024-05-06T07:08:09\""));
+    let icc = metadata
+        .icc_profile
+        .as_deref()
+        .expect("missing ICC profile");
+    assert_eq!(&icc[36..40], b"acsp");
+
+    // A plain sRGB file with alpha, animation and no metadata boxes
+    let animated = include_bytes!("../../assets/animated.jxl");
+    let metadata = jxl::read_jxl_metadata(animated)?;
+    assert!(metadata.info.has_alpha && metadata.info.animated);
+    let encoding = &metadata.info.color_encoding;
+    assert!(encoding.is_srgb && !encoding.has_icc_profile);
+    assert_eq!(encoding.cicp, Some([1, 13, 0, 1]));
+    assert_eq!(
+        encoding.description,
+        "RGB, D65, sRGB primaries, sRGB transfer"
+    );
+    assert_eq!(metadata.exif, None);
+    assert_eq!(metadata.xmp, None);
+    assert_eq!(metadata.icc_profile, None);
+
+    assert!(jxl::read_jxl_metadata(b"not a jxl file").is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_convert_jxl_to_png_keeps_metadata() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let input = temp_dir.path().join("photo.jxl");
+    fs::write(&input, METADATA_JXL)?;
+    let metadata = jxl::read_jxl_metadata(METADATA_JXL)?;
+
+    for bit_depth in [jxl::PngBitDepth::Eight, jxl::PngBitDepth::Sixteen] {
+        let output = temp_dir.path().join(format!("photo_{bit_depth:?}.png"));
+        jxl::convert_jxl_to_png_with_depth(&input, &output, bit_depth).await?;
+
+        let reader = png::Decoder::new(fs::File::open(&output)?).read_info()?;
+        let info = reader.info();
+        assert_eq!(info.icc_profile.as_deref(), metadata.icc_profile.as_deref());
+        // The png decoder does not parse eXIf, so the chunk is read directly
+        let png_data = fs::read(&output)?;
+        assert_eq!(png_chunk(&png_data, *b"eXIf"), metadata.exif.as_deref());
+        let xmp = info
+            .utf8_text
+            .iter()
+            .find(|chunk| chunk.keyword == "XML:com.adobe.xmp")
+            .expect("missing XMP chunk")
+            .get_text()?;
+        assert_eq!(Some(xmp.into_bytes()), metadata.xmp);
+    }
+
+    // sRGB images need no ICC profile
+    let srgb_input = temp_dir.path().join("animated.jxl");
+    fs::write(&srgb_input, include_bytes!("../../assets/animated.jxl"))?;
+    let srgb_output = temp_dir.path().join("animated.png");
+    jxl::convert_jxl_to_png(&srgb_input, &srgb_output).await?;
+    let reader = png::Decoder::new(fs::File::open(&srgb_output)?).read_info()?;
+    assert!(reader.info().icc_profile.is_none());
+    assert_eq!(png_chunk(&fs::read(&srgb_output)?, *b"eXIf"), None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_convert_jxl_to_png_ignores_bad_exif() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let source = temp_dir.path().join("source.png");
+    let encoded = temp_dir.path().join("encoded.jxl");
+    image::RgbImage::from_pixel(4, 4, image::Rgb([10, 20, 30])).save(&source)?;
+    crate::convert_image(&source, &encoded, None).await?;
+    let codestream = fs::read(&encoded)?;
+    assert!(codestream.starts_with(&[0xFF, 0x0A]));
+
+    // An Exif box whose TIFF header offset points past its payload
+    let mut container = jxl::JXL_CONTAINER_SIGNATURE.to_vec();
+    container.extend(jxl_box(*b"ftyp", b"jxl \0\0\0\0jxl "));
+    container.extend(jxl_box(*b"Exif", &[0, 0, 0, 99, b'I', b'I']));
+    container.extend(jxl_box(*b"jxlc", &codestream));
+    assert!(jxl::read_jxl_metadata(&container).is_err());
+
+    let input = temp_dir.path().join("bad_exif.jxl");
+    fs::write(&input, &container)?;
+    let output = temp_dir.path().join("bad_exif.png");
+    jxl::convert_jxl_to_png(&input, &output).await?;
+    assert_eq!(png_chunk(&fs::read(&output)?, *b"eXIf"), None);
+    let decoded = image::open(&output)?.to_rgba8();
+    assert_eq!(decoded.get_pixel(1, 1), &image::Rgba([10, 20, 30, 255]));
+    Ok(())
+}
@@ -0,0 +1,263 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use super::color_tests::{P3_ORANGE, SRGB_ORANGE, assert_close, display_p3_profile};
+use super::image_processing_tests::assert_upright;
+use crate::formats::{ImageFormatOptions, convert_image};
+use crate::image_processing::{open_image, open_image_with_icc_profile};
+use crate::jxl::read_jxl_metadata;
+use crate::metadata::{ImageMetadata, MetadataKind, MetadataPolicy, read_container_metadata};
+use image::{
+    DynamicImage, ImageDecoder, ImageEncoder, ImageReader, Rgb, RgbImage,
+    codecs::{jpeg::JpegEncoder, png::PngEncoder},
+    metadata::Orientation,
+};
+use std::path::Path;
+use tempfile::TempDir;
+
+/// Little-endian EXIF with Orientation 6 (rotate 90 degrees) and Make "Cam".
+const EXIF: &[u8] = b"II*\0\x08\0\0\0\x02\0\
+    \x0f\x01\x02\0\x04\0\0\0Cam\0\
+    \x12\x01\x03\0\x01\0\0\0\x06\0\0\0\
+    \0\0\0\0";
+
+/// The EXIF block a kept orientation is written as, without the camera make.
+const ORIENTATION_EXIF: &[u8] = b"II*\0\x08\0\0\0\x01\0\x12\x01\x03\0\x01\0\0\0\x06\0\0\0\0\0\0\0";
+
+const XMP: &[u8] = br#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF/></x:xmpmeta>"#;
+
+/// Writes a 32x16 JPEG (red left half, blue right half) with a Display P3 profile,
+/// [`EXIF`] and [`XMP`].
+fn write_tagged_jpeg(path: &Path) -> anyhow::Result<()> {
+    let img = RgbImage::from_fn(32, 16, |x, _| {
+        if x < 16 {
+            Rgb([255, 0, 0])
+        } else {
+            Rgb([0, 0, 255])
+        }
+    });
+    let mut jpeg = Vec::new();
+    let mut encoder = JpegEncoder::new_with_quality(&mut jpeg, 95);
+    encoder.set_icc_profile(display_p3_profile())?;
+    encoder.encode_image(&img)?;
+
+    let mut segments = Vec::new();
+    for (prefix, payload) in [
+        (&b"Exif\0\0"[..], EXIF),
+        (&b"http://ns.adobe.com/xap/1.0/\0"[..], XMP),
+    ] {
+        segments.extend_from_slice(&[0xFF, 0xE1]);
+        segments.extend_from_slice(&u16::try_from(2 + prefix.len() + payload.len())?.to_be_bytes());
+        segments.extend_from_slice(prefix);
+        segments.extend_from_slice(payload);
+    }
+    jpeg.splice(2..2, segments);
+    std::fs::write(path, jpeg)?;
+    Ok(())
+}
+
+/// Reads the metadata of a converted file, going through `jxl-oxide` for JPEG XL.
+fn read_metadata(path: &Path) -> anyhow::Result<ImageMetadata> {
+    let data = std::fs::read(path)?;
+    if path.extension().is_some_and(|ext| ext == "jxl") {
+        let metadata = read_jxl_metadata(&data)?;
+        return Ok(ImageMetadata {
+            exif: metadata.exif,
+            xmp: metadata.xmp,
+            ..ImageMetadata::default()
+        });
+    }
+    let mut metadata = read_container_metadata(&data);
+    metadata.icc_profile = open_image_with_icc_profile(path)?.1;
+    Ok(metadata)
+}
+
+#[test]
+fn test_metadata_policy_keeps() {
+    let default = MetadataPolicy::default();
+    assert!(default.keeps(MetadataKind::IccProfile));
+    assert!(!default.keeps(MetadataKind::Exif));
+    assert!(!default.keeps(MetadataKind::Orientation));
+
+    // The orientation is part of the EXIF block
+    let exif = MetadataPolicy::KeepOnly(vec![MetadataKind::Exif]);
+    assert!(exif.keeps(MetadataKind::Orientation));
+    let orientation = MetadataPolicy::KeepOnly(vec![MetadataKind::Orientation]);
+    assert!(!orientation.keeps(MetadataKind::Exif));
+
+    assert!(MetadataPolicy::KeepAll.keeps(MetadataKind::Text));
+    assert!(!MetadataPolicy::StripAll.keeps(MetadataKind::IccProfile));
+}
+
+#[tokio::test]
+async fn test_convert_image_keep_all_metadata() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let input = temp_dir.path().join("tagged.jpg");
+    write_tagged_jpeg(&input)?;
+    let profile = display_p3_profile();
+
+    for name in ["out.jpg", "out.png", "out.webp"] {
+        let output = temp_dir.path().join(name);
+        let options = ImageFormatOptions::default().with_metadata(MetadataPolicy::KeepAll);
+        convert_image(&input, &output, Some(options)).await?;
+
+        let metadata = read_metadata(&output)?;
+        assert_eq!(metadata.exif.as_deref(), Some(EXIF), "{name}");
+        assert_eq!(metadata.xmp.as_deref(), Some(XMP), "{name}");
+        assert_eq!(
+            metadata.icc_profile.as_deref(),
+            Some(profile.as_slice()),
+            "{name}"
+        );
+        // The output still decodes, and the image crate sees the orientation
+        let mut decoder = ImageReader::open(&output)?
+            .with_guessed_format()?
+            .into_decoder()?;
+        if name != "out.png" {
+            assert_eq!(decoder.orientation()?, Orientation::Rotate90, "{name}");
+        }
+        DynamicImage::from_decoder(decoder)?;
+    }
+
+    // JPEG XL readers ignore the EXIF orientation, so it is applied to the pixels
+    let output = temp_dir.path().join("out.jxl");
+    let options = ImageFormatOptions::jxl().with_metadata(MetadataPolicy::KeepAll);
+    convert_image(&input, &output, Some(options)).await?;
+    let metadata = read_metadata(&output)?;
+    assert_eq!(metadata.xmp.as_deref(), Some(XMP));
+    assert_eq!(metadata.orientation(), Some(1));
+    assert_upright(&open_image(&output)?.to_rgb8());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_convert_image_strips_metadata() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let input = temp_dir.path().join("tagged.jpg");
+    write_tagged_jpeg(&input)?;
+
+    for (name, options) in [
+        ("out.jpg", ImageFormatOptions::jpeg()),
+        ("out.png", ImageFormatOptions::png()),
+        ("out.webp", ImageFormatOptions::webp()),
+        ("out.jxl", ImageFormatOptions::jxl()),
+    ] {
+        let output = temp_dir.path().join(name);
+        let options = options.with_metadata(MetadataPolicy::StripAll);
+        convert_image(&input, &output, Some(options)).await?;
+        assert_eq!(read_metadata(&output)?, ImageMetadata::default(), "{name}");
+    }
+
+    // The default keeps only the ICC profile
+    let output = temp_dir.path().join("default.webp");
+    convert_image(&input, &output, None).await?;
+    let metadata = read_metadata(&output)?;
+    assert!(metadata.exif.is_none() && metadata.xmp.is_none());
+    assert_eq!(metadata.icc_profile, Some(display_p3_profile()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_convert_image_metadata_whitelist() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let input = temp_dir.path().join("tagged.jpg");
+    write_tagged_jpeg(&input)?;
+    let policy =
+        MetadataPolicy::KeepOnly(vec![MetadataKind::IccProfile, MetadataKind::Orientation]);
+
+    for name in ["out.jpg", "out.png", "out.webp"] {
+        let output = temp_dir.path().join(name);
+        let options = ImageFormatOptions::default().with_metadata(policy.clone());
+        convert_image(&input, &output, Some(options)).await?;
+
+        // Only the orientation is left of the EXIF block
+        let metadata = read_metadata(&output)?;
+        assert_eq!(metadata.exif.as_deref(), Some(ORIENTATION_EXIF), "{name}");
+        assert!(metadata.xmp.is_none(), "{name}");
+        assert_eq!(metadata.icc_profile, Some(display_p3_profile()), "{name}");
+    }
+
+    // Auto-orienting leaves nothing to record
+    let output = temp_dir.path().join("oriented.jpg");
+    let options = ImageFormatOptions::jpeg()
+        .with_metadata(policy)
+        .with_auto_orient(true);
+    convert_image(&input, &output, Some(options)).await?;
+    assert!(read_metadata(&output)?.exif.is_none());
+    assert_upright(&open_image(&output)?.to_rgb8());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stripped_icc_profile_converts_to_srgb() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let input = temp_dir.path().join("p3.png");
+    let img = RgbImage::from_pixel(8, 8, Rgb(P3_ORANGE));
+    let mut encoder = PngEncoder::new(std::fs::File::create(&input)?);
+    encoder.set_icc_profile(display_p3_profile())?;
+    encoder.write_image(img.as_raw(), 8, 8, image::ExtendedColorType::Rgb8)?;
+
+    let output = temp_dir.path().join("out.png");
+    let options = ImageFormatOptions::png().with_metadata(MetadataPolicy::StripAll);
+    convert_image(&input, &output, Some(options)).await?;
+    let (decoded, icc_profile) = open_image_with_icc_profile(&output)?;
+    assert!(icc_profile.is_none());
+    assert_close(&decoded.to_rgb8().get_pixel(4, 4).0, &SRGB_ORANGE, 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_png_text_metadata() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let input = temp_dir.path().join("generated.png");
+    let mut info = png::Info::with_size(4, 4);
+    info.color_type = png::ColorType::Rgb;
+    info.uncompressed_latin1_text
+        .push(png::text_metadata::TEXtChunk::new(
+            "parameters",
+            "steps: 20",
+        ));
+    let mut writer =
+        png::Encoder::with_info(std::fs::File::create(&input)?, info)?.write_header()?;
+    writer.write_image_data(&[128; 4 * 4 * 3])?;
+    writer.finish()?;
+
+    let read_text = |path: &Path| -> anyhow::Result<Vec<(String, String)>> {
+        let reader = png::Decoder::new(std::fs::File::open(path)?).read_info()?;
+        Ok(reader
+            .info()
+            .uncompressed_latin1_text
+            .iter()
+            .map(|chunk| (chunk.keyword.clone(), chunk.text.clone()))
+            .collect())
+    };
+
+    let kept = temp_dir.path().join("kept.png");
+    let options = ImageFormatOptions::png().with_metadata(MetadataPolicy::KeepAll);
+    convert_image(&input, &kept, Some(options)).await?;
+    assert_eq!(
+        read_text(&kept)?,
+        [("parameters".to_string(), "steps: 20".to_string())]
+    );
+
+    let stripped = temp_dir.path().join("stripped.png");
+    convert_image(&input, &stripped, None).await?;
+    assert!(read_text(&stripped)?.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_jxl_metadata_to_png() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let input = temp_dir.path().join("metadata.jxl");
+    std::fs::write(&input, include_bytes!("../../assets/metadata.jxl"))?;
+    let source = read_jxl_metadata(&std::fs::read(&input)?)?;
+
+    let output = temp_dir.path().join("out.png");
+    let options = ImageFormatOptions::png().with_metadata(MetadataPolicy::KeepAll);
+    convert_image(&input, &output, Some(options)).await?;
+    let metadata = read_metadata(&output)?;
+    assert!(source.exif.is_some() && source.xmp.is_some());
+    assert_eq!(metadata.xmp, source.xmp);
+    assert_eq!(metadata.exif.is_some(), source.exif.is_some());
+    Ok(())
+}
@@ -0,0 +1,105 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use crate::formats::{ImageFormatOptions, convert_image, convert_image_with_metrics};
+use crate::metrics::{compare_image_files, compare_images};
+use image::{DynamicImage, Rgb, RgbImage, Rgba, RgbaImage};
+use tempfile::TempDir;
+
+/// A 96x96 gradient with some texture, so lossy encoders have detail to lose.
+fn textured_image() -> RgbImage {
+    RgbImage::from_fn(96, 96, |x, y| {
+        let texture = ((x * 7 + y * 13) % 17) as u8 * 4;
+        #[allow(clippy::cast_possible_truncation)]
+        Rgb([
+            (x * 2) as u8 + texture,
+            (y * 2) as u8,
+            ((x + y) as u8) / 2 + texture,
+        ])
+    })
+}
+
+#[test]
+fn test_identical_images() -> anyhow::Result<()> {
+    let img = DynamicImage::ImageRgb8(textured_image());
+    let metrics = compare_images(&img, &img)?;
+    assert!(metrics.psnr.is_infinite());
+    assert!((metrics.ssim - 1.0).abs() < 1e-6, "{}", metrics.ssim);
+    assert!((metrics.ms_ssim - 1.0).abs() < 1e-6, "{}", metrics.ms_ssim);
+    assert_eq!(metrics.max_error, 0);
+    assert_eq!(metrics.error_map.dimensions(), (96, 96));
+    assert!(metrics.error_map.pixels().all(|p| p[0] == 0));
+    Ok(())
+}
+
+#[test]
+fn test_known_error() -> anyhow::Result<()> {
+    let reference = RgbImage::from_pixel(32, 32, Rgb([100, 100, 100]));
+    let mut distorted = reference.clone();
+    distorted.put_pixel(3, 4, Rgb([100, 110, 100]));
+    distorted.put_pixel(20, 9, Rgb([94, 100, 100]));
+
+    let metrics = compare_images(
+        &DynamicImage::ImageRgb8(reference),
+        &DynamicImage::ImageRgb8(distorted),
+    )?;
+    // Two errors of 10 and 6 levels over 32 * 32 * 3 samples
+    let mse = (100.0 + 36.0) / (255.0 * 255.0) / (32.0 * 32.0 * 3.0);
+    assert!((metrics.psnr - -10.0 * f64::log10(mse)).abs() < 1e-3);
+    assert_eq!(metrics.max_error, 10);
+    assert_eq!(metrics.error_map.get_pixel(3, 4)[0], 10);
+    assert_eq!(metrics.error_map.get_pixel(20, 9)[0], 6);
+    assert_eq!(metrics.error_map.get_pixel(0, 0)[0], 0);
+    assert!(metrics.ssim < 1.0 && metrics.ssim > 0.9);
+    Ok(())
+}
+
+#[test]
+fn test_transparent_pixels_and_sizes() -> anyhow::Result<()> {
+    // Colours under fully transparent pixels do not count
+    let reference = RgbaImage::from_pixel(16, 16, Rgba([255, 0, 0, 0]));
+    let distorted = RgbaImage::from_pixel(16, 16, Rgba([0, 0, 255, 0]));
+    let metrics = compare_images(
+        &DynamicImage::ImageRgba8(reference),
+        &DynamicImage::ImageRgba8(distorted),
+    )?;
+    assert!(metrics.psnr.is_infinite());
+
+    let small = DynamicImage::new_rgb8(8, 8);
+    let error = compare_images(&small, &DynamicImage::new_rgb8(8, 9)).unwrap_err();
+    assert!(error.to_string().contains("8x9"), "{error}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_convert_image_with_metrics() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let input = temp_dir.path().join("source.png");
+    textured_image().save(&input)?;
+
+    let high_path = temp_dir.path().join("high.jpg");
+    let low_path = temp_dir.path().join("low.jpg");
+    let high = convert_image_with_metrics(
+        &input,
+        &high_path,
+        Some(ImageFormatOptions::jpeg().with_quality(95)),
+    )
+    .await?;
+    let low = convert_image_with_metrics(
+        &input,
+        &low_path,
+        Some(ImageFormatOptions::jpeg().with_quality(20)),
+    )
+    .await?;
+    assert!(high.psnr > low.psnr, "{} <= {}", high.psnr, low.psnr);
+    assert!(high.ssim > low.ssim && low.ssim < 1.0);
+    assert!(high.ms_ssim > low.ms_ssim && low.ms_ssim > 0.0);
+    assert!(low.max_error > 0);
+
+    // Lossless JPEG XL reads back identical, and files compare the same way
+    let jxl = temp_dir.path().join("lossless.jxl");
+    convert_image(&input, &jxl, Some(ImageFormatOptions::jxl())).await?;
+    let metrics = compare_image_files(&input, &jxl)?;
+    assert!(metrics.psnr.is_infinite());
+    assert_eq!(metrics.max_error, 0);
+    Ok(())
+}
@@ -91,6 +91,29 @@ fn test_f32_to_u8() {
     assert_eq!(numeric::f32_to_u8(0.6), 1);
 }
 
+#[test]
+fn test_f32_to_u16() {
+    // Normal cases
+    assert_eq!(numeric::f32_to_u16(0.0), 0);
+    assert_eq!(numeric::f32_to_u16(1.0), 1);
+    assert_eq!(numeric::f32_to_u16(65535.0), 65535);
+    assert_eq!(numeric::f32_to_u16(32767.4), 32767);
+    assert_eq!(numeric::f32_to_u16(32767.6), 32768);
+
+    // Edge cases
+    assert_eq!(numeric::f32_to_u16(f32::NAN), 0);
+    assert_eq!(numeric::f32_to_u16(f32::INFINITY), 65535);
+    assert_eq!(numeric::f32_to_u16(f32::NEG_INFINITY), 0);
+    assert_eq!(numeric::f32_to_u16(-1.0), 0);
+    assert_eq!(numeric::f32_to_u16(65536.0), 65535);
+
+    // Values just inside bounds
+    assert_eq!(numeric::f32_to_u16(65534.4), 65534);
+    assert_eq!(numeric::f32_to_u16(65534.6), 65535);
+    assert_eq!(numeric::f32_to_u16(0.4), 0);
+    assert_eq!(numeric::f32_to_u16(0.6), 1);
+}
+
 #[test]
 #[allow(
     clippy::float_cmp,
@@ -147,6 +170,7 @@ fn test_f32_to_u32() {
 fn test_f32_to_u32_max_precision_limitation() {
     // When converting u32::MAX to f32 and back, we expect to get u32::MAX
     // This is because we handle the imprecise representation gracefully
+    #[allow(clippy::cast_precision_loss)]
     let max_as_f32 = u32::MAX as f32;
     assert_eq!(numeric::f32_to_u32(max_as_f32), u32::MAX);
 
@@ -155,6 +179,7 @@ fn test_f32_to_u32_max_precision_limitation() {
     debug!("This is slightly less than u32::MAX due to f32's precision limitations");
 
     // Also test u32::MAX - 1 to verify it's handled correctly
+    #[allow(clippy::cast_precision_loss)]
     let max_minus_one_f32 = (u32::MAX - 1) as f32;
     let result = numeric::f32_to_u32(max_minus_one_f32);
     debug!("Note: (u32::MAX - 1) as f32 = {max_minus_one_f32}");
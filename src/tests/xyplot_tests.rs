@@ -13,7 +13,9 @@ fn create_test_image(path: &std::path::Path, width: u32, height: u32) -> Result<
     let mut img = RgbImage::new(width, height);
     // Fill with a test pattern
     for (x, y, pixel) in img.enumerate_pixels_mut() {
-        *pixel = Rgb([((x * 255) / width) as u8, ((y * 255) / height) as u8, 128u8]);
+        #[allow(clippy::cast_possible_truncation)]
+        let gradient = [((x * 255) / width) as u8, ((y * 255) / height) as u8, 128u8];
+        *pixel = Rgb(gradient);
     }
     img.save(path)?;
     Ok(())
@@ -41,6 +43,7 @@ fn test_basic_plot() -> Result<()> {
         top_padding: DEFAULT_TOP_PADDING,
         left_padding: DEFAULT_LEFT_PADDING,
         font_size: None,
+        auto_orient: false,
     };
 
     create_plot(&config)?;
@@ -70,6 +73,7 @@ fn test_with_labels() -> Result<()> {
         top_padding: DEFAULT_TOP_PADDING,
         left_padding: DEFAULT_LEFT_PADDING,
         font_size: None,
+        auto_orient: false,
     };
 
     create_plot(&config)?;
@@ -99,6 +103,7 @@ fn test_with_row_and_column_labels() -> Result<()> {
         top_padding: DEFAULT_TOP_PADDING,
         left_padding: DEFAULT_LEFT_PADDING,
         font_size: None,
+        auto_orient: false,
     };
 
     create_plot(&config)?;
@@ -128,6 +133,7 @@ fn test_different_image_sizes() -> Result<()> {
         top_padding: DEFAULT_TOP_PADDING,
         left_padding: DEFAULT_LEFT_PADDING,
         font_size: None,
+        auto_orient: false,
     };
 
     create_plot(&config)?;
@@ -155,6 +161,7 @@ fn test_single_image() -> Result<()> {
         top_padding: DEFAULT_TOP_PADDING,
         left_padding: DEFAULT_LEFT_PADDING,
         font_size: None,
+        auto_orient: false,
     };
 
     create_plot(&config)?;
@@ -195,6 +202,7 @@ fn test_many_images() -> Result<()> {
         top_padding: DEFAULT_TOP_PADDING,
         left_padding: DEFAULT_LEFT_PADDING,
         font_size: None,
+        auto_orient: false,
     };
 
     create_plot(&config)?;
@@ -223,6 +231,7 @@ fn test_mismatched_row_labels() {
         top_padding: DEFAULT_TOP_PADDING,
         left_padding: DEFAULT_LEFT_PADDING,
         font_size: None,
+        auto_orient: false,
     };
 
     create_plot(&config).unwrap();
@@ -249,6 +258,7 @@ fn test_mismatched_column_labels() {
         top_padding: DEFAULT_TOP_PADDING,
         left_padding: DEFAULT_LEFT_PADDING,
         font_size: None,
+        auto_orient: false,
     };
 
     create_plot(&config).unwrap();
@@ -276,6 +286,7 @@ fn test_empty_labels() -> Result<()> {
         top_padding: DEFAULT_TOP_PADDING,
         left_padding: DEFAULT_LEFT_PADDING,
         font_size: None,
+        auto_orient: false,
     };
 
     create_plot(&config)?;
@@ -306,6 +317,7 @@ fn test_dynamic_padding() -> Result<()> {
         top_padding: DEFAULT_TOP_PADDING,
         left_padding: DEFAULT_LEFT_PADDING,
         font_size: None,
+        auto_orient: false,
     };
 
     create_plot(&config)?;
@@ -343,6 +355,7 @@ fn test_different_size_images() -> Result<()> {
         top_padding: DEFAULT_TOP_PADDING,
         left_padding: DEFAULT_LEFT_PADDING,
         font_size: None,
+        auto_orient: false,
     };
 
     create_plot(&config)?;
@@ -381,6 +394,7 @@ fn test_label_alignment() -> Result<()> {
         top_padding: DEFAULT_TOP_PADDING,
         left_padding: DEFAULT_LEFT_PADDING,
         font_size: None,
+        auto_orient: false,
     };
 
     create_plot(&config)?;
@@ -414,6 +428,7 @@ fn test_column_label_alignment_with_different_ar() -> Result<()> {
         top_padding: DEFAULT_TOP_PADDING,
         left_padding: DEFAULT_LEFT_PADDING,
         font_size: None,
+        auto_orient: false,
     };
 
     create_plot(&config)?;
@@ -448,6 +463,7 @@ fn test_column_label_alignment_with_different_ar() -> Result<()> {
     let expected_x: i32 = cell_start + image_offset;
 
     // Search for text in a wider region around the expected position
+    #[allow(clippy::cast_sign_loss)]
     let found_text = (0..100).any(|offset: i32| {
         has_black_pixels(
             expected_x.saturating_add(offset).saturating_sub(50) as u32,
@@ -468,6 +484,7 @@ fn test_column_label_alignment_with_different_ar() -> Result<()> {
     let expected_x: i32 = cell_start + image_offset;
 
     // Search for text in a wider region around the expected position
+    #[allow(clippy::cast_sign_loss)]
     let found_text = (0..100).any(|offset: i32| {
         has_black_pixels(
             expected_x.saturating_add(offset).saturating_sub(50) as u32,
@@ -503,6 +520,7 @@ fn test_column_label_alignment_with_different_ar() -> Result<()> {
         );
 
         // Only check if we're not too close to the image center
+        #[allow(clippy::cast_possible_wrap)]
         if check_start + check_width as i32 <= image_center {
             assert!(
                 !has_black_pixels(check_start.try_into().unwrap(), 0, check_width, 40),
@@ -515,6 +533,7 @@ fn test_column_label_alignment_with_different_ar() -> Result<()> {
 }
 
 #[test]
+#[allow(clippy::too_many_lines)]
 fn test_column_label_alignments() -> Result<()> {
     let temp_dir = tempdir()?;
     let img1_path = temp_dir.path().join("test1.png");
@@ -537,6 +556,7 @@ fn test_column_label_alignments() -> Result<()> {
         top_padding: DEFAULT_TOP_PADDING,
         left_padding: DEFAULT_LEFT_PADDING,
         font_size: None,
+        auto_orient: false,
     };
     create_plot(&config)?;
 
@@ -591,6 +611,7 @@ fn test_column_label_alignments() -> Result<()> {
         top_padding: DEFAULT_TOP_PADDING,
         left_padding: DEFAULT_LEFT_PADDING,
         font_size: None,
+        auto_orient: false,
     };
     create_plot(&config)?;
 
@@ -616,6 +637,7 @@ fn test_column_label_alignments() -> Result<()> {
     // Search in a wider area for the first column
     let mut found_first = false;
     for x_offset in -20_i32..=20_i32 {
+        #[allow(clippy::cast_sign_loss)]
         let search_x = first_col_x.saturating_add(x_offset as u32);
         if has_black_pixels(search_x, 0, 50, DEFAULT_TOP_PADDING) {
             found_first = true;
@@ -628,6 +650,7 @@ fn test_column_label_alignments() -> Result<()> {
     // Search in a wider area for the second column
     let mut found_second = false;
     for x_offset in -20_i32..=20_i32 {
+        #[allow(clippy::cast_sign_loss)]
         let search_x = second_col_x.saturating_add(x_offset as u32);
         if has_black_pixels(search_x, 0, 50, DEFAULT_TOP_PADDING) {
             found_second = true;
@@ -663,6 +686,7 @@ fn test_debug_mode() -> Result<()> {
         top_padding: DEFAULT_TOP_PADDING,
         left_padding: DEFAULT_LEFT_PADDING,
         font_size: None,
+        auto_orient: false,
     };
 
     create_plot(&config)?;
@@ -708,6 +732,7 @@ fn test_column_label_alignments_comprehensive() -> Result<()> {
             top_padding: DEFAULT_TOP_PADDING,
             left_padding: DEFAULT_LEFT_PADDING,
             font_size: None,
+            auto_orient: false,
         };
 
         create_plot(&config)?;
@@ -778,6 +803,7 @@ fn test_top_padding_variations() -> Result<()> {
             top_padding: padding,
             left_padding: DEFAULT_LEFT_PADDING,
             font_size: None,
+            auto_orient: false,
         };
 
         create_plot(&config)?;
@@ -845,6 +871,7 @@ fn test_zero_top_padding() -> Result<()> {
         top_padding: 0,
         left_padding: DEFAULT_LEFT_PADDING,
         font_size: None,
+        auto_orient: false,
     };
 
     create_plot(&config)?;
@@ -884,6 +911,7 @@ fn test_multiline_column_labels() -> Result<()> {
         top_padding: DEFAULT_TOP_PADDING,
         left_padding: DEFAULT_LEFT_PADDING,
         font_size: None,
+        auto_orient: false,
     };
 
     create_plot(&config)?;
@@ -970,6 +998,7 @@ fn test_multiline_row_labels() -> Result<()> {
         top_padding: DEFAULT_TOP_PADDING,
         left_padding: DEFAULT_LEFT_PADDING,
         font_size: None,
+        auto_orient: false,
     };
 
     create_plot(&config)?;
@@ -1035,6 +1064,7 @@ fn test_row_label_alignments() -> Result<()> {
             top_padding: DEFAULT_TOP_PADDING,
             left_padding: DEFAULT_LEFT_PADDING,
             font_size: None,
+            auto_orient: false,
         };
 
         create_plot(&config)?;
@@ -1101,6 +1131,7 @@ fn test_dynamic_padding_with_multiline() -> Result<()> {
         top_padding: DEFAULT_TOP_PADDING,
         left_padding: DEFAULT_LEFT_PADDING,
         font_size: None,
+        auto_orient: false,
     };
 
     create_plot(&config)?;
@@ -1120,3 +1151,91 @@ fn test_dynamic_padding_with_multiline() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_plot_with_jxl_images() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let png_path = temp_dir.path().join("source.png");
+    let jxl_path = temp_dir.path().join("test1.jxl");
+    let img2_path = temp_dir.path().join("test2.png");
+    let output_path = temp_dir.path().join("output.png");
+
+    create_test_image(&png_path, 120, 80)?;
+    create_test_image(&img2_path, 100, 100)?;
+    let options = crate::formats::ImageFormatOptions::jxl();
+    crate::formats::convert_image(&png_path, &jxl_path, Some(options)).await?;
+
+    let config = PlotConfig {
+        images: vec![jxl_path, img2_path],
+        output: output_path.clone(),
+        rows: 1,
+        row_labels: vec![],
+        column_labels: vec!["JXL".to_string(), "PNG".to_string()],
+        column_label_alignment: LabelAlignment::Center,
+        row_label_alignment: LabelAlignment::Center,
+        debug_mode: false,
+        top_padding: DEFAULT_TOP_PADDING,
+        left_padding: DEFAULT_LEFT_PADDING,
+        font_size: None,
+        auto_orient: false,
+    };
+
+    create_plot(&config)?;
+    let output = image::open(&output_path)?;
+    // Both cells are sized from the largest image, including the JXL one
+    assert!(output.width() >= 240);
+    assert!(output.height() >= 100);
+    Ok(())
+}
+
+#[test]
+fn test_plot_auto_orient() -> Result<()> {
+    use super::image_processing_tests::{assert_upright, write_jpeg_with_orientation};
+
+    let temp_dir = tempdir()?;
+    let img_path = temp_dir.path().join("phone.jpg");
+    write_jpeg_with_orientation(&img_path, 6)?;
+
+    let stored_output = temp_dir.path().join("stored.png");
+    let mut config = PlotConfig {
+        images: vec![img_path],
+        output: stored_output.clone(),
+        ..PlotConfig::default()
+    };
+    create_plot(&config)?;
+    assert_eq!(image::open(&stored_output)?.dimensions(), (32, 16));
+
+    let upright_output = temp_dir.path().join("upright.png");
+    config.output = upright_output.clone();
+    config.auto_orient = true;
+    create_plot(&config)?;
+    assert_upright(&image::open(&upright_output)?.to_rgb8());
+    Ok(())
+}
+
+#[test]
+fn test_plot_output_formats() -> Result<()> {
+    use crate::image_processing::{DetectedImageFormat, detect_image_format, open_image};
+
+    let temp_dir = tempdir()?;
+    let img_path = temp_dir.path().join("test.png");
+    create_test_image(&img_path, 32, 32)?;
+
+    // Plots are written like `convert_image` output, so AVIF and JPEG XL work too
+    let mut config = PlotConfig {
+        images: vec![img_path],
+        output: temp_dir.path().join("plot.avif"),
+        ..PlotConfig::default()
+    };
+    create_plot(&config)?;
+    let data = std::fs::read(&config.output)?;
+    assert_eq!(
+        detect_image_format(data[..12].try_into()?),
+        Some(DetectedImageFormat::Avif)
+    );
+
+    config.output = temp_dir.path().join("plot.jxl");
+    create_plot(&config)?;
+    assert_eq!(open_image(&config.output)?.dimensions(), (32, 32));
+    Ok(())
+}
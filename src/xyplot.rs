@@ -35,6 +35,7 @@
 //!         top_padding: 40,
 //!         left_padding: 40,
 //!         font_size: Some(40.0),
+//!         auto_orient: false,
 //!     };
 //!
 //!     create_plot(&config)?;
@@ -44,11 +45,14 @@
 
 #![warn(clippy::all, clippy::pedantic)]
 
+use crate::formats::{default_options_for, detect_output_format, encode_image};
+use crate::image_processing::{get_image_info, open_image, open_image_oriented};
 use crate::layout::{Layout, LayoutElement, LayoutRect};
+use crate::metadata::ImageMetadata;
 use crate::numeric::{f32_to_i32, f32_to_u32, i32_to_u32, u32_to_i32};
 use anyhow::{Context, Result};
 use fontdue::{Font, FontSettings};
-use image::{GenericImageView, Rgb, RgbImage};
+use image::{DynamicImage, Rgb, RgbImage};
 use std::path::{Path, PathBuf};
 use std::sync::OnceLock;
 
@@ -231,22 +235,17 @@ fn draw_text(
 }
 
 /// Labels can be aligned differently relative to the content they label.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum LabelAlignment {
     /// Place labels at the left/top edge
     Start,
     /// Center labels (default)
+    #[default]
     Center,
     /// Place labels at the right/bottom edge
     End,
 }
 
-impl Default for LabelAlignment {
-    fn default() -> Self {
-        Self::Center
-    }
-}
-
 /// Configuration for creating an image plot with labels.
 ///
 /// This struct defines the layout and content of an image grid plot,
@@ -270,13 +269,14 @@ impl Default for LabelAlignment {
 ///     top_padding: 40,
 ///     left_padding: 40,
 ///     font_size: Some(40.0),
+///     auto_orient: false,
 /// };
 /// ```
 #[derive(Debug)]
 pub struct PlotConfig {
     /// List of image file paths to include in the plot
     pub images: Vec<PathBuf>,
-    /// Output file path where the plot will be saved
+    /// Output file path where the plot will be saved; its extension selects the format
     pub output: PathBuf,
     /// Number of rows in the image grid
     pub rows: u32,
@@ -298,6 +298,8 @@ pub struct PlotConfig {
     pub left_padding: u32,
     /// Optional font size for labels
     pub font_size: Option<f32>,
+    /// Whether to rotate and flip images according to their EXIF orientation
+    pub auto_orient: bool,
 }
 
 impl Default for PlotConfig {
@@ -314,6 +316,7 @@ impl Default for PlotConfig {
             top_padding: DEFAULT_TOP_PADDING,
             left_padding: DEFAULT_LEFT_PADDING,
             font_size: None,
+            auto_orient: false,
         }
     }
 }
@@ -379,8 +382,27 @@ pub(crate) fn load_fonts() -> FontPair<'static> {
     }
 }
 
+/// Reads the dimensions of an image as drawn in the plot from its headers.
+fn plot_image_dimensions(path: &Path, auto_orient: bool) -> Result<(u32, u32)> {
+    let info = get_image_info(path)?;
+    Ok(if auto_orient {
+        info.oriented_dimensions()
+    } else {
+        (info.width, info.height)
+    })
+}
+
+/// Opens an image for drawing in the plot.
+fn open_plot_image(path: &Path, auto_orient: bool) -> Result<DynamicImage> {
+    if auto_orient {
+        open_image_oriented(path)
+    } else {
+        open_image(path)
+    }
+}
+
 /// Finds the maximum dimensions of a set of images.
-fn find_max_dimensions(images: &[PathBuf]) -> Result<(u32, u32)> {
+fn find_max_dimensions(images: &[PathBuf], auto_orient: bool) -> Result<(u32, u32)> {
     let mut max_width = 0;
     let mut max_height = 0;
 
@@ -388,9 +410,8 @@ fn find_max_dimensions(images: &[PathBuf]) -> Result<(u32, u32)> {
         if !path.exists() {
             continue;
         }
-        let img = image::open(path)
+        let dims = plot_image_dimensions(path, auto_orient)
             .with_context(|| format!("Failed to open image at {}", path.display()))?;
-        let dims = img.dimensions();
         max_width = max_width.max(dims.0);
         max_height = max_height.max(dims.1);
     }
@@ -584,8 +605,7 @@ fn calculate_layout(config: &PlotConfig, max_width: u32, max_height: u32, cols:
             });
         }
 
-        let img = image::open(img_path).unwrap().to_rgb8();
-        let (img_width, img_height) = img.dimensions();
+        let (img_width, img_height) = plot_image_dimensions(img_path, config.auto_orient).unwrap();
         let x_offset = (max_width - img_width) / 2;
         let y_offset = (max_height - img_height) / 2;
 
@@ -640,7 +660,7 @@ fn calculate_layout(config: &PlotConfig, max_width: u32, max_height: u32, cols:
 /// - The output path has an invalid extension
 pub fn create_plot(config: &PlotConfig) -> Result<()> {
     let cols = validate_plot_config(config)?;
-    let (max_width, max_height) = find_max_dimensions(&config.images)?;
+    let (max_width, max_height) = find_max_dimensions(&config.images, config.auto_orient)?;
 
     let layout = calculate_layout(config, max_width, max_height, cols);
 
@@ -672,7 +692,7 @@ pub fn create_plot(config: &PlotConfig) -> Result<()> {
     for element in layout.elements {
         match element {
             LayoutElement::Image { rect, path } => {
-                let img = image::open(Path::new(&path))?.to_rgb8();
+                let img = open_plot_image(Path::new(&path), config.auto_orient)?.to_rgb8();
                 for (x, y, pixel) in img.enumerate_pixels() {
                     let canvas_x = i32_to_u32(rect.x + u32_to_i32(x));
                     let canvas_y = i32_to_u32(rect.y + u32_to_i32(y));
@@ -696,9 +716,16 @@ pub fn create_plot(config: &PlotConfig) -> Result<()> {
         }
     }
 
-    canvas
-        .save(&config.output)
-        .with_context(|| format!("Failed to save output image: {}", config.output.display()))?;
+    // Encode like `convert_image`, so every output format it supports works here too
+    let output_format = detect_output_format(&config.output)?;
+    encode_image(
+        DynamicImage::ImageRgb8(canvas),
+        ImageMetadata::default(),
+        output_format,
+        &default_options_for(output_format),
+    )
+    .and_then(|encoded| std::fs::write(&config.output, encoded).map_err(Into::into))
+    .with_context(|| format!("Failed to save output image: {}", config.output.display()))?;
 
     Ok(())
 }